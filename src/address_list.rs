@@ -0,0 +1,95 @@
+use core::mem;
+use mwgc::Heap;
+
+use crate::error::ErrorCode;
+
+/// A sorted set of heap addresses the runtime needs to remember something
+/// about (like which objects are frozen), kept in the heap itself so it
+/// isn't limited to a fixed size. When it fills up, it moves to a bigger
+/// allocation, and the old one is left for the collector. The collector
+/// has to `mark` it, or it'll be freed out from under us.
+pub struct AddressList<'heap> {
+    items: &'heap mut [usize],
+    count: usize,
+}
+
+impl<'heap> AddressList<'heap> {
+    pub fn new() -> AddressList<'heap> {
+        AddressList { items: &mut [], count: 0 }
+    }
+
+    pub fn as_slice(&self) -> &[usize] {
+        &self.items[0 .. self.count]
+    }
+
+    pub fn contains(&self, addr: usize) -> bool {
+        self.as_slice().binary_search(&addr).is_ok()
+    }
+
+    /// Is `addr` inside the list's own storage?
+    pub fn is_inside(&self, addr: usize) -> bool {
+        let start = self.items.as_ptr() as usize;
+        addr >= start && addr < start + mem::size_of_val(self.items)
+    }
+
+    /// Make sure there's room to insert one more address, so it can be
+    /// done before an allocation that would be lost if the insert failed.
+    pub fn reserve(&mut self, heap: &mut Heap<'heap>) -> Result<(), ErrorCode> {
+        if self.count < self.items.len() { return Ok(()) }
+        let bigger = heap.allocate_array::<usize>(self.items.len() * 2 + 4).ok_or(ErrorCode::OutOfMemory)?;
+        bigger[0 .. self.count].copy_from_slice(self.as_slice());
+        self.items = bigger;
+        Ok(())
+    }
+
+    /// Add an address, if it isn't already there. This can only fail if
+    /// there was no room, and nothing was `reserve`d.
+    pub fn insert(&mut self, heap: &mut Heap<'heap>, addr: usize) -> Result<(), ErrorCode> {
+        if let Err(index) = self.as_slice().binary_search(&addr) {
+            self.reserve(heap)?;
+            self.items.copy_within(index .. self.count, index + 1);
+            self.items[index] = addr;
+            self.count += 1;
+        }
+        Ok(())
+    }
+
+    /// Drop every address that `keep` returns false for.
+    pub fn retain(&mut self, mut keep: impl FnMut(usize) -> bool) {
+        let mut kept = 0;
+        for i in 0 .. self.count {
+            let addr = self.items[i];
+            if keep(addr) {
+                self.items[kept] = addr;
+                kept += 1;
+            }
+        }
+        self.count = kept;
+    }
+
+    /// Mark the list's own allocation during a collection.
+    pub fn mark(&self, heap: &mut Heap<'heap>) {
+        if !self.items.is_empty() { heap.mark(self.items.as_ptr()); }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use mwgc::Heap;
+    use super::AddressList;
+
+    #[test]
+    fn grows_and_stays_sorted() {
+        let mut data = [ 0u8; 1024 ];
+        let mut heap = Heap::from_bytes(&mut data);
+        let mut list = AddressList::new();
+        for &addr in [ 50, 10, 40, 30, 20, 10 ].iter() { list.insert(&mut heap, addr).unwrap(); }
+        assert_eq!(list.as_slice(), &[ 10, 20, 30, 40, 50 ]);
+        assert!(list.contains(30));
+        assert!(!list.contains(35));
+
+        list.retain(|addr| addr != 20 && addr != 50);
+        assert_eq!(list.as_slice(), &[ 10, 30, 40 ]);
+    }
+}
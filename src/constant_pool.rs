@@ -1,11 +1,14 @@
 use core::mem;
 
+use crate::decode_int::decode_uint;
+use crate::disassembler::decode_next;
 use crate::error::{ErrorCode};
 
 
 /// Decoded code block metadata from the constant pool. This is allocated on
 /// our (runtime) stack, so it needs to be relatively compact, but it won't
 /// be on the heap like the stack frame.
+#[derive(Clone, Copy)]
 pub struct Code<'rom> {
     pub local_count: u8,
     pub max_stack: u8,
@@ -19,15 +22,101 @@ impl<'rom> Code<'rom> {
 }
 
 
+/// The fields of an image's header (see `docs/bytecode.md`), which comes
+/// before its constant pool.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ImageHeader {
+    // bytes per word on the target the image was built for, or 0 if it works on either
+    pub word_size: u8,
+    pub global_count: usize,
+    // offset of the "main" code block in the constant pool
+    pub main: u32,
+}
+
+const IMAGE_MAGIC: [u8; 4] = [ 0xf0, 0x9f, 0x97, 0xbf ];
+const IMAGE_VERSION: u8 = 1;
+// the original format, with no word size, and no padding after the header
+const IMAGE_VERSION_0: u8 = 0;
+// the constant pool starts on a boundary that suits either word size
+const IMAGE_ALIGNMENT: usize = 8;
+
+
 /// Wrapper for a `&'rom [u8]` that provides functions to safely access
 /// small bits of its internals.
 pub struct ConstantPool<'rom> {
     pub data: &'rom [u8],
+    // bytes per word on the target the image was built for, or 0 if it
+    // wasn't recorded
+    word_size: u8,
+    // offset of the symbol section, if there is one
+    symbols: Option<u32>,
 }
 
 impl<'rom> ConstantPool<'rom> {
     pub fn new(data: &'rom [u8]) -> ConstantPool<'rom> {
-        ConstantPool { data }
+        ConstantPool { data, word_size: 0, symbols: None }
+    }
+
+    /// Split an image file into its header and the constant pool after it,
+    /// which remembers the word size from the header. A version 1 header is
+    /// padded to a multiple of 8 bytes, so the pool is word-aligned if the
+    /// image is. A version 0 header has no word size or padding. A bad magic
+    /// number, version, or word size, or a header that's cut off, is
+    /// `InvalidImage`.
+    pub fn from_image(image: &'rom [u8]) -> Result<(ImageHeader, ConstantPool<'rom>), ErrorCode> {
+        if image.len() < 6 || image[0 .. 4] != IMAGE_MAGIC { return Err(ErrorCode::InvalidImage) }
+        if image[4] == IMAGE_VERSION_0 {
+            let main = decode_uint(image, 6).map_err(|_| ErrorCode::InvalidImage)?;
+            let header = ImageHeader { word_size: 0, global_count: image[5] as usize, main: main.value as u32 };
+            return Ok((header, ConstantPool::new(&image[main.new_index ..])));
+        }
+        if image.len() < 7 || image[4] != IMAGE_VERSION { return Err(ErrorCode::InvalidImage) }
+        let word_size = image[5];
+        if word_size != 0 && word_size != 4 && word_size != 8 { return Err(ErrorCode::InvalidImage) }
+        let main = decode_uint(image, 7).map_err(|_| ErrorCode::InvalidImage)?;
+        let header = ImageHeader { word_size, global_count: image[6] as usize, main: main.value as u32 };
+        let start = main.new_index.next_multiple_of(IMAGE_ALIGNMENT);
+        if start > image.len() { return Err(ErrorCode::InvalidImage) }
+        Ok((header, ConstantPool { data: &image[start ..], word_size, symbols: None }))
+    }
+
+    /// Bytes per word on the target this image was built for, if known.
+    /// Constants sized for one word size are mis-read on another.
+    pub fn target_word_size(&self) -> Option<usize> {
+        if self.word_size == 0 { None } else { Some(self.word_size as usize) }
+    }
+
+    /// Name code blocks with the symbol section at `offset`: a uint count
+    /// of symbols, then for each, a uint length, that many bytes of name,
+    /// and a uint code offset.
+    pub fn set_symbol_table(&mut self, offset: u32) {
+        self.symbols = Some(offset);
+    }
+
+    /// The code offset for a name in the symbol section, if there's a
+    /// symbol section, and the name is in it.
+    pub fn find_symbol(&self, name: &str) -> Option<u32> {
+        let data = self.data.get(((self.symbols? as usize) << 2) ..)?;
+        let count = decode_uint(data, 0).ok()?;
+        let mut index = count.new_index;
+        for _ in 0 .. count.value {
+            let len = decode_uint(data, index).ok()?;
+            let end = len.new_index.checked_add(len.value as usize)?;
+            let symbol = data.get(len.new_index .. end)?;
+            let offset = decode_uint(data, end).ok()?;
+            if symbol == name.as_bytes() { return Some(offset.value as u32) }
+            index = offset.new_index;
+        }
+        None
+    }
+
+    /// The byte string at `offset`: a uint length, then that many bytes,
+    /// which must all be inside the constant pool.
+    pub fn get_bytes(&self, offset: u32) -> Option<&'rom [u8]> {
+        let data = self.data.get(((offset as usize) << 2) ..)?;
+        let len = decode_uint(data, 0).ok()?;
+        let end = len.new_index.checked_add(len.value as usize)?;
+        data.get(len.new_index .. end)
     }
 
     // offsets are always shifted 2 bits right
@@ -53,14 +142,29 @@ impl<'rom> ConstantPool<'rom> {
         Ok(Code { local_count, max_stack, bytecode })
     }
 
+    /// Check every code block in `entry_offsets` before running any of
+    /// them: the header has to be sane, the bytecode has to fit inside the
+    /// pool, and every instruction has to decode. This doesn't look at what
+    /// the instructions do; `verify_code` does that.
+    pub fn validate(&self, entry_offsets: &[u32]) -> Result<(), ErrorCode> {
+        for &offset in entry_offsets {
+            let code = self.get_code(self.addr_from_offset(offset))?;
+            let mut pc = 0;
+            while (pc as usize) < code.bytecode.len() {
+                pc = decode_next(code.bytecode, pc)?.1;
+            }
+        }
+        Ok(())
+    }
+
     /// Turn a pointer into a reference if it's safely within the constant pool.
-    pub fn safe_ref<T>(&self, ptr: *const T) -> Option<&'rom T> {
+    pub(crate) fn safe_ref<T>(&self, ptr: *const T) -> Option<&'rom T> {
         if self.is_in_constant_pool(ptr) { Some(unsafe { &*ptr }) } else { None }
     }
 
     /// Turn a pointer and length into a slice if it's safely within the constant pool.
-    pub fn safe_slice<T>(&self, ptr: *const T, len: usize) -> Option<&'rom [T]> {
-        if self.is_in_constant_pool_range(ptr as usize, len * mem::size_of::<T>()) {
+    pub(crate) fn safe_slice<T>(&self, ptr: *const T, len: usize) -> Option<&'rom [T]> {
+        if self.is_in_constant_pool_range(ptr as usize, len.checked_mul(mem::size_of::<T>())?) {
             Some(unsafe { core::slice::from_raw_parts(ptr, len) })
         } else {
             None
@@ -73,6 +177,75 @@ impl<'rom> ConstantPool<'rom> {
 
     fn is_in_constant_pool_range(&self, addr: usize, len: usize) -> bool {
         let pool = self.data.as_ptr() as usize;
-        addr >= pool && addr + len <= pool + self.data.len()
+        addr >= pool && addr.checked_add(len).is_some_and(|end| end <= pool + self.data.len())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::error::ErrorCode;
+    use crate::opcode::Opcode;
+    use super::ConstantPool;
+
+    #[test]
+    fn validate() {
+        // [load local 0; return 1], [nop]
+        let pool = ConstantPool::new(&[
+            1, 2, 4, 0, Opcode::LoadLocalN as u8, 0, Opcode::ReturnN as u8, 2,
+            0, 0, 1, 0, Opcode::Nop as u8, 0, 0, 0,
+        ]);
+        assert_eq!(pool.validate(&[]), Ok(()));
+        assert_eq!(pool.validate(&[ 0, 2 ]), Ok(()));
+        // past the end of the pool:
+        assert_eq!(pool.validate(&[ 0, 4 ]), Err(ErrorCode::InvalidAddress));
+    }
+
+    #[test]
+    fn validate_malformed() {
+        // too many locals
+        let pool = ConstantPool::new(&[ 64, 2, 0, 0 ]);
+        assert_eq!(pool.validate(&[ 0 ]), Err(ErrorCode::InvalidCodeObject));
+        // too much stack
+        let pool = ConstantPool::new(&[ 0, 64, 0, 0 ]);
+        assert_eq!(pool.validate(&[ 0 ]), Err(ErrorCode::InvalidCodeObject));
+        // bytecode runs off the end of the pool
+        let pool = ConstantPool::new(&[ 0, 0, 8, 0, Opcode::Nop as u8, Opcode::Nop as u8, Opcode::Nop as u8, Opcode::Nop as u8 ]);
+        assert_eq!(pool.validate(&[ 0 ]), Err(ErrorCode::InvalidAddress));
+        // immediate cut off by the end of the block
+        let pool = ConstantPool::new(&[ 0, 0, 2, 0, Opcode::Nop as u8, Opcode::Immediate as u8, 0, 0 ]);
+        assert_eq!(pool.validate(&[ 0 ]), Err(ErrorCode::TruncatedCode));
+    }
+
+    #[test]
+    fn bytes() {
+        let pool = ConstantPool::new(&[
+            5, b'h', b'e', b'l', b'l', b'o', 0, 0,
+            0, 0, 0, 0, 0x80, 1, b'x', 0,
+        ]);
+        assert_eq!(pool.get_bytes(0), Some(&b"hello"[..]));
+        assert_eq!(pool.get_bytes(2), Some(&[][..]));
+        // 128 bytes don't fit.
+        assert_eq!(pool.get_bytes(3), None);
+        assert_eq!(pool.get_bytes(4), None);
+    }
+
+    #[test]
+    fn symbols() {
+        let mut pool = ConstantPool::new(&[
+            0, 0, 1, 0, Opcode::Nop as u8, 0, 0, 0,
+            // "nop" -> 0, "x" -> 300
+            2, 3, b'n', b'o', b'p', 0, 1, b'x', 0xac, 2,
+        ]);
+        assert_eq!(pool.find_symbol("nop"), None);
+        pool.set_symbol_table(2);
+        assert_eq!(pool.find_symbol("nop"), Some(0));
+        assert_eq!(pool.find_symbol("x"), Some(300));
+        assert_eq!(pool.find_symbol("no"), None);
+        assert_eq!(pool.find_symbol(""), None);
+
+        // running off the end of the pool finds nothing.
+        pool.set_symbol_table(3);
+        assert_eq!(pool.find_symbol("x"), None);
     }
 }
@@ -1,4 +1,5 @@
 use core::mem;
+use crate::error::ErrorCode;
 
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub struct DecodedInt {
@@ -16,20 +17,22 @@ const MAX_SHIFT: usize = mem::size_of::<usize>() * 8;
 
 /// uint is encoded as "varint":
 /// 7 bits at a time, LSB first, high bit is set on all but the last byte.
-pub fn decode_uint(bytes: &[u8], mut index: usize) -> Option<DecodedInt> {
-    if index >= bytes.len() { return None }
+/// Running out of bytes is `TruncatedCode`; more bytes than a word can hold
+/// is `MalformedVarint`.
+pub fn decode_uint(bytes: &[u8], mut index: usize) -> Result<DecodedInt, ErrorCode> {
+    if index >= bytes.len() { return Err(ErrorCode::TruncatedCode) }
     let mut value: isize = 0;
     let mut shift: usize = 0;
 
     while bytes[index] & 0x80 != 0 {
         value = value | (((bytes[index] & 0x7f) as isize) << shift);
         index += 1;
-        if index >= bytes.len() { return None }
+        if index >= bytes.len() { return Err(ErrorCode::TruncatedCode) }
         shift += 7;
-        if shift >= MAX_SHIFT { return None }
+        if shift >= MAX_SHIFT { return Err(ErrorCode::MalformedVarint) }
     }
     value = value | ((bytes[index] as isize) << shift);
-    Some(DecodedInt::new(value, index + 1))
+    Ok(DecodedInt::new(value, index + 1))
 }
 
 /// sint is encoded as "zigzag":
@@ -37,48 +40,109 @@ pub fn decode_uint(bytes: &[u8], mut index: usize) -> Option<DecodedInt> {
 /// when the sign bit is set, the rest of the number is inverted, so -1 is
 /// encoded as 0x01, -2 as 0x03, and so on. the result is then encoded the
 /// same as a varint.
-pub fn decode_sint(bytes: &[u8], index: usize) -> Option<DecodedInt> {
-    decode_uint(bytes, index).map(|d| DecodedInt::new((d.value >> 1) ^ -(d.value & 1), d.new_index))
+pub fn decode_sint(bytes: &[u8], index: usize) -> Result<DecodedInt, ErrorCode> {
+    decode_uint(bytes, index).map(|d| {
+        DecodedInt::new((((d.value as usize) >> 1) as isize) ^ -(d.value & 1), d.new_index)
+    })
+}
+
+/// Encode `value` as a varint into the start of `out`, returning the number
+/// of bytes written, or `None` if `out` is too small to hold them.
+pub fn encode_uint(mut value: usize, out: &mut [u8]) -> Option<usize> {
+    let mut index = 0;
+    while value >= 0x80 {
+        *out.get_mut(index)? = ((value & 0x7f) as u8) | 0x80;
+        index += 1;
+        value >>= 7;
+    }
+    *out.get_mut(index)? = value as u8;
+    Some(index + 1)
+}
+
+/// Encode `value` as a zigzag sint into the start of `out`, returning the
+/// number of bytes written, or `None` if `out` is too small to hold them.
+pub fn encode_sint(value: isize, out: &mut [u8]) -> Option<usize> {
+    encode_uint((value.wrapping_shl(1) ^ (value >> (MAX_SHIFT - 1))) as usize, out)
 }
 
 
+
 #[cfg(test)]
 mod tests {
-    use super::{decode_sint, decode_uint, DecodedInt};
+    use crate::error::ErrorCode;
+    use super::{decode_sint, decode_uint, encode_sint, encode_uint, DecodedInt};
 
     #[test]
     fn uint() {
-        assert_eq!(decode_uint(&[ 0 ], 0), Some(DecodedInt::new(0, 1)));
-        assert_eq!(decode_uint(&[ 1 ], 0), Some(DecodedInt::new(1, 1)));
-        assert_eq!(decode_uint(&[ 2 ], 0), Some(DecodedInt::new(2, 1)));
-        assert_eq!(decode_uint(&[ 0x7e ], 0), Some(DecodedInt::new(126, 1)));
-        assert_eq!(decode_uint(&[ 0x7f ], 0), Some(DecodedInt::new(127, 1)));
-        assert_eq!(decode_uint(&[ 0x80, 0x01 ], 0), Some(DecodedInt::new(128, 2)));
-        assert_eq!(decode_uint(&[ 0x82, 0x40 ], 0), Some(DecodedInt::new(8194, 2)));
-        assert_eq!(decode_uint(&[ 0x80, 0x01, 0x80 ], 1), Some(DecodedInt::new(1, 2)));
-        assert_eq!(decode_uint(&[ 0x80, 0x80, 0x80, 0x80, 0x02 ], 0), Some(DecodedInt::new(0x20000000, 5)));
-
-        assert_eq!(decode_uint(&[ 0x80 ], 0), None);
-        assert_eq!(decode_uint(&[ 0 ], 1), None);
-        assert_eq!(decode_uint(&[ 0 ], 3), None);
-        assert_eq!(decode_uint(&[ 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 1 ], 0), None);
+        assert_eq!(decode_uint(&[ 0 ], 0), Ok(DecodedInt::new(0, 1)));
+        assert_eq!(decode_uint(&[ 1 ], 0), Ok(DecodedInt::new(1, 1)));
+        assert_eq!(decode_uint(&[ 2 ], 0), Ok(DecodedInt::new(2, 1)));
+        assert_eq!(decode_uint(&[ 0x7e ], 0), Ok(DecodedInt::new(126, 1)));
+        assert_eq!(decode_uint(&[ 0x7f ], 0), Ok(DecodedInt::new(127, 1)));
+        assert_eq!(decode_uint(&[ 0x80, 0x01 ], 0), Ok(DecodedInt::new(128, 2)));
+        assert_eq!(decode_uint(&[ 0x82, 0x40 ], 0), Ok(DecodedInt::new(8194, 2)));
+        assert_eq!(decode_uint(&[ 0x80, 0x01, 0x80 ], 1), Ok(DecodedInt::new(1, 2)));
+        assert_eq!(decode_uint(&[ 0x80, 0x80, 0x80, 0x80, 0x02 ], 0), Ok(DecodedInt::new(0x20000000, 5)));
+
+        assert_eq!(decode_uint(&[ 0x80 ], 0), Err(ErrorCode::TruncatedCode));
+        assert_eq!(decode_uint(&[ 0 ], 1), Err(ErrorCode::TruncatedCode));
+        assert_eq!(decode_uint(&[ 0 ], 3), Err(ErrorCode::TruncatedCode));
+        assert_eq!(decode_uint(&[ 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 1 ], 0), Err(ErrorCode::MalformedVarint));
     }
 
     #[test]
     fn sint() {
-        assert_eq!(decode_sint(&[ 0 ], 0), Some(DecodedInt::new(0, 1)));
-        assert_eq!(decode_sint(&[ 1 ], 0), Some(DecodedInt::new(-1, 1)));
-        assert_eq!(decode_sint(&[ 2 ], 0), Some(DecodedInt::new(1, 1)));
-        assert_eq!(decode_sint(&[ 0x7e ], 0), Some(DecodedInt::new(63, 1)));
-        assert_eq!(decode_sint(&[ 0x7f ], 0), Some(DecodedInt::new(-64, 1)));
-        assert_eq!(decode_sint(&[ 0x80, 0x01 ], 0), Some(DecodedInt::new(64, 2)));
-        assert_eq!(decode_sint(&[ 0x82, 0x40 ], 0), Some(DecodedInt::new(4097, 2)));
-        assert_eq!(decode_sint(&[ 0x80, 0x01, 0x80 ], 1), Some(DecodedInt::new(-1, 2)));
-        assert_eq!(decode_sint(&[ 0x80, 0x80, 0x80, 0x80, 0x02 ], 0), Some(DecodedInt::new(0x10000000, 5)));
-
-        assert_eq!(decode_sint(&[ 0x80 ], 0), None);
-        assert_eq!(decode_sint(&[ 0 ], 1), None);
-        assert_eq!(decode_sint(&[ 0 ], 3), None);
-        assert_eq!(decode_sint(&[ 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 1 ], 0), None);
+        assert_eq!(decode_sint(&[ 0 ], 0), Ok(DecodedInt::new(0, 1)));
+        assert_eq!(decode_sint(&[ 1 ], 0), Ok(DecodedInt::new(-1, 1)));
+        assert_eq!(decode_sint(&[ 2 ], 0), Ok(DecodedInt::new(1, 1)));
+        assert_eq!(decode_sint(&[ 0x7e ], 0), Ok(DecodedInt::new(63, 1)));
+        assert_eq!(decode_sint(&[ 0x7f ], 0), Ok(DecodedInt::new(-64, 1)));
+        assert_eq!(decode_sint(&[ 0x80, 0x01 ], 0), Ok(DecodedInt::new(64, 2)));
+        assert_eq!(decode_sint(&[ 0x82, 0x40 ], 0), Ok(DecodedInt::new(4097, 2)));
+        assert_eq!(decode_sint(&[ 0x80, 0x01, 0x80 ], 1), Ok(DecodedInt::new(-1, 2)));
+        assert_eq!(decode_sint(&[ 0x80, 0x80, 0x80, 0x80, 0x02 ], 0), Ok(DecodedInt::new(0x10000000, 5)));
+        assert_eq!(decode_sint(&[ 0xfe, 0xff, 0xff, 0xff, 0x0f ], 0), Ok(DecodedInt::new(0x7fffffff, 5)));
+        #[cfg(target_pointer_width = "64")]
+        assert_eq!(
+            decode_sint(&[ 0xfe, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01 ], 0),
+            Ok(DecodedInt::new(isize::max_value(), 10))
+        );
+
+        assert_eq!(decode_sint(&[ 0x80 ], 0), Err(ErrorCode::TruncatedCode));
+        assert_eq!(decode_sint(&[ 0 ], 1), Err(ErrorCode::TruncatedCode));
+        assert_eq!(decode_sint(&[ 0 ], 3), Err(ErrorCode::TruncatedCode));
+        assert_eq!(decode_sint(&[ 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 1 ], 0), Err(ErrorCode::MalformedVarint));
+    }
+
+    #[test]
+    fn encode() {
+        let mut buffer = [ 0u8; 10 ];
+        assert_eq!(encode_uint(0, &mut buffer), Some(1));
+        assert_eq!(buffer[0], 0);
+        assert_eq!(encode_uint(128, &mut buffer), Some(2));
+        assert_eq!(&buffer[0 .. 2], &[ 0x80, 0x01 ]);
+        assert_eq!(encode_sint(-1, &mut buffer), Some(1));
+        assert_eq!(buffer[0], 0x01);
+        assert_eq!(encode_sint(4097, &mut buffer), Some(2));
+        assert_eq!(&buffer[0 .. 2], &[ 0x82, 0x40 ]);
+
+        assert_eq!(encode_uint(128, &mut buffer[0 .. 1]), None);
+        assert_eq!(encode_sint(0, &mut []), None);
+    }
+
+    #[test]
+    fn sint_round_trip() {
+        let mut buffer = [ 0u8; 10 ];
+        let values = [
+            0, 1, -1, 63, -64, 64, -65, 4097, -4097, 0x7fffffff, -0x80000000, isize::max_value(), isize::min_value(),
+        ];
+        for &n in values.iter() {
+            let len = encode_sint(n, &mut buffer).unwrap();
+            assert_eq!(decode_sint(&buffer, 0), Ok(DecodedInt::new(n, len)));
+        }
+        for n in -1000 .. 1000 {
+            let len = encode_sint(n, &mut buffer).unwrap();
+            assert_eq!(decode_sint(&buffer[0 .. len], 0), Ok(DecodedInt::new(n, len)));
+        }
     }
 }
@@ -1,8 +1,10 @@
 use core::fmt;
+use crate::constant_pool::Code;
 use crate::decode_int::decode_sint;
 use crate::error::ErrorCode;
-use crate::opcode::{Binary, FIRST_N1_OPCODE, FIRST_N2_OPCODE, LAST_N_OPCODE, Opcode, Unary};
+use crate::opcode::{Binary, Opcode, Unary};
 
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Instruction {
     pub offset: usize,
     pub opcode: Opcode,
@@ -10,14 +12,76 @@ pub struct Instruction {
     pub n2: isize,
 }
 
+impl Instruction {
+    /// A count in `stack_effect` that's only known at runtime.
+    pub const DYNAMIC: usize = usize::MAX;
+
+    /// How many items this instruction pops off the stack, and how many it
+    /// pushes back on. A count that depends on what's on the stack (like
+    /// the fill count of `New`) or what a call returns is `DYNAMIC`.
+    /// Unknown opcodes are `DYNAMIC` both ways.
+    pub fn stack_effect(&self) -> (usize, usize) {
+        let n1 = if self.n1 < 0 { 0 } else { self.n1 as usize };
+        let n2 = if self.n2 < 0 { 0 } else { self.n2 as usize };
+        match self.opcode {
+            Opcode::Break | Opcode::Nop | Opcode::Jump | Opcode::JumpRel | Opcode::CopyLocal |
+                Opcode::IncLocalN | Opcode::DecLocalN | Opcode::IncGlobalN => (0, 0),
+            Opcode::Immediate | Opcode::Constant | Opcode::LoadLocalN | Opcode::LoadGlobalN | Opcode::StackAddrN |
+                Opcode::StackRoom | Opcode::SnapshotGlobals | Opcode::DecTestLocalN | Opcode::PickN | Opcode::CallDepth |
+                Opcode::CaptureStack => (0, 1),
+            Opcode::FrameInfo | Opcode::ConstantBytes => (0, 2),
+            Opcode::Drop | Opcode::StoreLocalN | Opcode::StoreGlobalN | Opcode::If | Opcode::JumpIfPointer |
+                Opcode::RestoreGlobals | Opcode::JumpIfZero | Opcode::JumpIfNonZero | Opcode::AddGlobalN |
+                Opcode::FreeObject | Opcode::JumpDynamic | Opcode::Freeze | Opcode::Throw => (1, 0),
+            Opcode::Size | Opcode::LoadSlotN | Opcode::Unary | Opcode::LoadField | Opcode::TypeOf | Opcode::NewWeak => (1, 1),
+            Opcode::Dup => (1, 2),
+            Opcode::StoreSlotN => (2, 0),
+            Opcode::LoadSlot | Opcode::Binary | Opcode::SlotDiff | Opcode::LoadIndexed => (2, 1),
+            Opcode::Swap => (2, 2),
+            Opcode::Over => (2, 3),
+            Opcode::StoreSlot | Opcode::StoreIndexed => (3, 0),
+            Opcode::ExchangeSlot | Opcode::Select => (3, 1),
+            Opcode::Rot => (3, 3),
+            Opcode::ReturnN => (n1, 0),
+            Opcode::NewNN => (n2, 1),
+            Opcode::CallNN => (1 + n1, n2),
+            Opcode::CallN => (1 + n1, Instruction::DYNAMIC),
+            Opcode::Return => (Instruction::DYNAMIC, 0),
+            Opcode::New | Opcode::NewTaggedN | Opcode::NewAlignedN | Opcode::NewFromStack => (Instruction::DYNAMIC, 1),
+            Opcode::Call | Opcode::CallNativeN | Opcode::Unknown => (Instruction::DYNAMIC, Instruction::DYNAMIC),
+        }
+    }
+
+    /// Where this instruction jumps to, if it's a jump with a fixed target.
+    pub fn jump_target(&self) -> Option<isize> {
+        match self.opcode {
+            Opcode::Jump | Opcode::JumpIfPointer | Opcode::JumpIfZero | Opcode::JumpIfNonZero => Some(self.n1),
+            Opcode::JumpRel => Some(self.offset as isize + self.n1),
+            _ => None,
+        }
+    }
+}
+
 impl fmt::Display for Instruction {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{:04x}: ", self.offset)?;
-        match self.opcode {
+        write!(f, "{:04x}: {}", self.offset, Mnemonic(self))
+    }
+}
+
+// just the instruction, without its offset
+struct Mnemonic<'a>(&'a Instruction);
+
+impl<'a> fmt::Display for Mnemonic<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let i = self.0;
+        match i.opcode {
             Opcode::Break => write!(f, "BREAK"),
             Opcode::Nop => write!(f, "NOP"),
             Opcode::Dup => write!(f, "DUP"),
             Opcode::Drop => write!(f, "DROP"),
+            Opcode::Swap => write!(f, "SWAP"),
+            Opcode::Over => write!(f, "OVER"),
+            Opcode::Rot => write!(f, "ROT"),
             Opcode::Call => write!(f, "CALL"),
             Opcode::Return => write!(f, "RET"),
             Opcode::New => write!(f, "NEW"),
@@ -25,21 +89,22 @@ impl fmt::Display for Instruction {
             Opcode::LoadSlot => write!(f, "LDS"),
             Opcode::StoreSlot => write!(f, "STS"),
             Opcode::If => write!(f, "IF"),
-            Opcode::Immediate => write!(f, "LD #{}", self.n1),
-            Opcode::Constant => write!(f, "LDC #{}", self.n1 << 2),
-            Opcode::LoadSlotN => write!(f, "LDS #{}", self.n1),
-            Opcode::StoreSlotN => write!(f, "STS #{}", self.n1),
-            Opcode::LoadLocalN => write!(f, "LD @{}", self.n1),
-            Opcode::StoreLocalN => write!(f, "ST @{}", self.n1),
-            Opcode::LoadGlobalN => write!(f, "LD ${}", self.n1),
-            Opcode::StoreGlobalN => write!(f, "ST ${}", self.n1),
-            Opcode::Unary => match Unary::from_usize(self.n1 as usize) {
+            Opcode::Immediate => write!(f, "LD #{}", i.n1),
+            Opcode::Constant => write!(f, "LDC #{}", i.n1 << 2),
+            Opcode::LoadSlotN => write!(f, "LDS #{}", i.n1),
+            Opcode::StoreSlotN => write!(f, "STS #{}", i.n1),
+            Opcode::LoadLocalN => write!(f, "LD @{}", i.n1),
+            Opcode::StoreLocalN => write!(f, "ST @{}", i.n1),
+            Opcode::LoadGlobalN => write!(f, "LD ${}", i.n1),
+            Opcode::StoreGlobalN => write!(f, "ST ${}", i.n1),
+            Opcode::Unary => match Unary::from_usize(i.n1 as usize) {
                 Unary::Not => write!(f, "NOT"),
                 Unary::Negative => write!(f, "NEG"),
                 Unary::BitNot => write!(f, "INV"),
+                Unary::Abs => write!(f, "ABS"),
                 _ => write!(f, "?unary?"),
             },
-            Opcode::Binary => match Binary::from_usize(self.n1 as usize) {
+            Opcode::Binary => match Binary::from_usize(i.n1 as usize) {
                 Binary::Add => write!(f, "ADD"),
                 Binary::Subtract => write!(f, "SUB"),
                 Binary::Multiply => write!(f, "MUL"),
@@ -54,13 +119,62 @@ impl fmt::Display for Instruction {
                 Binary::ShiftLeft => write!(f, "LSL"),
                 Binary::ShiftRight => write!(f, "LSR"),
                 Binary::SignShiftRight => write!(f, "ASR"),
+                Binary::LogicalAnd => write!(f, "LAND"),
+                Binary::LogicalOr => write!(f, "LOR"),
+                Binary::RefEquals => write!(f, "REQ"),
+                Binary::GreaterThan => write!(f, "GT"),
+                Binary::GreaterOrEqual => write!(f, "GE"),
+                Binary::UnsignedLessThan => write!(f, "ULT"),
+                Binary::UnsignedDivide => write!(f, "UDIV"),
+                Binary::UnsignedModulo => write!(f, "UMOD"),
+                Binary::Min => write!(f, "MIN"),
+                Binary::Max => write!(f, "MAX"),
+                Binary::AddChecked => write!(f, "ADDC"),
+                Binary::SubtractChecked => write!(f, "SUBC"),
+                Binary::MultiplyChecked => write!(f, "MULC"),
                 _ => write!(f, "?binary?"),
             },
-            Opcode::CallN => write!(f, "CALL #{}", self.n1),
-            Opcode::ReturnN => write!(f, "RET #{}", self.n1),
-            Opcode::Jump => write!(f, "JUMP {:04x}", self.n1),
-            Opcode::NewNN => write!(f, "NEW #{}, #{}", self.n1, self.n2),
-            _ => write!(f, "???({:x})", self.opcode as u8),
+            Opcode::CallN => write!(f, "CALL #{}", i.n1),
+            Opcode::ReturnN => write!(f, "RET #{}", i.n1),
+            Opcode::Jump => write!(f, "JUMP {:04x}", i.n1),
+            Opcode::StackRoom => write!(f, "ROOM #{}", i.n1),
+            Opcode::JumpIfPointer => write!(f, "JPTR {:04x}", i.n1),
+            Opcode::StackAddrN => write!(f, "SADDR #{}", i.n1),
+            Opcode::NewNN => write!(f, "NEW #{}, #{}", i.n1, i.n2),
+            Opcode::LoadField => write!(f, "FIELD #{}, #{}", i.n1, i.n2),
+            Opcode::SnapshotGlobals => write!(f, "LD ${}, #{}", i.n1, i.n2),
+            Opcode::RestoreGlobals => write!(f, "ST ${}, #{}", i.n1, i.n2),
+            Opcode::CallNN => write!(f, "CALL #{}, #{}", i.n1, i.n2),
+            Opcode::CopyLocal => write!(f, "COPY @{}, @{}", i.n2, i.n1),
+            Opcode::FrameInfo => write!(f, "FRAME"),
+            Opcode::ExchangeSlot => write!(f, "XCHG"),
+            Opcode::TypeOf => write!(f, "TYPE"),
+            Opcode::FreeObject => write!(f, "FREE"),
+            Opcode::Select => write!(f, "SEL"),
+            Opcode::SlotDiff => write!(f, "SDIFF"),
+            Opcode::JumpDynamic => write!(f, "JUMP *"),
+            Opcode::CaptureStack => write!(f, "STACK"),
+            Opcode::Throw => write!(f, "THROW"),
+            Opcode::NewFromStack => write!(f, "NEW *"),
+            Opcode::NewWeak => write!(f, "WEAK"),
+            Opcode::CallDepth => write!(f, "DEPTH"),
+            Opcode::LoadIndexed => write!(f, "LDX"),
+            Opcode::StoreIndexed => write!(f, "STX"),
+            Opcode::Freeze => write!(f, "FREEZE"),
+            Opcode::NewTaggedN => write!(f, "NEW :{}", i.n1),
+            Opcode::IncGlobalN => write!(f, "INC ${}", i.n1),
+            Opcode::AddGlobalN => write!(f, "ADD ${}", i.n1),
+            Opcode::NewAlignedN => write!(f, "NEW %{}", i.n1),
+            Opcode::DecTestLocalN => write!(f, "DECZ @{}", i.n1),
+            Opcode::PickN => write!(f, "PICK #{}", i.n1),
+            Opcode::JumpRel => write!(f, "JUMP {:+}", i.n1),
+            Opcode::JumpIfZero => write!(f, "JZ {:04x}", i.n1),
+            Opcode::JumpIfNonZero => write!(f, "JNZ {:04x}", i.n1),
+            Opcode::IncLocalN => write!(f, "INC @{}", i.n1),
+            Opcode::DecLocalN => write!(f, "DEC @{}", i.n1),
+            Opcode::CallNativeN => write!(f, "SYS #{}", i.n1),
+            Opcode::ConstantBytes => write!(f, "LDB #{}", i.n1 << 2),
+            _ => write!(f, "???({:x})", i.opcode as u8),
         }
     }
 }
@@ -92,7 +206,173 @@ pub fn disassemble<'a>(bytes: &'a [u8]) -> Disassembler<'a> {
 
 pub fn disassemble_to_string<W: fmt::Write>(bytes: &[u8], f: &mut W) -> fmt::Result {
     for i in disassemble(bytes) {
-        write!(f, "{}\n", i)?;
+        writeln!(f, "{}", i)?;
+    }
+    Ok(())
+}
+
+/// Disassemble bytecode with the raw bytes of each instruction between its
+/// offset and its mnemonic, for debugging a code generator.
+pub fn disassemble_verbose<W: fmt::Write>(bytes: &[u8], f: &mut W) -> fmt::Result {
+    // line up the mnemonics past the bytes of the longest instruction
+    let widest = encodings(bytes).map(|(_, pc, next_pc)| (next_pc - pc) as usize).max().unwrap_or(0);
+    for (instruction, pc, next_pc) in encodings(bytes) {
+        write!(f, "{:04x}:", pc)?;
+        for b in &bytes[pc as usize .. next_pc as usize] { write!(f, " {:02x}", b)?; }
+        for _ in (next_pc - pc) as usize .. widest { write!(f, "   ")?; }
+        writeln!(f, "   {}", Mnemonic(&instruction))?;
+    }
+    Ok(())
+}
+
+// each instruction, with the range of bytes it was decoded from.
+fn encodings(bytes: &[u8]) -> impl Iterator<Item = (Instruction, u16, u16)> + '_ {
+    let mut pc = 0;
+    core::iter::from_fn(move || {
+        if pc as usize >= bytes.len() { return None }
+        let (instruction, next_pc) = decode_next(bytes, pc).ok()?;
+        let start = pc;
+        pc = next_pc;
+        Some((instruction, start, next_pc))
+    })
+}
+
+/// Disassemble bytecode with its jump targets named `L0`, `L1`, ... in
+/// the order they appear, each on a line of its own before the instruction
+/// it names, and jumps refer to them by name instead of by offset. A jump
+/// that doesn't land on an instruction keeps its raw offset.
+///
+/// The jump targets are collected into `targets` first, so it needs room
+/// for every distinct one, or this fails before writing anything.
+pub fn disassemble_with_labels<W: fmt::Write>(bytes: &[u8], targets: &mut [u16], f: &mut W) -> fmt::Result {
+    let count = collect_labels(bytes, targets).ok_or(fmt::Error)?;
+    let labels = &targets[0 .. count];
+    let label_for = |offset: isize| {
+        if offset < 0 || offset > u16::MAX as isize { return None }
+        labels.binary_search(&(offset as u16)).ok()
+    };
+    for i in disassemble(bytes) {
+        if let Some(label) = label_for(i.offset as isize) {
+            writeln!(f, "L{}:", label)?;
+        }
+        match i.jump_target().and_then(label_for) {
+            Some(label) => {
+                write!(f, "{:04x}: ", i.offset)?;
+                fmt::Write::write_fmt(&mut OperatorOnly(f, false), format_args!("{}", Mnemonic(&i)))?;
+                writeln!(f, " L{}", label)?;
+            },
+            None => writeln!(f, "{}", i)?,
+        }
+    }
+    Ok(())
+}
+
+// fill `targets` with every distinct jump target that lands on an
+// instruction, sorted, and return how many there are, or `None` if they
+// don't fit.
+fn collect_labels(bytes: &[u8], targets: &mut [u16]) -> Option<usize> {
+    let mut count = 0;
+    for i in disassemble(bytes) {
+        let target = match i.jump_target() {
+            Some(target) if target >= 0 && (target as usize) < bytes.len() => target as u16,
+            _ => continue,
+        };
+        if let Err(index) = targets[0 .. count].binary_search(&target) {
+            if count == targets.len() { return None }
+            targets.copy_within(index .. count, index + 1);
+            targets[index] = target;
+            count += 1;
+        }
+    }
+
+    // walk both in order, keeping only the targets that are an instruction's offset.
+    let (mut kept, mut next) = (0, 0);
+    for i in disassemble(bytes) {
+        while next < count && (targets[next] as usize) < i.offset { next += 1; }
+        if next < count && targets[next] as usize == i.offset {
+            targets[kept] = targets[next];
+            kept += 1;
+            next += 1;
+        }
+    }
+    Some(kept)
+}
+
+// writes a mnemonic up to the first space, leaving off its operands.
+struct OperatorOnly<'a, W: fmt::Write>(&'a mut W, bool);
+
+impl<'a, W: fmt::Write> fmt::Write for OperatorOnly<'a, W> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        if self.1 { return Ok(()) }
+        match s.find(' ') {
+            Some(n) => {
+                self.1 = true;
+                self.0.write_str(&s[0 .. n])
+            },
+            None => self.0.write_str(s),
+        }
+    }
+}
+
+/// Disassemble a whole code block, with a header line for its local count
+/// and stack size.
+pub fn disassemble_code_to_string<W: fmt::Write>(code: &Code, f: &mut W) -> fmt::Result {
+    writeln!(f, "; locals={} stack={}", code.local_count, code.max_stack)?;
+    disassemble_to_string(code.bytecode, f)
+}
+
+/// Is `pc` the start of an instruction, decoding from the top of the bytecode?
+pub fn is_instruction_start(bytes: &[u8], pc: u16) -> bool {
+    let mut i = 0;
+    while i < pc {
+        match decode_next(bytes, i) {
+            Ok((_, next)) => i = next,
+            Err(_) => return false,
+        }
+    }
+    i == pc
+}
+
+// every offset in some bytecode where an instruction starts, found by
+// decoding it once from the top, so checking a jump target doesn't have to
+// decode it all again. nothing past an instruction that can't be decoded
+// counts. it's a bitmap covering the whole 16-bit pc range.
+pub(crate) struct InstructionStarts {
+    bits: [u32; 2048],
+}
+
+impl InstructionStarts {
+    pub(crate) fn new(bytes: &[u8]) -> InstructionStarts {
+        let mut starts = InstructionStarts { bits: [ 0; 2048 ] };
+        let mut pc = 0;
+        while (pc as usize) < bytes.len() {
+            starts.bits[pc as usize / 32] |= 1 << (pc % 32);
+            match decode_next(bytes, pc) {
+                Ok((_, next)) => pc = next,
+                Err(_) => break,
+            }
+        }
+        starts
+    }
+
+    pub(crate) fn contains(&self, pc: isize) -> bool {
+        pc >= 0 && pc <= u16::MAX as isize && self.bits[pc as usize / 32] & (1 << (pc % 32)) != 0
+    }
+}
+
+/// Check that every jump with a fixed target lands on the start of an
+/// instruction inside the bytecode, so an assembler can catch bad labels.
+/// On failure, returns the offset of the first jump that doesn't (or of an
+/// instruction that couldn't be decoded, since nothing after it can be).
+pub fn verify_jumps(bytes: &[u8]) -> Result<(), usize> {
+    let starts = InstructionStarts::new(bytes);
+    let mut pc = 0;
+    while (pc as usize) < bytes.len() {
+        let (instruction, next_pc) = decode_next(bytes, pc).map_err(|_| pc as usize)?;
+        if let Some(target) = instruction.jump_target() {
+            if !starts.contains(target) { return Err(instruction.offset) }
+        }
+        pc = next_pc;
     }
     Ok(())
 }
@@ -106,24 +386,21 @@ pub fn decode_next(bytes: &[u8], index: u16) -> Result<(Instruction, u16), Error
     // immediates?
     let mut n1: isize = 0;
     let mut n2: isize = 0;
-    if instruction >= FIRST_N1_OPCODE && instruction < LAST_N_OPCODE {
-        if let Some(d1) = decode_sint(bytes, i) {
-            n1 = d1.value;
-            i = d1.new_index;
-            if instruction >= FIRST_N2_OPCODE {
-                if let Some(d2) = decode_sint(bytes, i) {
-                    n2 = d2.value;
-                    i = d2.new_index;
-                } else {
-                    return Err(ErrorCode::TruncatedCode);
-                }
-            }
-        } else {
-            return Err(ErrorCode::TruncatedCode);
+    let immediates = Opcode::immediate_count(instruction);
+    if immediates > 0 {
+        let d1 = decode_sint(bytes, i)?;
+        n1 = d1.value;
+        i = d1.new_index;
+        if immediates > 1 {
+            let d2 = decode_sint(bytes, i)?;
+            n2 = d2.value;
+            i = d2.new_index;
         }
     }
 
     let instruction = Instruction { opcode: Opcode::from_u8(instruction), n1, n2, offset: index as usize };
+    // the pc is 16 bits, so the next one has to fit, or it would wrap around.
+    if i > u16::MAX as usize { return Err(ErrorCode::OutOfBounds) }
     Ok((instruction, i as u16))
 }
 
@@ -131,8 +408,12 @@ pub fn decode_next(bytes: &[u8], index: u16) -> Result<(Instruction, u16), Error
 #[cfg(test)]
 mod tests {
     use mwgc::StringBuffer;
+    use crate::constant_pool::Code;
+    use crate::decode_int::encode_sint;
     use crate::opcode::Opcode;
-    use super::disassemble_to_string;
+    use super::{
+        Instruction, disassemble_code_to_string, disassemble_to_string, disassemble_verbose, disassemble_with_labels, verify_jumps,
+    };
 
     #[test]
     fn disassemble() {
@@ -164,40 +445,44 @@ mod tests {
         let bytes: &[u8] = &[
             Opcode::Dup as u8, Opcode::New as u8, Opcode::Size as u8, Opcode::StoreSlotN as u8, 0x84, 4,
             Opcode::LoadLocalN as u8, 0x80, 0x80, 1, Opcode::StoreLocalN as u8, 6,
-            Opcode::ReturnN as u8, 2,
+            Opcode::ReturnN as u8, 2, Opcode::Swap as u8, Opcode::Over as u8, Opcode::Rot as u8,
         ];
         let mut buffer: [u8; 256] = [0; 256];
         let mut b = StringBuffer::new(&mut buffer);
         disassemble_to_string(&bytes, &mut b).ok();
         assert_eq!(
             b.to_str(),
-            "0000: DUP\n0001: NEW\n0002: SIZE\n0003: STS #258\n0006: LD @8192\n000a: ST @3\n000c: RET #1\n"
+            "0000: DUP\n0001: NEW\n0002: SIZE\n0003: STS #258\n0006: LD @8192\n000a: ST @3\n000c: RET #1\n000e: SWAP\n000f: OVER\n0010: ROT\n"
         );
 
         let bytes: &[u8] = &[
             Opcode::NewNN as u8, 0x80, 0x80, 0x80, 1, 0x82, 0x80, 0x80, 1,
             Opcode::LoadGlobalN as u8, 12, Opcode::StoreGlobalN as u8, 100, Opcode::Call as u8,
-            Opcode::CallN as u8, 0x80, 1,
+            Opcode::CallN as u8, 0x80, 1, Opcode::LoadField as u8, 16, 32,
+            Opcode::SnapshotGlobals as u8, 0, 4, Opcode::RestoreGlobals as u8, 2, 6, Opcode::CallNN as u8, 4, 2,
+            Opcode::CopyLocal as u8, 2, 6,
         ];
         let mut buffer: [u8; 256] = [0; 256];
         let mut b = StringBuffer::new(&mut buffer);
         disassemble_to_string(&bytes, &mut b).ok();
         assert_eq!(
             b.to_str(),
-            "0000: NEW #1048576, #1048577\n0009: LD $6\n000b: ST $50\n000d: CALL\n000e: CALL #64\n"
+            "0000: NEW #1048576, #1048577\n0009: LD $6\n000b: ST $50\n000d: CALL\n000e: CALL #64\n\
+             0011: FIELD #8, #16\n0014: LD $0, #2\n0017: ST $1, #3\n001a: CALL #2, #1\n001d: COPY @3, @1\n"
         );
 
         let bytes: &[u8] = &[
             Opcode::Unary as u8, 0,
             Opcode::Unary as u8, 2,
             Opcode::Unary as u8, 4,
+            Opcode::Unary as u8, 6,
         ];
         let mut buffer: [u8; 256] = [0; 256];
         let mut b = StringBuffer::new(&mut buffer);
         disassemble_to_string(&bytes, &mut b).ok();
         assert_eq!(
             b.to_str(),
-            "0000: NOT\n0002: NEG\n0004: INV\n"
+            "0000: NOT\n0002: NEG\n0004: INV\n0006: ABS\n"
         );
 
         let bytes: &[u8] = &[
@@ -225,13 +510,157 @@ mod tests {
             Opcode::Binary as u8, 22,
             Opcode::Binary as u8, 24,
             Opcode::Binary as u8, 26,
+            Opcode::Binary as u8, 28,
+            Opcode::Binary as u8, 30,
+            Opcode::Binary as u8, 32,
+            Opcode::Binary as u8, 34,
+            Opcode::Binary as u8, 36,
+            Opcode::Binary as u8, 38,
+            Opcode::Binary as u8, 40,
+            Opcode::Binary as u8, 42,
+            Opcode::Binary as u8, 44,
+            Opcode::Binary as u8, 46,
+            Opcode::Binary as u8, 48,
+            Opcode::Binary as u8, 50,
+            Opcode::Binary as u8, 52,
         ];
         let mut buffer: [u8; 256] = [0; 256];
         let mut b = StringBuffer::new(&mut buffer);
         disassemble_to_string(&bytes, &mut b).ok();
         assert_eq!(
             b.to_str(),
-            "0000: AND\n0002: XOR\n0004: LSL\n0006: LSR\n0008: ASR\n"
+            "0000: AND\n0002: XOR\n0004: LSL\n0006: LSR\n0008: ASR\n000a: LAND\n000c: LOR\n000e: REQ\n0010: GT\n0012: GE\n0014: ULT\n0016: UDIV\n0018: UMOD\n001a: MIN\n001c: MAX\n001e: ADDC\n0020: SUBC\n0022: MULC\n"
         );
+
+        let bytes: &[u8] = &[
+            Opcode::StackRoom as u8, 6, Opcode::JumpIfPointer as u8, 0x80, 1,
+            Opcode::StackAddrN as u8, 2, Opcode::FrameInfo as u8, Opcode::ExchangeSlot as u8,
+            Opcode::NewTaggedN as u8, 14, Opcode::TypeOf as u8, Opcode::FreeObject as u8,
+            Opcode::Select as u8, Opcode::IncGlobalN as u8, 6, Opcode::AddGlobalN as u8, 0,
+            Opcode::SlotDiff as u8, Opcode::JumpDynamic as u8, Opcode::CaptureStack as u8,
+            Opcode::Throw as u8, Opcode::NewAlignedN as u8, 32, Opcode::DecTestLocalN as u8, 4,
+            Opcode::NewFromStack as u8, Opcode::NewWeak as u8, Opcode::CallDepth as u8,
+            Opcode::LoadIndexed as u8, Opcode::StoreIndexed as u8, Opcode::Freeze as u8,
+            Opcode::PickN as u8, 6, Opcode::JumpRel as u8, 5,
+            Opcode::JumpIfZero as u8, 0x80, 1, Opcode::JumpIfNonZero as u8, 4,
+            Opcode::IncLocalN as u8, 2, Opcode::DecLocalN as u8, 6, Opcode::CallNativeN as u8, 4,
+            Opcode::ConstantBytes as u8, 6,
+        ];
+        let mut buffer: [u8; 512] = [0; 512];
+        let mut b = StringBuffer::new(&mut buffer);
+        disassemble_to_string(&bytes, &mut b).ok();
+        assert_eq!(
+            b.to_str(),
+            "0000: ROOM #3\n0002: JPTR 0040\n0005: SADDR #1\n0007: FRAME\n0008: XCHG\n0009: NEW :7\n000b: TYPE\n000c: FREE\n000d: SEL\n000e: INC $3\n0010: ADD $0\n0012: SDIFF\n0013: JUMP *\n0014: STACK\n0015: THROW\n0016: NEW %16\n0018: DECZ @2\n001a: NEW *\n001b: WEAK\n001c: DEPTH\n001d: LDX\n001e: STX\n001f: FREEZE\n0020: PICK #3\n0022: JUMP -3\n0024: JZ 0040\n0027: JNZ 0002\n0029: INC @1\n002b: DEC @3\n002d: SYS #2\n002f: LDB #12\n"
+        );
+    }
+
+    #[test]
+    fn disassemble_code() {
+        let bytes: &[u8] = &[ Opcode::LoadLocalN as u8, 2, Opcode::ReturnN as u8, 2 ];
+        let mut buffer: [u8; 256] = [0; 256];
+        let mut b = StringBuffer::new(&mut buffer);
+        disassemble_code_to_string(&Code::new(2, 4, bytes), &mut b).ok();
+        assert_eq!(b.to_str(), "; locals=2 stack=4\n0000: LD @1\n0002: RET #1\n");
+    }
+
+    #[test]
+    fn jumps() {
+        let jump = Opcode::Jump as u8;
+        // a backward jump to the start, and a forward one to an instruction with a two-byte immediate:
+        assert_eq!(verify_jumps(&[ Opcode::Nop as u8, jump, 0, jump, 10, Opcode::Immediate as u8, 0x80, 2 ]), Ok(()));
+        // past the end:
+        assert_eq!(verify_jumps(&[ Opcode::Nop as u8, jump, 8 ]), Err(1));
+        // into the middle of an immediate:
+        assert_eq!(verify_jumps(&[ Opcode::Immediate as u8, 0x80, 2, jump, 2 ]), Err(3));
+        // relative jumps count from their own offset:
+        assert_eq!(verify_jumps(&[ Opcode::Nop as u8, Opcode::JumpRel as u8, 1 ]), Ok(()));
+        assert_eq!(verify_jumps(&[ Opcode::Nop as u8, Opcode::JumpRel as u8, 3 ]), Err(1));
+        // a truncated instruction stops the check:
+        assert_eq!(verify_jumps(&[ Opcode::Nop as u8, Opcode::Immediate as u8 ]), Err(1));
+
+        // the last offset a 16-bit pc can reach:
+        let mut long = [ Opcode::Nop as u8; u16::MAX as usize ];
+        long[0] = jump;
+        encode_sint(u16::MAX as isize - 1, &mut long[1 ..]).unwrap();
+        assert_eq!(verify_jumps(&long), Ok(()));
+    }
+
+    #[test]
+    fn disassemble_labels() {
+        // count local 0 down to zero, then return it
+        let bytes: &[u8] = &[
+            Opcode::DecTestLocalN as u8, 0, Opcode::JumpIfNonZero as u8, 0x10, Opcode::Jump as u8, 0,
+            Opcode::Immediate as u8, 2, Opcode::LoadLocalN as u8, 0, Opcode::ReturnN as u8, 2,
+        ];
+        let mut targets = [ 0u16; 2 ];
+        let mut buffer: [u8; 256] = [0; 256];
+        let mut b = StringBuffer::new(&mut buffer);
+        disassemble_with_labels(bytes, &mut targets, &mut b).ok();
+        assert_eq!(
+            b.to_str(),
+            "L0:\n0000: DECZ @0\n0002: JNZ L1\n0004: JUMP L0\n0006: LD #1\nL1:\n0008: LD @0\n000a: RET #1\n"
+        );
+
+        // not enough room for both targets:
+        let mut buffer: [u8; 256] = [0; 256];
+        let mut b = StringBuffer::new(&mut buffer);
+        assert!(disassemble_with_labels(bytes, &mut targets[0 .. 1], &mut b).is_err());
+        assert_eq!(b.to_str(), "");
+
+        // each jump keeps its own mnemonic, and a target inside an instruction isn't a label.
+        let jumps: &[u8] = &[
+            Opcode::JumpIfPointer as u8, 4, Opcode::JumpRel as u8, 4, Opcode::JumpIfZero as u8, 6,
+            Opcode::Nop as u8,
+        ];
+        let mut targets = [ 0u16; 3 ];
+        let mut buffer: [u8; 256] = [0; 256];
+        let mut b = StringBuffer::new(&mut buffer);
+        disassemble_with_labels(jumps, &mut targets, &mut b).ok();
+        assert_eq!(b.to_str(), "0000: JPTR L0\nL0:\n0002: JUMP L1\nL1:\n0004: JZ 0003\n0006: NOP\n");
+
+        // the raw form is unchanged
+        let mut buffer: [u8; 256] = [0; 256];
+        let mut b = StringBuffer::new(&mut buffer);
+        disassemble_to_string(bytes, &mut b).ok();
+        assert_eq!(
+            b.to_str(),
+            "0000: DECZ @0\n0002: JNZ 0008\n0004: JUMP 0000\n0006: LD #1\n0008: LD @0\n000a: RET #1\n"
+        );
+    }
+
+    #[test]
+    fn disassemble_raw_bytes() {
+        let bytes: &[u8] = &[
+            Opcode::Nop as u8, Opcode::Immediate as u8, 2, Opcode::Constant as u8, 0x80, 2,
+            Opcode::LoadField as u8, 0x80, 1, 0x10, Opcode::ReturnN as u8, 2,
+        ];
+        let mut buffer: [u8; 256] = [0; 256];
+        let mut b = StringBuffer::new(&mut buffer);
+        disassemble_verbose(bytes, &mut b).ok();
+        assert_eq!(
+            b.to_str(),
+            "0000: 01            NOP\n0001: 10 02         LD #1\n0003: 11 80 02      LDC #512\n0006: 21 80 01 10   FIELD #64, #8\n000a: 1b 02         RET #1\n"
+        );
+    }
+
+    #[test]
+    fn stack_effect() {
+        let effect = |opcode, n1, n2| Instruction { offset: 0, opcode, n1, n2 }.stack_effect();
+        assert_eq!(effect(Opcode::Nop, 0, 0), (0, 0));
+        assert_eq!(effect(Opcode::Immediate, 7, 0), (0, 1));
+        assert_eq!(effect(Opcode::Binary, 0, 0), (2, 1));
+        assert_eq!(effect(Opcode::Dup, 0, 0), (1, 2));
+        assert_eq!(effect(Opcode::Rot, 0, 0), (3, 3));
+        assert_eq!(effect(Opcode::StoreSlot, 0, 0), (3, 0));
+        assert_eq!(effect(Opcode::FrameInfo, 0, 0), (0, 2));
+        // immediates can set the counts:
+        assert_eq!(effect(Opcode::NewNN, 4, 2), (2, 1));
+        assert_eq!(effect(Opcode::CallNN, 3, 2), (4, 2));
+        assert_eq!(effect(Opcode::ReturnN, 2, 0), (2, 0));
+        // or only the stack knows:
+        assert_eq!(effect(Opcode::New, 0, 0), (Instruction::DYNAMIC, 1));
+        assert_eq!(effect(Opcode::CallN, 1, 0), (2, Instruction::DYNAMIC));
+        assert_eq!(effect(Opcode::Call, 0, 0), (Instruction::DYNAMIC, Instruction::DYNAMIC));
     }
 }
@@ -1,7 +1,8 @@
 use core::fmt;
+use crate::opcode::Opcode;
 use crate::stack_frame::{RuntimeContext, StackFrame};
 
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum ErrorCode {
     // these errors indicate that there's something wrong with your bytecode generator:
     InvalidCodeObject = 1,
@@ -11,31 +12,87 @@ pub enum ErrorCode {
     OutOfBounds,
     UnknownOpcode,
     TruncatedCode,
+    // an immediate with more continuation bytes than a word can hold:
+    MalformedVarint,
     StackUnderflow,
     StackOverflow,
     LocalsOverflow,
+    UninitializedLocal,
+    ReturnCountMismatch,
+    DivideByZero,
+    // always for `isize::MIN / -1`, `ABS` of `isize::MIN`, and the checked binary ops, and
+    // otherwise only with `RuntimeOptions::trap_overflow`:
+    ArithmeticOverflow,
+    // writing to (or freeing) an object made read-only by `Freeze`:
+    Immutable,
+    // `CallNativeN` with an index past the end of `Runtime::set_natives`:
+    InvalidNativeIndex,
 
     // these errors are resource constraints:
     OutOfMemory,
+    GlobalsAllocationFailed,
     TimeExceeded,
     CyclesExceeded,
+    HeapExceeded,
+    CallDepthExceeded,
+    // code returned more values than there was room for in `results`:
+    ResultsOverflow,
+
+    // the runtime was set up wrong:
+    // the heap and constant pool share memory, so addresses are ambiguous.
+    OverlappingRegions,
+    // the image was built for a different word size than this target's.
+    WordSizeMismatch,
+    // the image header is missing, cut off, or from an unknown version.
+    InvalidImage,
+    // the heap buffer is too small to allocate anything from.
+    HeapTooSmall,
 
     // these errors were invoked by your code object intentionally:
+    // (without a break handler, `Runtime::resume` continues after it)
     Break,
+    // `Throw`, with the code it threw in `RuntimeError::thrown`:
+    Thrown,
+
+    // not really an error: execution is paused, and can be picked up again
+    // with `Runtime::resume`:
+    CallIntercepted,
+    // `resume` was called, but nothing was paused:
+    NotSuspended,
+    // `step` was given a context that isn't from the latest `start`, or has returned:
+    NotStepping,
+    // `execute_named` was given a name that isn't in the symbol table:
+    UnknownSymbol,
 }
 
 pub struct RuntimeError {
     pub code: ErrorCode,
     pub frame: *const StackFrame,
+    // the bytecode that couldn't be decoded or executed, if that's what went wrong:
+    pub opcode: Option<u8>,
+    // the code that bytecode passed to `Throw`, if that's what went wrong:
+    pub thrown: Option<u16>,
 }
 
 impl RuntimeError {
     pub fn new(code: ErrorCode) -> RuntimeError {
-        RuntimeError { code, frame: core::ptr::null() }
+        RuntimeError { code, frame: core::ptr::null(), opcode: None, thrown: None }
     }
 
     pub fn from<'a, 'rom, 'heap>(code: ErrorCode, context: &'a RuntimeContext<'rom, 'heap>) -> RuntimeError {
-        RuntimeError { code, frame: context.frame as *const StackFrame }
+        RuntimeError { code, frame: context.frame as *const StackFrame, opcode: None, thrown: None }
+    }
+
+    /// Attach the bytecode at the frame's current pc.
+    pub fn at_opcode<'a, 'rom, 'heap>(mut self, context: &'a RuntimeContext<'rom, 'heap>) -> RuntimeError {
+        self.opcode = context.code.bytecode.get(context.frame.pc as usize).cloned();
+        self
+    }
+
+    /// Attach the opcode of an instruction the pc may have already moved past.
+    pub fn with_opcode(mut self, opcode: Opcode) -> RuntimeError {
+        self.opcode = Some(opcode as u8);
+        self
     }
 }
 
@@ -43,6 +100,12 @@ impl RuntimeError {
 impl<'heap> fmt::Debug for RuntimeError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "{:?}", self.code)?;
+        if let Some(opcode) = self.opcode {
+            write!(f, "({:#04x})", opcode)?;
+        }
+        if let Some(thrown) = self.thrown {
+            write!(f, "({})", thrown)?;
+        }
         if let Some(frame) = unsafe { self.frame.as_ref() } {
             if f.alternate() {
                 write!(f, " at {:#?}", frame)?;
@@ -1,14 +1,29 @@
 #![no_std]
 
+mod address_list;
 mod constant_pool;
 mod decode_int;
 mod disassembler;
 mod error;
 mod opcode;
+mod reachability;
+mod results;
 mod runtime;
+mod stack_depth;
 mod stack_frame;
+mod verify;
 
-pub use disassembler::{disassemble, disassemble_to_string};
+pub use constant_pool::{Code, ConstantPool, ImageHeader};
+pub use decode_int::{encode_sint, encode_uint};
+pub use disassembler::{
+    Instruction, disassemble, disassemble_code_to_string, disassemble_to_string, disassemble_verbose, disassemble_with_labels,
+    verify_jumps,
+};
 pub use error::{ErrorCode, RuntimeError};
-pub use opcode::{Binary, Opcode, Unary};
-pub use runtime::Runtime;
+pub use opcode::{Binary, Opcode, Unary, opcode_cost};
+pub use reachability::{Reachability, reachable_code};
+pub use results::Results;
+pub use runtime::{BreakAction, NativeFunction, POISON, Runtime, RuntimeOptions, StepResult};
+pub use stack_depth::max_stack_depth;
+pub use stack_frame::RuntimeContext;
+pub use verify::{verify_code, verify_image};
@@ -1,5 +1,3 @@
-use core::mem;
-
 #[repr(u8)]
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub enum Opcode {
@@ -10,14 +8,17 @@ pub enum Opcode {
     Drop = 0x03,                        // (probably only hand-crafted code/tests)
     Call = 0x04,                        // call S2 with S1 args preceding
     Return = 0x05,                      // return S1 items from stack
-    New = 0x06,                         // S1(slots) S2(fill_from_stack) -> obj S1
+    New = 0x06,                         // S1(slots) S2(fill_from_stack) -> obj S1 (first pushed is slot 0)
     Size = 0x07,                        // #slots(S1) -> S1
     LoadSlot = 0x08,                    // S1[S2] -> S1
     StoreSlot = 0x09,                   // S1[S2] := S3
     If = 0x0a,
+    Swap = 0x0b,                        // S1 S2 -> S2 S1
+    Over = 0x0c,                        // S1 S2 -> S1 S2 S1
+    Rot = 0x0d,                         // S1 S2 S3 -> S2 S3 S1
 
     // 1 immediate:
-    Immediate = 0x10,                   // N1 -> S1
+    Immediate = 0x10,                   // N1 -> S1 (any signed word, stored as its bit pattern)
     Constant = 0x11,                    // addr(constant_pool + (N1 << 2)) -> S1,
     LoadSlotN = 0x12,                   // S1[N1] -> S1
     StoreSlotN = 0x13,                  // S1[N1] := S2
@@ -30,22 +31,156 @@ pub enum Opcode {
     CallN = 0x1a,                       // call S1 with N1 args preceding
     ReturnN = 0x1b,                     // return N1 items from stack
     Jump = 0x1c,
+    StackRoom = 0x1d,                   // (sp + N1 <= max_stack) -> S1
+    JumpIfPointer = 0x1e,               // jump to N1 if S1 is an object reference
+    StackAddrN = 0x1f,                  // addr(stack[sp - 1 - N1]) -> S1
 
     // 2 immediates:
     NewNN = 0x20,                       // N1(slots) N2(fill) -> obj S1
+    LoadField = 0x21,                   // sign-extended bits N1 .. N1+N2 of S1 -> S1
+    SnapshotGlobals = 0x22,             // obj($N1 .. $N1+N2) -> S1
+    RestoreGlobals = 0x23,              // S1[0 .. N2] -> $N1 .. $N1+N2
+    CallNN = 0x24,                      // call S1 with N1 args preceding, which must return N2 items
+    CopyLocal = 0x25,                   // @N2 := @N1
+
+    // 0 immediates (extended):
+    FrameInfo = 0x30,                   // -> local_count(S2) max_stack(S1)
+    ExchangeSlot = 0x31,                // S1[S2] := S3, old S1[S2] -> S1
+    TypeOf = 0x32,                      // tag(S1) -> S1
+    FreeObject = 0x33,                  // S1 -> (free list)
+    Select = 0x34,                      // (S3 ? S1 : S2) -> S1, without branching
+    SlotDiff = 0x35,                    // (S1 - S2) / word -> S1, within one object
+    JumpDynamic = 0x36,                 // jump to S1
+    CaptureStack = 0x37,                // obj(stack[0 .. sp]) -> S1, leaving the stack as it was
+    Throw = 0x38,                       // stop with error code S1
+    NewFromStack = 0x39,                // S1(count) -> obj(the count items before it) S1
+    NewWeak = 0x3a,                     // weak(S1) -> S1
+    CallDepth = 0x3b,                   // (frames above the one `execute` started) -> S1
+    LoadIndexed = 0x3c,                 // S1[S2] -> S1, if S2 is within S1
+    StoreIndexed = 0x3d,                // S1[S2] := S3, if S2 is within S1
+    Freeze = 0x3e,                      // S1 -> (read-only)
+
+    // 1 immediate (extended):
+    NewTaggedN = 0x40,                  // S1(slots) S2(fill) -> obj(tag N1) S1
+    IncGlobalN = 0x41,                  // $N1 := $N1 + 1
+    AddGlobalN = 0x42,                  // $N1 := $N1 + S1
+    NewAlignedN = 0x43,                 // S1(slots) S2(fill) -> obj(aligned to N1 bytes) S1
+    DecTestLocalN = 0x44,               // @N1 := max(@N1 - 1, 0), (@N1 == 0) -> S1
+    PickN = 0x45,                       // stack[sp - 1 - N1] -> S1
+    JumpRel = 0x46,                     // jump to (this instruction + N1)
+    JumpIfZero = 0x47,                  // jump to N1 if S1 is 0
+    JumpIfNonZero = 0x48,               // jump to N1 if S1 isn't 0
+    IncLocalN = 0x49,                   // @N1 := @N1 + 1
+    DecLocalN = 0x4a,                   // @N1 := @N1 - 1
+    CallNativeN = 0x4b,                 // native N1(the S1 items before S1) -> its results
+    ConstantBytes = 0x4c,               // addr(bytes at constant N1 << 2) S2, len S1
 
     Unknown = 0xff,
 }
 
-// opcodes 0x1X have one immediate; 0x2X have two
-pub const FIRST_N1_OPCODE: u8 = 0x10;
-pub const FIRST_N2_OPCODE: u8 = 0x20;
-pub const LAST_N_OPCODE: u8 = 0x30;
-
 impl Opcode {
-    // why isn't this automatic or derivable?
+    // opcodes 0x1X and 0x4X have one immediate; 0x2X have two; everything else has none
+    pub fn immediate_count(n: u8) -> usize {
+        match n >> 4 {
+            0x1 | 0x4 => 1,
+            0x2 => 2,
+            _ => 0,
+        }
+    }
+
+    // true if `n` is one of the opcodes above.
+    pub fn is_valid(n: u8) -> bool {
+        Opcode::from_u8(n) != Opcode::Unknown
+    }
+
+    // every byte that isn't one of the opcodes above becomes `Unknown`.
     pub fn from_u8(n: u8) -> Opcode {
-        unsafe { mem::transmute(n) }
+        match n {
+            0x00 => Opcode::Break,
+            0x01 => Opcode::Nop,
+            0x02 => Opcode::Dup,
+            0x03 => Opcode::Drop,
+            0x04 => Opcode::Call,
+            0x05 => Opcode::Return,
+            0x06 => Opcode::New,
+            0x07 => Opcode::Size,
+            0x08 => Opcode::LoadSlot,
+            0x09 => Opcode::StoreSlot,
+            0x0a => Opcode::If,
+            0x0b => Opcode::Swap,
+            0x0c => Opcode::Over,
+            0x0d => Opcode::Rot,
+            0x10 => Opcode::Immediate,
+            0x11 => Opcode::Constant,
+            0x12 => Opcode::LoadSlotN,
+            0x13 => Opcode::StoreSlotN,
+            0x14 => Opcode::LoadLocalN,
+            0x15 => Opcode::StoreLocalN,
+            0x16 => Opcode::LoadGlobalN,
+            0x17 => Opcode::StoreGlobalN,
+            0x18 => Opcode::Unary,
+            0x19 => Opcode::Binary,
+            0x1a => Opcode::CallN,
+            0x1b => Opcode::ReturnN,
+            0x1c => Opcode::Jump,
+            0x1d => Opcode::StackRoom,
+            0x1e => Opcode::JumpIfPointer,
+            0x1f => Opcode::StackAddrN,
+            0x20 => Opcode::NewNN,
+            0x21 => Opcode::LoadField,
+            0x22 => Opcode::SnapshotGlobals,
+            0x23 => Opcode::RestoreGlobals,
+            0x24 => Opcode::CallNN,
+            0x25 => Opcode::CopyLocal,
+            0x30 => Opcode::FrameInfo,
+            0x31 => Opcode::ExchangeSlot,
+            0x32 => Opcode::TypeOf,
+            0x33 => Opcode::FreeObject,
+            0x34 => Opcode::Select,
+            0x35 => Opcode::SlotDiff,
+            0x36 => Opcode::JumpDynamic,
+            0x37 => Opcode::CaptureStack,
+            0x38 => Opcode::Throw,
+            0x39 => Opcode::NewFromStack,
+            0x3a => Opcode::NewWeak,
+            0x3b => Opcode::CallDepth,
+            0x3c => Opcode::LoadIndexed,
+            0x3d => Opcode::StoreIndexed,
+            0x3e => Opcode::Freeze,
+            0x40 => Opcode::NewTaggedN,
+            0x41 => Opcode::IncGlobalN,
+            0x42 => Opcode::AddGlobalN,
+            0x43 => Opcode::NewAlignedN,
+            0x44 => Opcode::DecTestLocalN,
+            0x45 => Opcode::PickN,
+            0x46 => Opcode::JumpRel,
+            0x47 => Opcode::JumpIfZero,
+            0x48 => Opcode::JumpIfNonZero,
+            0x49 => Opcode::IncLocalN,
+            0x4a => Opcode::DecLocalN,
+            0x4b => Opcode::CallNativeN,
+            0x4c => Opcode::ConstantBytes,
+            _ => Opcode::Unknown,
+        }
+    }
+}
+
+
+/// The worst-case cost of executing an opcode, in the "cycles" that
+/// `max_cycles` counts. Summing these along a path through a code block
+/// gives an upper bound for how many cycles it can use.
+pub fn opcode_cost(opcode: Opcode) -> u32 {
+    match opcode {
+        // these touch memory that has to be bounds-checked:
+        Opcode::LoadSlot | Opcode::StoreSlot | Opcode::LoadSlotN | Opcode::StoreSlotN | Opcode::ExchangeSlot |
+            Opcode::LoadIndexed | Opcode::StoreIndexed | Opcode::Size | Opcode::TypeOf | Opcode::SlotDiff => 2,
+        // these allocate or free a stack frame:
+        Opcode::Call | Opcode::CallN | Opcode::CallNN | Opcode::CallNativeN | Opcode::Return | Opcode::ReturnN => 4,
+        // these allocate an object, or copy a range of slots:
+        Opcode::New | Opcode::NewNN | Opcode::NewTaggedN | Opcode::NewAlignedN | Opcode::NewFromStack |
+            Opcode::NewWeak |
+            Opcode::SnapshotGlobals | Opcode::RestoreGlobals | Opcode::CaptureStack => 4,
+        _ => 1,
     }
 }
 
@@ -56,12 +191,20 @@ pub enum Unary {
     Not = 0,
     Negative = 1,
     BitNot = 2,
+    Abs = 3,
     Unknown = 0xff,
 }
 
 impl Unary {
+    // anything that isn't one of the operations above becomes `Unknown`.
     pub fn from_usize(n: usize) -> Unary {
-        unsafe { mem::transmute(n) }
+        match n {
+            0 => Unary::Not,
+            1 => Unary::Negative,
+            2 => Unary::BitNot,
+            3 => Unary::Abs,
+            _ => Unary::Unknown,
+        }
     }
 }
 
@@ -83,11 +226,57 @@ pub enum Binary {
     ShiftLeft = 11,
     ShiftRight = 12,
     SignShiftRight = 13,
+    LogicalAnd = 14,
+    LogicalOr = 15,
+    // identity: two references to the same object, never a deep comparison
+    RefEquals = 16,
+    GreaterThan = 17,
+    GreaterOrEqual = 18,
+    // these treat both operands as unsigned words
+    UnsignedLessThan = 19,
+    UnsignedDivide = 20,
+    UnsignedModulo = 21,
+    Min = 22,
+    Max = 23,
+    // these always fail with `ArithmeticOverflow` instead of wrapping
+    AddChecked = 24,
+    SubtractChecked = 25,
+    MultiplyChecked = 26,
     Unknown = 0xff,
 }
 
 impl Binary {
+    // anything that isn't one of the operations above becomes `Unknown`.
     pub fn from_usize(n: usize) -> Binary {
-        unsafe { mem::transmute(n) }
+        match n {
+            0 => Binary::Add,
+            1 => Binary::Subtract,
+            2 => Binary::Multiply,
+            3 => Binary::Divide,
+            4 => Binary::Modulo,
+            5 => Binary::Equals,
+            6 => Binary::LessThan,
+            7 => Binary::LessOrEqual,
+            8 => Binary::BitOr,
+            9 => Binary::BitAnd,
+            10 => Binary::BitXor,
+            11 => Binary::ShiftLeft,
+            12 => Binary::ShiftRight,
+            13 => Binary::SignShiftRight,
+            14 => Binary::LogicalAnd,
+            15 => Binary::LogicalOr,
+            16 => Binary::RefEquals,
+            17 => Binary::GreaterThan,
+            18 => Binary::GreaterOrEqual,
+            19 => Binary::UnsignedLessThan,
+            20 => Binary::UnsignedDivide,
+            21 => Binary::UnsignedModulo,
+            22 => Binary::Min,
+            23 => Binary::Max,
+            24 => Binary::AddChecked,
+            25 => Binary::SubtractChecked,
+            26 => Binary::MultiplyChecked,
+            _ => Binary::Unknown,
+        }
     }
 }
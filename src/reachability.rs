@@ -0,0 +1,80 @@
+use crate::constant_pool::ConstantPool;
+use crate::disassembler::decode_next;
+use crate::error::ErrorCode;
+use crate::opcode::Opcode;
+
+/// Summary of a reachability scan: the first `count` entries of the
+/// `reachable` buffer are the code offsets that can be reached.
+#[derive(Debug, PartialEq)]
+pub struct Reachability {
+    pub count: usize,
+    // true if reachable code makes calls through addresses that weren't
+    // loaded from the constant pool right before the call. those targets
+    // can't be known statically, but they were still counted if their
+    // address was loaded with `Constant` somewhere in reachable code.
+    pub has_dynamic_calls: bool,
+}
+
+/// Find every code block in the constant pool that can be reached from a
+/// set of entry points, by following each code block's `Constant` loads of
+/// other code blocks. This is conservative: a code block whose address is
+/// loaded anywhere in reachable code counts as reachable, whether it's
+/// called or not. Code addresses stored inside constant objects aren't
+/// followed.
+///
+/// `reachable` is filled with code offsets, and also used as the work
+/// queue, so it needs to be big enough to hold every reachable block.
+pub fn reachable_code(
+    pool: &ConstantPool,
+    entries: &[u32],
+    reachable: &mut [u32],
+) -> Result<Reachability, ErrorCode> {
+    let mut count = 0;
+    let mut has_dynamic_calls = false;
+    for &offset in entries {
+        add_offset(pool, offset, reachable, &mut count)?;
+    }
+
+    let mut next = 0;
+    while next < count {
+        let code = pool.get_code(pool.addr_from_offset(reachable[next]))?;
+        next += 1;
+
+        let mut pc = 0;
+        // the last two opcodes, to spot calls to a constant address
+        let mut history = [ Opcode::Nop, Opcode::Nop ];
+        while (pc as usize) < code.bytecode.len() {
+            let (instruction, next_pc) = decode_next(code.bytecode, pc)?;
+            match instruction.opcode {
+                Opcode::Constant => {
+                    let words = pool.data.len().div_ceil(4);
+                    if instruction.n1 >= 0 && (instruction.n1 as usize) < words {
+                        let offset = instruction.n1 as u32;
+                        if pool.get_code(pool.addr_from_offset(offset)).is_ok() {
+                            add_offset(pool, offset, reachable, &mut count)?;
+                        }
+                    }
+                },
+                Opcode::CallN if history[1] != Opcode::Constant => has_dynamic_calls = true,
+                // the arg count is pushed between the address and the call.
+                Opcode::Call if history[0] != Opcode::Constant || history[1] != Opcode::Immediate => {
+                    has_dynamic_calls = true;
+                },
+                _ => (),
+            }
+            history = [ history[1], instruction.opcode ];
+            pc = next_pc;
+        }
+    }
+
+    Ok(Reachability { count, has_dynamic_calls })
+}
+
+fn add_offset(pool: &ConstantPool, offset: u32, reachable: &mut [u32], count: &mut usize) -> Result<(), ErrorCode> {
+    if reachable[0 .. *count].contains(&offset) { return Ok(()) }
+    pool.get_code(pool.addr_from_offset(offset))?;
+    if *count >= reachable.len() { return Err(ErrorCode::OutOfBounds) }
+    reachable[*count] = offset;
+    *count += 1;
+    Ok(())
+}
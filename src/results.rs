@@ -0,0 +1,55 @@
+use core::ops::Index;
+use core::slice;
+
+/// The values a code block returned, from `Runtime::execute_results`:
+/// only the returned values are visible, not the rest of the buffer they
+/// were written into.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Results<'a> {
+    values: &'a [usize],
+}
+
+impl<'a> Results<'a> {
+    pub fn new(values: &'a [usize]) -> Results<'a> {
+        Results { values }
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// The value at `index`, if the code returned that many.
+    pub fn get(&self, index: usize) -> Option<usize> {
+        self.values.get(index).cloned()
+    }
+
+    pub fn iter(&self) -> slice::Iter<'a, usize> {
+        self.values.iter()
+    }
+
+    pub fn as_slice(&self) -> &'a [usize] {
+        self.values
+    }
+}
+
+// panics if `index` is past the returned values, like a slice would.
+impl<'a> Index<usize> for Results<'a> {
+    type Output = usize;
+
+    fn index(&self, index: usize) -> &usize {
+        &self.values[index]
+    }
+}
+
+impl<'a> IntoIterator for Results<'a> {
+    type Item = &'a usize;
+    type IntoIter = slice::Iter<'a, usize>;
+
+    fn into_iter(self) -> slice::Iter<'a, usize> {
+        self.values.iter()
+    }
+}
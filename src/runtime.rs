@@ -1,17 +1,64 @@
 use core::{fmt, mem};
+use core::num::NonZeroUsize;
 use mwgc::Heap;
 
-use crate::constant_pool::{ConstantPool};
-use crate::disassembler::{decode_next, Instruction};
+use crate::address_list::AddressList;
+use crate::constant_pool::{Code, ConstantPool};
+use crate::disassembler::{decode_next, is_instruction_start, Instruction};
 use crate::error::{ErrorCode, RuntimeError};
-use crate::opcode::{Binary, Opcode, Unary};
-use crate::stack_frame::{PreviousContext, RuntimeContext};
+use crate::opcode::{Binary, Opcode, Unary, opcode_cost};
+use crate::results::Results;
+use crate::stack_frame::{
+    DETACHED_CODE_OFFSET, FLAG_CHECK_LOCALS, PreviousContext, RuntimeContext, StackFrame, check_results, expect_results,
+};
 
 
-// pub struct RuntimeOptions {
-//     pub global_count: usize,
-//     pub current_time: Option<fn() -> usize>,
-// }
+#[derive(Clone, Copy, Default)]
+pub struct RuntimeOptions {
+    pub global_count: usize,
+    pub current_time: Option<fn() -> usize>,
+    // called with (code offset, pc) when a `Break` executes; without one, it's an error
+    pub break_handler: Option<fn(u32, u16) -> BreakAction>,
+    // called with the callee's code offset on each `Call`; if it returns
+    // true, execution stops with `CallIntercepted` before entering the callee
+    pub call_interceptor: Option<fn(u32) -> bool>,
+    // fill the unfilled slots of new objects with `POISON` instead of zero,
+    // to make reads of uninitialized slots stand out
+    pub poison_objects: bool,
+    // fail with `UninitializedLocal` when code reads a local it never wrote
+    pub check_uninitialized_locals: bool,
+    // make `JumpDynamic` fail with `OutOfBounds` if it lands in the middle
+    // of an instruction (this decodes the code block up to the target)
+    pub check_jump_targets: bool,
+    // fail with `ArithmeticOverflow` when add, subtract, multiply, or
+    // negate would overflow (including the opcodes that add to a local or
+    // global in place), or a shift is by more bits than a word has,
+    // instead of wrapping around
+    pub trap_overflow: bool,
+    // fail with `CallDepthExceeded` instead of calling a function this many
+    // frames above the one `execute` started, so runaway recursion doesn't
+    // just run the heap out of memory
+    pub max_call_depth: Option<usize>,
+    // offset of the symbol section in the constant pool, for
+    // `execute_named`: the image header doesn't record it, so the host has
+    // to say where it is
+    pub symbol_table: Option<u32>,
+}
+
+/// A function the host provides for `CallNativeN`: it gets the args, and
+/// room on the stack for results, and returns how many results it wrote.
+pub type NativeFunction = fn(&[usize], &mut [usize]) -> Result<usize, ErrorCode>;
+
+/// What unfilled slots of new objects hold, with `poison_objects` turned on.
+pub const POISON: usize = (usize::MAX / 0xffff) * 0xdead;
+
+/// What a break handler wants done with a `Break` opcode.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BreakAction {
+    Continue,       // treat it like a `Nop`
+    Return,         // return from the current frame, with no results
+    Error,          // stop with `ErrorCode::Break`
+}
 
 
 // what to do after executing a bytecode
@@ -19,131 +66,692 @@ use crate::stack_frame::{PreviousContext, RuntimeContext};
 enum Disposition {
     Continue,       // keep going, possibly across a jump
     Skip,           // skip next instruction
-    Call(usize, usize),
+    Call(usize, usize, u8), // code addr, arg count, flags for the new frame
     Return(usize),
     Jump(u16),
+    Throw(u16),     // stop with `ErrorCode::Thrown`
+}
+
+/// What one `Runtime::step` did.
+pub enum StepResult<'heap> {
+    /// Ran this instruction, and there's more to do.
+    Ran(Instruction),
+    /// The code block that was started returned these values (or ran off
+    /// the end, returning nothing), so there's nothing left to do.
+    Returned(&'heap [usize]),
 }
 
+// what `execute_traced` calls before each instruction
+type Trace<'t> = &'t mut dyn FnMut(&Instruction, &RuntimeContext);
+
+// resource limits for one run of the interpreter, and who to tell about
+// each instruction
+#[derive(Default)]
+struct Limits<'t> {
+    max_cycles: Option<NonZeroUsize>,
+    deadline: Option<NonZeroUsize>,
+    max_heap_bytes: Option<usize>,
+    max_call_depth: Option<usize>,
+    trace: Option<Trace<'t>>,
+    // running under `dry_run`: don't hand anything to the host
+    dry: bool,
+}
+
+
+// (code addr, arg count, frame flags) of a call that hasn't been entered yet
+type PendingCall = (usize, usize, u8);
+
+// an execution that was paused, and can be picked back up
+struct Suspended<'rom> {
+    frame: usize,
+    code: Code<'rom>,
+    depth: usize,
+    // where to pick up: after the intercepted call, or after the `Break`
+    pc: u16,
+    // the call it stopped before, or `None` if it stopped at a `Break`
+    call: Option<PendingCall>,
+}
+
+// the context handed out by `start`, which has to survive collections
+// until it finishes
+struct Stepping<'rom> {
+    frame: usize,
+    code: Code<'rom>,
+    depth: usize,
+}
+
+impl<'rom> Stepping<'rom> {
+    fn of(context: &RuntimeContext<'rom, '_>) -> Stepping<'rom> {
+        Stepping { frame: context.frame_addr(), code: context.code, depth: context.depth }
+    }
+}
+
+// what `dry_run` puts back when it's done, where a collection can see it
+struct DryRunSaved<'rom, 'heap> {
+    globals: &'heap mut [usize],
+    free_list: [usize; FREE_LIST_SIZE],
+    free_count: usize,
+    suspended: Option<Suspended<'rom>>,
+}
+
+
+// objects released by `FreeObject`, kept around for `New` to reuse
+const FREE_LIST_SIZE: usize = 8;
+// mwgc rounds allocations up to 16-byte blocks, so a freed object can be
+// reused for any size that would round up the same way.
+const FREE_LIST_SLACK: usize = 16 / mem::size_of::<usize>();
+
+
+// a heap buffer smaller than this can't hold the heap's own bookkeeping and
+// any objects, so it's refused up front instead of failing the first allocation
+const MIN_HEAP_BYTES: usize = 64;
+
+
+// the most words any allocation can span: a stack frame with 63 locals
+// and 63 stack slots, its header, and a bitmap for checking its locals
+const MAX_ALLOCATION_WORDS: usize = 63 + 63 + 3 + 1;
+
+
+// header word of a tagged object: the tag, then 8 bits of slot count
+const HEADER_TAG_SHIFT: usize = 8;
+const HEADER_SLOTS_MASK: usize = 0xff;
+const MAX_TAG: usize = usize::MAX >> HEADER_TAG_SHIFT;
+
+// the most an object can be aligned by `NewAlignedN`, in bytes
+const MAX_ALIGNMENT: usize = 64;
+
 
 pub struct Runtime<'rom, 'heap> {
     constant_pool: ConstantPool<'rom>,
+    // optional read-only memory outside the heap, for tables built at load time:
+    rodata: ConstantPool<'rom>,
     heap: Heap<'heap>,
     globals: &'heap mut [usize],
     current_time: Option<fn() -> usize>,
+    break_handler: Option<fn(u32, u16) -> BreakAction>,
+    call_interceptor: Option<fn(u32) -> bool>,
+    poison_objects: bool,
+    frame_flags: u8,
+    check_jump_targets: bool,
+    trap_overflow: bool,
+    max_call_depth: Option<usize>,
+    suspended: Option<Suspended<'rom>>,
+    stepping: Option<Stepping<'rom>>,
+    free_list: [usize; FREE_LIST_SIZE],
+    free_count: usize,
+    // weak references made by `NewWeak`, which are cleared when their
+    // target is collected
+    weak_refs: AddressList<'heap>,
+    // the allocations of objects made read-only by `Freeze`
+    frozen: AddressList<'heap>,
+    // every object with a header word (from `NewTaggedN` or `NewAlignedN`),
+    // so slot 0 of a plain object is never mistaken for a header
+    tagged: AddressList<'heap>,
+    // the allocations of objects the host is holding onto, which survive
+    // collections until they're unpinned
+    pinned: AddressList<'heap>,
+    // (code offset, [(pc, source line)]) for any code blocks that have them
+    line_tables: &'rom [(u32, &'rom [(u16, u32)])],
+    natives: &'rom [NativeFunction],
+    dry_run_saved: Option<DryRunSaved<'rom, 'heap>>,
+    final_stack_depth: usize,
 }
 
 impl<'rom, 'heap> Runtime<'rom, 'heap> {
+    /// Build a runtime from a bare constant pool, without an image header.
+    /// The pool doesn't say what word size it was built for, so only
+    /// `from_image` can catch a `WordSizeMismatch`.
     pub fn new(
         constant_pool_data: &'rom [u8],
         heap_data: &'heap mut [u8],
         global_count: usize,
         current_time: Option<fn() -> usize>,
     ) -> Result<Runtime<'rom, 'heap>, RuntimeError> {
-        let constant_pool = ConstantPool::new(constant_pool_data);
-        let mut heap = Heap::from_bytes(heap_data);
+        Runtime::with_options(constant_pool_data, heap_data, RuntimeOptions {
+            global_count,
+            current_time,
+            ..RuntimeOptions::default()
+        })
+    }
+
+    /// Like `new`, with the rest of the options too.
+    pub fn with_options(
+        constant_pool_data: &'rom [u8],
+        heap_data: &'heap mut [u8],
+        options: RuntimeOptions,
+    ) -> Result<Runtime<'rom, 'heap>, RuntimeError> {
+        Runtime::on_heap_data(ConstantPool::new(constant_pool_data), heap_data, options)
+    }
+
+    /// Build a runtime from an image file, header and all. The header's
+    /// global count is used instead of `options.global_count`, and an image
+    /// built for a different word size is `WordSizeMismatch`.
+    pub fn from_image(
+        image: &'rom [u8],
+        heap_data: &'heap mut [u8],
+        options: RuntimeOptions,
+    ) -> Result<Runtime<'rom, 'heap>, RuntimeError> {
+        let (header, constant_pool) = ConstantPool::from_image(image).map_err(RuntimeError::new)?;
+        Runtime::on_heap_data(constant_pool, heap_data, RuntimeOptions { global_count: header.global_count, ..options })
+    }
+
+    fn on_heap_data(
+        constant_pool: ConstantPool<'rom>,
+        heap_data: &'heap mut [u8],
+        options: RuntimeOptions,
+    ) -> Result<Runtime<'rom, 'heap>, RuntimeError> {
+        let pool_start = constant_pool.data.as_ptr() as usize;
+        let heap_start = heap_data.as_ptr() as usize;
+        if pool_start < heap_start + heap_data.len() && heap_start < pool_start + constant_pool.data.len() {
+            return Err(RuntimeError::new(ErrorCode::OverlappingRegions));
+        }
+        if heap_data.len() < MIN_HEAP_BYTES { return Err(RuntimeError::new(ErrorCode::HeapTooSmall)) }
+        Runtime::on_heap(constant_pool, Heap::from_bytes(heap_data), options)
+    }
+
+    /// Build a runtime on a heap that's already in use, so it can be shared
+    /// with other allocators. Anything already allocated is left alone, but
+    /// the runtime can't tell those objects apart from its own.
+    /// The heap's extent isn't visible here, so only a constant pool that
+    /// starts or ends inside the heap is caught as `OverlappingRegions`.
+    pub fn with_heap(
+        constant_pool_data: &'rom [u8],
+        heap: Heap<'heap>,
+        options: RuntimeOptions,
+    ) -> Result<Runtime<'rom, 'heap>, RuntimeError> {
+        Runtime::on_heap(ConstantPool::new(constant_pool_data), heap, options)
+    }
+
+    fn on_heap(
+        mut constant_pool: ConstantPool<'rom>,
+        mut heap: Heap<'heap>,
+        options: RuntimeOptions,
+    ) -> Result<Runtime<'rom, 'heap>, RuntimeError> {
+        if let (Some(first), Some(last)) = (constant_pool.data.first(), constant_pool.data.last()) {
+            if heap.is_ptr_inside(first as *const u8) || heap.is_ptr_inside(last as *const u8) {
+                return Err(RuntimeError::new(ErrorCode::OverlappingRegions));
+            }
+        }
+        if heap.get_stats().total_bytes == 0 { return Err(RuntimeError::new(ErrorCode::HeapTooSmall)) }
+        if let Some(offset) = options.symbol_table { constant_pool.set_symbol_table(offset); }
+        if constant_pool.target_word_size().is_some_and(|size| size != mem::size_of::<usize>()) {
+            return Err(RuntimeError::new(ErrorCode::WordSizeMismatch));
+        }
         // just allocate the globals as a heap object
-        let globals = heap.allocate_array::<usize>(global_count).ok_or_else(|| {
-            RuntimeError::new(ErrorCode::OutOfMemory)
+        let globals = heap.allocate_array::<usize>(options.global_count).ok_or_else(|| {
+            RuntimeError::new(ErrorCode::GlobalsAllocationFailed)
         })?;
-        Ok(Runtime { constant_pool, heap, globals, current_time })
+        let rodata = ConstantPool::new(&[]);
+        Ok(Runtime {
+            constant_pool,
+            rodata,
+            heap,
+            globals,
+            current_time: options.current_time,
+            break_handler: options.break_handler,
+            call_interceptor: options.call_interceptor,
+            poison_objects: options.poison_objects,
+            check_jump_targets: options.check_jump_targets,
+            trap_overflow: options.trap_overflow,
+            max_call_depth: options.max_call_depth,
+            frame_flags: if options.check_uninitialized_locals { FLAG_CHECK_LOCALS } else { 0 },
+            suspended: None,
+            stepping: None,
+            free_list: [0; FREE_LIST_SIZE],
+            free_count: 0,
+            weak_refs: AddressList::new(),
+            frozen: AddressList::new(),
+            tagged: AddressList::new(),
+            pinned: AddressList::new(),
+            line_tables: &[],
+            natives: &[],
+            dry_run_saved: None,
+            final_stack_depth: 0,
+        })
     }
 
+    /// Run the code block at `code_offset` with `args` as its first locals,
+    /// copying whatever it returns into `results`, and returning how many
+    /// values that was. If the heap fills up, garbage is collected: only
+    /// objects reachable from the globals or the running code survive, so
+    /// the host shouldn't count on keeping any others.
     pub fn execute(
         &mut self,
         code_offset: u32,
         args: &[usize],
         results: &mut [usize],
-        max_cycles: Option<core::num::NonZeroUsize>,
-        deadline: Option<core::num::NonZeroUsize>,
+        max_cycles: Option<NonZeroUsize>,
+        deadline: Option<NonZeroUsize>,
+    ) -> Result<usize, RuntimeError> {
+        self.run(code_offset, None, args, results, Limits { max_cycles, deadline, ..Limits::default() })
+    }
+
+    /// Same as `execute`, but finds the code block by its name in the
+    /// symbol table (`RuntimeOptions::symbol_table`), failing with
+    /// `UnknownSymbol` if it isn't there.
+    pub fn execute_named(
+        &mut self,
+        name: &str,
+        args: &[usize],
+        results: &mut [usize],
+        max_cycles: Option<NonZeroUsize>,
+        deadline: Option<NonZeroUsize>,
+    ) -> Result<usize, RuntimeError> {
+        let code_offset = self.constant_pool.find_symbol(name).ok_or_else(|| RuntimeError::new(ErrorCode::UnknownSymbol))?;
+        self.execute(code_offset, args, results, max_cycles, deadline)
+    }
+
+    /// Same as `execute`, but calls `trace` before each instruction runs,
+    /// with the instruction and the context it's about to run in (so the
+    /// frame's pc is still the instruction's). Instructions skipped by `If`
+    /// aren't traced.
+    pub fn execute_traced(
+        &mut self,
+        code_offset: u32,
+        args: &[usize],
+        results: &mut [usize],
+        max_cycles: Option<NonZeroUsize>,
+        deadline: Option<NonZeroUsize>,
+        trace: &mut dyn FnMut(&Instruction, &RuntimeContext),
+    ) -> Result<usize, RuntimeError> {
+        self.run(code_offset, None, args, results, Limits { max_cycles, deadline, trace: Some(trace), ..Limits::default() })
+    }
+
+    /// Same as `execute`, but returns only the values the code returned,
+    /// instead of a count into `results`.
+    pub fn execute_results<'a>(
+        &mut self,
+        code_offset: u32,
+        args: &[usize],
+        results: &'a mut [usize],
+        max_cycles: Option<NonZeroUsize>,
+        deadline: Option<NonZeroUsize>,
+    ) -> Result<Results<'a>, RuntimeError> {
+        let count = self.execute(code_offset, args, results, max_cycles, deadline)?;
+        Ok(Results::new(&results[0 .. count]))
+    }
+
+    /// Execute a code block that isn't in the constant pool, like a snippet
+    /// of bytecode under test. The code can't `Call` anything, because there
+    /// would be no way to return to it.
+    pub fn execute_code(
+        &mut self,
+        code: Code<'rom>,
+        args: &[usize],
+        results: &mut [usize],
+        max_cycles: Option<NonZeroUsize>,
+        deadline: Option<NonZeroUsize>,
+    ) -> Result<usize, RuntimeError> {
+        // the same limits `ConstantPool::get_code` puts on a code block's header
+        if code.local_count > 63 || code.max_stack > 63 { return Err(RuntimeError::new(ErrorCode::InvalidCodeObject)) }
+        self.run(DETACHED_CODE_OFFSET, Some(code), args, results, Limits { max_cycles, deadline, ..Limits::default() })
+    }
+
+    /// Simulate a code block against resource limits, to find out if it
+    /// would complete within them. Returns the number of values the code
+    /// block would have returned, or the error for the first limit it would
+    /// have hit (`CyclesExceeded`, `HeapExceeded`, or `CallDepthExceeded`).
+    /// Native functions aren't called: each `CallNativeN` acts as if its
+    /// native returned a single 0. Calls aren't intercepted either, since
+    /// there would be nothing to resume.
+    ///
+    /// Garbage is collected when the heap fills up, just like `execute`.
+    /// The heap budget only counts bytes the code block keeps alive, so
+    /// with `max_heap_bytes`, garbage is collected before starting, and
+    /// again before deciding the code block has gone over.
+    ///
+    /// Afterwards, the globals, the free list, and any suspended execution
+    /// are put back the way they were, and the objects it allocated are left
+    /// unreachable. Not everything can be undone, though: stores into
+    /// objects that existed before the dry run, and `Freeze` of those
+    /// objects, are kept.
+    pub fn dry_run(
+        &mut self,
+        code_offset: u32,
+        args: &[usize],
+        max_cycles: Option<NonZeroUsize>,
+        max_heap_bytes: Option<usize>,
+        max_call_depth: Option<usize>,
+    ) -> Result<usize, RuntimeError> {
+        if max_heap_bytes.is_some() { self.collect(None, &[]); }
+        let count = self.globals.len();
+        let globals = match self.heap.allocate_array::<usize>(count) {
+            Some(globals) => globals,
+            None => {
+                self.collect(None, &[]);
+                self.heap.allocate_array::<usize>(count).ok_or_else(|| RuntimeError::new(ErrorCode::OutOfMemory))?
+            },
+        };
+        globals.copy_from_slice(self.globals);
+        self.dry_run_saved = Some(DryRunSaved {
+            globals,
+            free_list: self.free_list,
+            free_count: self.free_count,
+            suspended: self.suspended.take(),
+        });
+
+        // a dry run only counts the values returned, so there's nowhere to put them.
+        let limits = Limits { max_cycles, max_heap_bytes, max_call_depth, dry: true, ..Limits::default() };
+        let rv = self.run(code_offset, None, args, &mut [], limits);
+        let saved = self.dry_run_saved.take().unwrap();
+        self.globals.copy_from_slice(saved.globals);
+        self.free_list = saved.free_list;
+        self.free_count = saved.free_count;
+        self.suspended = saved.suspended;
+        rv
+    }
+
+    /// If execution stopped with `CallIntercepted`, this is the code offset
+    /// of the function that was about to be called, and its args.
+    pub fn intercepted_call(&self) -> Option<(u32, &[usize])> {
+        let suspended = self.suspended.as_ref()?;
+        let context = RuntimeContext::resume(&self.heap, suspended.frame, suspended.code, suspended.depth).ok()?;
+        let (addr, count, _) = suspended.call?;
+        let stack = context.stack();
+        if count > stack.len() { return None }
+        // the suspended frame can't be freed while `self` is borrowed.
+        let args = unsafe { core::slice::from_raw_parts(stack[stack.len() - count ..].as_ptr(), count) };
+        Some((self.constant_pool.offset_from_addr(addr), args))
+    }
+
+    /// Continue an execution that stopped with `CallIntercepted`, by
+    /// entering the intercepted function as if nothing happened, or one that
+    /// stopped at a `Break` (with no break handler), at the next instruction.
+    /// While stopped at a `Break`, the frame's pc and sp are left as they
+    /// were, so the error still points at the `Break`, and the stack and
+    /// locals can be inspected before resuming.
+    pub fn resume(
+        &mut self,
+        results: &mut [usize],
+        max_cycles: Option<NonZeroUsize>,
+        deadline: Option<NonZeroUsize>,
+    ) -> Result<usize, RuntimeError> {
+        let (mut context, call) = self.take_suspended()?;
+        if let Some((addr, count, flags)) = call {
+            let mut next = context.push(&self.constant_pool, &mut self.heap, addr, count, flags);
+            if next.as_ref().err() == Some(&ErrorCode::OutOfMemory) {
+                self.collect(Some(&context), &[]);
+                next = context.push(&self.constant_pool, &mut self.heap, addr, count, flags);
+            }
+            context = next.map_err(|e| RuntimeError::from(e, &context))?;
+        }
+        self.run_context(context, results, Limits { max_cycles, deadline, ..Limits::default() })
+    }
+
+    /// Continue an execution that stopped with `CallIntercepted`, without
+    /// entering the intercepted function: its args are dropped, and `values`
+    /// are handed back to the caller as if the function had returned them.
+    pub fn resume_with(
+        &mut self,
+        values: &[usize],
+        results: &mut [usize],
+        max_cycles: Option<NonZeroUsize>,
+        deadline: Option<NonZeroUsize>,
     ) -> Result<usize, RuntimeError> {
-        let code_addr = self.constant_pool.addr_from_offset(code_offset);
+        // a `Break` has no call to fake.
+        if self.suspended.as_ref().is_none_or(|s| s.call.is_none()) {
+            return Err(RuntimeError::new(ErrorCode::NotSuspended));
+        }
+        let (mut context, call) = self.take_suspended()?;
+        let (_, count, flags) = call.unwrap();
+        check_results(flags, values.len()).map_err(|e| RuntimeError::from(e, &context))?;
+        context.get_n(count).map_err(|e| RuntimeError::from(e, &context))?;
+        for &v in values {
+            context.put(v).map_err(|e| RuntimeError::from(e, &context))?;
+        }
+        self.run_context(context, results, Limits { max_cycles, deadline, ..Limits::default() })
+    }
 
-        let mut context = RuntimeContext::start(&self.constant_pool, &mut self.heap, code_addr).map_err(|e| {
+    fn take_suspended(&mut self) -> Result<(RuntimeContext<'rom, 'heap>, Option<PendingCall>), RuntimeError> {
+        let suspended = self.suspended.take().ok_or_else(|| RuntimeError::new(ErrorCode::NotSuspended))?;
+        let context = RuntimeContext::resume(&self.heap, suspended.frame, suspended.code, suspended.depth).map_err(|e| {
             RuntimeError::new(e)
         })?;
+        context.frame.pc = suspended.pc;
+        Ok((context, suspended.call))
+    }
 
-        let mut skip = false;
-        let mut cycles = 0;
+    /// Set up a context for running a code block one instruction at a
+    /// time, with `step`. Only one can be stepped at a time: starting
+    /// another (or a `soft_reset`) lets this one's frames be collected, so
+    /// `step` (and `set_local`) refuses it after that. The context can only
+    /// be changed through the runtime, which checks it first.
+    pub fn start(&mut self, code_offset: u32, args: &[usize]) -> Result<RuntimeContext<'rom, 'heap>, RuntimeError> {
+        self.stepping = None;
+        let context = self.start_context(code_offset, None, args)?;
+        self.stepping = Some(Stepping::of(&context));
+        Ok(context)
+    }
 
+    // if `code` is missing, it's loaded from the constant pool at `code_offset`.
+    fn start_context(
+        &mut self,
+        code_offset: u32,
+        code: Option<Code<'rom>>,
+        args: &[usize],
+    ) -> Result<RuntimeContext<'rom, 'heap>, RuntimeError> {
+        let code = match code {
+            Some(code) => code,
+            None => self.constant_pool.get_code(self.constant_pool.addr_from_offset(code_offset)).map_err(|e| {
+                RuntimeError::new(e)
+            })?,
+        };
+        self.suspended = None;
+
+        let mut context = RuntimeContext::start(&mut self.heap, code, code_offset, self.frame_flags);
+        if context.as_ref().err() == Some(&ErrorCode::OutOfMemory) {
+            // nothing is running yet, but the args have to survive.
+            self.collect(None, args);
+            context = RuntimeContext::start(&mut self.heap, code, code_offset, self.frame_flags);
+        }
+        let mut context = context.map_err(RuntimeError::new)?;
         context.start_locals(args).map_err(|e| RuntimeError::from(e, &context))?;
+        Ok(context)
+    }
 
-        loop {
-            if context.frame.pc as usize == context.code.bytecode.len() {
-                // ran out of bytecodes? nothing to return.
-                return Ok(0);
-            }
+    fn run(
+        &mut self,
+        code_offset: u32,
+        code: Option<Code<'rom>>,
+        args: &[usize],
+        results: &mut [usize],
+        limits: Limits,
+    ) -> Result<usize, RuntimeError> {
+        let context = self.start_context(code_offset, code, args)?;
+        self.run_context(context, results, limits)
+    }
+
+    fn run_context(
+        &mut self,
+        mut context: RuntimeContext<'rom, 'heap>,
+        results: &mut [usize],
+        mut limits: Limits,
+    ) -> Result<usize, RuntimeError> {
+        let heap_free = self.heap.get_stats().free_bytes;
+        let mut cycles = 0;
 
-            // outatime?
-            if let (Some(d), Some(t)) = (deadline, self.current_time) {
-                if t() >= d.get() {
-                    return Err(RuntimeError::from(ErrorCode::TimeExceeded, &context));
+        loop {
+            if let StepResult::Returned(return_values) = self.step_within(&mut context, &mut limits, &mut cycles)? {
+                // the count returned is always how many were copied into
+                // `results`, except in a dry run, which doesn't copy them.
+                let count = return_values.len();
+                if limits.dry { return Ok(count) }
+                if count > results.len() {
+                    // the pc is still at the return (or past the end, if it ran off).
+                    return Err(RuntimeError::from(ErrorCode::ResultsOverflow, &context).at_opcode(&context));
                 }
+                results[0..count].copy_from_slice(return_values);
+                return Ok(count);
             }
-            if let Some(m) = max_cycles {
-                cycles += 1;
-                if cycles > m.get() {
-                    return Err(RuntimeError::from(ErrorCode::CyclesExceeded, &context));
+
+            if let Some(m) = limits.max_heap_bytes {
+                // only count what's still alive: some of it may be garbage.
+                if heap_free.saturating_sub(self.heap.get_stats().free_bytes) > m {
+                    self.collect(Some(&context), &[]);
+                    if heap_free.saturating_sub(self.heap.get_stats().free_bytes) > m {
+                        return Err(RuntimeError::from(ErrorCode::HeapExceeded, &context));
+                    }
                 }
             }
+        }
+    }
 
-            let (instruction, next_pc) =
-                decode_next(context.code.bytecode, context.frame.pc).map_err(|e| RuntimeError::from(e, &context))?;
-            if skip {
-                skip = false;
-                context.frame.pc = next_pc;
-                continue;
+    /// Run exactly one instruction in `context` (from `start`), with no
+    /// resource limits. A call or return moves `context` to the new frame.
+    /// An `If` that's false skips the instruction after it in the same step.
+    /// A context that isn't the one from the latest `start`, or that has
+    /// already returned, is `NotStepping`.
+    pub fn step(&mut self, context: &mut RuntimeContext<'rom, 'heap>) -> Result<StepResult<'_>, RuntimeError> {
+        self.check_stepping(context)?;
+        let rv = self.step_within(context, &mut Limits::default(), &mut 0);
+        self.stepping = match rv {
+            Ok(StepResult::Returned(_)) => None,
+            _ => Some(Stepping::of(context)),
+        };
+        rv
+    }
+
+    /// Between steps, overwrite local #`n` of the frame `context` (from
+    /// `start`) is in. A context that `step` would refuse is `NotStepping`,
+    /// since its frame may have been freed.
+    pub fn set_local(&mut self, context: &mut RuntimeContext<'rom, 'heap>, n: usize, value: usize) -> Result<(), RuntimeError> {
+        self.check_stepping(context)?;
+        context.put_local(n, value).map_err(|e| RuntimeError::from(e, context))
+    }
+
+    // is `context` the one being stepped, so its frame is still alive?
+    fn check_stepping(&self, context: &RuntimeContext<'rom, 'heap>) -> Result<(), RuntimeError> {
+        if self.stepping.as_ref().map(|s| s.frame) != Some(context.frame_addr()) {
+            return Err(RuntimeError::new(ErrorCode::NotStepping));
+        }
+        Ok(())
+    }
+
+    fn step_within(
+        &mut self,
+        context: &mut RuntimeContext<'rom, 'heap>,
+        limits: &mut Limits,
+        cycles: &mut usize,
+    ) -> Result<StepResult<'heap>, RuntimeError> {
+        if context.frame.pc as usize == context.code.bytecode.len() {
+            // ran out of bytecodes? nothing to return.
+            self.final_stack_depth = context.frame.sp as usize;
+            return Ok(StepResult::Returned(&[]));
+        }
+
+        // outatime? compare the distance to the deadline, so a timer
+        // that wraps around between now and then still works.
+        if let (Some(d), Some(t)) = (limits.deadline, self.current_time) {
+            if t().wrapping_sub(d.get()) as isize >= 0 {
+                return Err(RuntimeError::from(ErrorCode::TimeExceeded, context));
             }
+        }
+
+        let (instruction, next_pc) = decode_charged(context, limits, cycles)?;
+        if let Some(trace) = limits.trace.as_mut() { trace(&instruction, context); }
 
-            // println!("-> {} {:#?}", instruction, frame);
-
-            match self.execute_one(instruction, &mut context).map_err(|e| RuntimeError::from(e, &context))? {
-                Disposition::Continue => {
-                    context.frame.pc = next_pc;
-                },
-                Disposition::Skip => {
-                    context.frame.pc = next_pc;
-                    skip = true;
-                },
-                Disposition::Call(addr, count) => {
-                    context.frame.pc = next_pc;
-                    context = context.push(&self.constant_pool, &mut self.heap, addr, count).map_err(|e| {
-                        RuntimeError::from(e, &context)
-                    })?;
-                },
-                Disposition::Return(count) => {
-                    match context.pop(&self.constant_pool, &self.heap, count).map_err(|e| {
-                        RuntimeError::from(e, &context)
-                    })? {
-                        PreviousContext::Done(return_values) => {
-                            let n: usize = core::cmp::min(results.len(), return_values.len());
-                            results[0..n].copy_from_slice(&return_values[0..n]);
-                            return Ok(count);
-                        },
-                        PreviousContext::Frame(prev) => {
-                            context = prev;
-                        },
+        match self.execute_one(instruction, context, limits.dry).map_err(|e| {
+            // the pc hasn't moved yet, so it still points at the opcode that failed.
+            let error = RuntimeError::from(e, context).at_opcode(context);
+            if error.code == ErrorCode::Break {
+                // stay at the break, so the error points at it, but `resume` will continue after.
+                self.suspended = Some(Suspended {
+                    frame: context.frame as *const StackFrame as usize,
+                    code: context.code,
+                    depth: context.depth,
+                    pc: next_pc,
+                    call: None,
+                });
+            }
+            error
+        })? {
+            Disposition::Continue => {
+                context.frame.pc = next_pc;
+            },
+            Disposition::Throw(code) => {
+                let mut error = RuntimeError::from(ErrorCode::Thrown, context);
+                error.thrown = Some(code);
+                return Err(error);
+            },
+            Disposition::Skip => {
+                context.frame.pc = next_pc;
+                if (next_pc as usize) < context.code.bytecode.len() {
+                    // skipped instructions are charged too, to keep the cost model simple.
+                    let (_, after) = decode_charged(context, limits, cycles)?;
+                    context.frame.pc = after;
+                }
+            },
+            Disposition::Call(addr, count, flags) => {
+                if context.frame.code_offset == DETACHED_CODE_OFFSET {
+                    return Err(RuntimeError::from(ErrorCode::InvalidCodeObject, context).with_opcode(instruction.opcode));
+                }
+                // a `dry_run` limit overrides the runtime's own.
+                if let Some(m) = limits.max_call_depth.or(self.max_call_depth) {
+                    if context.depth >= m {
+                        return Err(RuntimeError::from(ErrorCode::CallDepthExceeded, context).with_opcode(instruction.opcode));
                     }
-                },
-                Disposition::Jump(new_pc) => {
-                    if new_pc as usize >= context.code.bytecode.len() {
-                        return Err(RuntimeError::from(ErrorCode::OutOfBounds, &context));
+                }
+                context.frame.pc = next_pc;
+                if let Some(intercept) = self.call_interceptor.filter(|_| !limits.dry) {
+                    // invalid calls will fail in `push` as usual.
+                    if self.constant_pool.get_code(addr).is_ok() && intercept(self.constant_pool.offset_from_addr(addr)) {
+                        self.suspended = Some(Suspended {
+                            frame: context.frame as *const StackFrame as usize,
+                            code: context.code,
+                            depth: context.depth,
+                            pc: next_pc,
+                            call: Some((addr, count, flags)),
+                        });
+                        return Err(RuntimeError::from(ErrorCode::CallIntercepted, context).with_opcode(instruction.opcode));
                     }
-                    context.frame.pc = new_pc;
                 }
+                let mut next = context.push(&self.constant_pool, &mut self.heap, addr, count, flags);
+                if next.as_ref().err() == Some(&ErrorCode::OutOfMemory) {
+                    // `push` leaves the args alone if it can't allocate, so they're still on the stack.
+                    self.collect(Some(context), &[]);
+                    next = context.push(&self.constant_pool, &mut self.heap, addr, count, flags);
+                }
+                *context = next.map_err(|e| RuntimeError::from(e, context).with_opcode(instruction.opcode))?;
+            },
+            Disposition::Return(count) => {
+                match context.pop(&self.constant_pool, &self.heap, count).map_err(|e| {
+                    RuntimeError::from(e, context).with_opcode(instruction.opcode)
+                })? {
+                    PreviousContext::Done(return_values) => {
+                        self.final_stack_depth = context.frame.sp as usize;
+                        return Ok(StepResult::Returned(return_values));
+                    },
+                    PreviousContext::Frame(prev) => {
+                        *context = prev;
+                    },
+                }
+            },
+            Disposition::Jump(new_pc) => {
+                if new_pc as usize >= context.code.bytecode.len() {
+                    return Err(RuntimeError::from(ErrorCode::OutOfBounds, context).with_opcode(instruction.opcode));
+                }
+                context.frame.pc = new_pc;
             }
         }
+        Ok(StepResult::Ran(instruction))
     }
 
     fn execute_one(
         &mut self,
         instruction: Instruction,
         context: &mut RuntimeContext<'rom, 'heap>,
+        dry: bool,
     ) -> Result<Disposition, ErrorCode> {
         match instruction.opcode {
             // zero immediates:
 
             Opcode::Break => {
-                return Err(ErrorCode::Break);
+                let handler = self.break_handler.ok_or(ErrorCode::Break)?;
+                match handler(context.frame.code_offset, context.frame.pc) {
+                    BreakAction::Continue => (),
+                    BreakAction::Return => return Ok(Disposition::Return(0)),
+                    BreakAction::Error => return Err(ErrorCode::Break),
+                }
             },
             Opcode::Nop => {
                 // nothing
@@ -156,10 +764,31 @@ impl<'rom, 'heap> Runtime<'rom, 'heap> {
             Opcode::Drop => {
                 context.get()?;
             },
+            Opcode::Swap => {
+                let v2 = context.get()?;
+                let v1 = context.get()?;
+                context.put(v2)?;
+                context.put(v1)?;
+            },
+            Opcode::Over => {
+                let v2 = context.get()?;
+                let v1 = context.get()?;
+                context.put(v1)?;
+                context.put(v2)?;
+                context.put(v1)?;
+            },
+            Opcode::Rot => {
+                let v3 = context.get()?;
+                let v2 = context.get()?;
+                let v1 = context.get()?;
+                context.put(v2)?;
+                context.put(v3)?;
+                context.put(v1)?;
+            },
             Opcode::Call => {
                 let count = context.get()?;
                 let addr = context.get()?;
-                return Ok(Disposition::Call(addr, count));
+                return Ok(Disposition::Call(addr, count, 0));
             },
             Opcode::Return => {
                 let count = context.get()?;
@@ -195,6 +824,9 @@ impl<'rom, 'heap> Runtime<'rom, 'heap> {
                 context.put(instruction.n1 as usize)?;
             },
             Opcode::Constant => {
+                // catch bad offsets here, instead of whenever the address is used
+                let words = self.constant_pool.data.len().div_ceil(4);
+                if instruction.n1 < 0 || instruction.n1 as usize >= words { return Err(ErrorCode::InvalidAddress) }
                 context.put(self.constant_pool.addr_from_offset(instruction.n1 as u32))?;
             },
             Opcode::LoadSlotN => {
@@ -206,16 +838,26 @@ impl<'rom, 'heap> Runtime<'rom, 'heap> {
                 self.store_slot(context.get()?, instruction.n1 as usize, v)?;
             },
             Opcode::LoadLocalN => {
-                let locals = context.locals();
                 let n = instruction.n1 as usize;
-                if n >= locals.len() { return Err(ErrorCode::OutOfBounds) }
-                context.put(locals[n])?;
+                if n >= context.locals().len() { return Err(ErrorCode::OutOfBounds) }
+                context.check_local(n)?;
+                let v = context.locals()[n];
+                context.put(v)?;
             },
             Opcode::StoreLocalN => {
-                let locals = context.locals_mut();
                 let n = instruction.n1 as usize;
-                if n >= locals.len() { return Err(ErrorCode::OutOfBounds) }
-                locals[n] = context.get()?;
+                if n >= context.locals().len() { return Err(ErrorCode::OutOfBounds) }
+                let v = context.get()?;
+                context.locals_mut()[n] = v;
+                context.mark_local_written(n);
+            },
+            Opcode::CopyLocal => {
+                let (src, dst) = (instruction.n1 as usize, instruction.n2 as usize);
+                if src >= context.locals().len() || dst >= context.locals().len() { return Err(ErrorCode::OutOfBounds) }
+                context.check_local(src)?;
+                let locals = context.locals_mut();
+                locals[dst] = locals[src];
+                context.mark_local_written(dst);
             },
             Opcode::LoadGlobalN => {
                 let n = instruction.n1 as usize;
@@ -240,13 +882,26 @@ impl<'rom, 'heap> Runtime<'rom, 'heap> {
             },
             Opcode::CallN => {
                 let addr = context.get()?;
-                return Ok(Disposition::Call(addr, instruction.n1 as usize));
+                return Ok(Disposition::Call(addr, instruction.n1 as usize, 0));
             },
             Opcode::ReturnN => {
                 return Ok(Disposition::Return(instruction.n1 as usize));
             },
             Opcode::Jump => {
-                return Ok(Disposition::Jump(instruction.n1 as u16));
+                return Ok(Disposition::Jump(jump_target(instruction.n1)?));
+            },
+            Opcode::JumpIfPointer => {
+                if self.is_pointer(context.get()?) { return Ok(Disposition::Jump(jump_target(instruction.n1)?)); }
+            },
+            Opcode::StackAddrN => {
+                let sp = context.frame.sp as isize;
+                if instruction.n1 < 0 || instruction.n1 >= sp { return Err(ErrorCode::StackUnderflow) }
+                let addr = &context.stack()[(sp - 1 - instruction.n1) as usize] as *const usize as usize;
+                context.put(addr)?;
+            },
+            Opcode::StackRoom => {
+                let room = (context.frame.sp as isize) + instruction.n1 <= (context.code.max_stack as isize);
+                context.put(if room { 1 } else { 0 })?;
             },
 
             // two immediates:
@@ -255,6 +910,216 @@ impl<'rom, 'heap> Runtime<'rom, 'heap> {
                 let obj = self.new_object(instruction.n1 as usize, instruction.n2 as usize, context)?;
                 context.put(obj)?;
             },
+            Opcode::LoadField => {
+                let v = context.get()?;
+                context.put(load_field(v, instruction.n1, instruction.n2)?)?;
+            },
+            Opcode::SnapshotGlobals => {
+                let (start, count) = self.global_range(instruction.n1, instruction.n2)?;
+                let obj = self.new_object(count, 0, context)?;
+                for i in 0 .. count { self.store_slot(obj, i, self.globals[start + i])?; }
+                context.put(obj)?;
+            },
+            Opcode::RestoreGlobals => {
+                let (start, count) = self.global_range(instruction.n1, instruction.n2)?;
+                let obj = context.get()?;
+                // check the whole object before touching any globals
+                if count > self.object_size(obj)? { return Err(ErrorCode::OutOfBounds) }
+                for i in 0 .. count { self.load_slot(obj, i)?; }
+                for i in 0 .. count { self.globals[start + i] = self.load_slot(obj, i)?; }
+            },
+            Opcode::CallNN => {
+                let addr = context.get()?;
+                if instruction.n2 < 0 { return Err(ErrorCode::OutOfBounds) }
+                let flags = expect_results(instruction.n2 as usize)?;
+                return Ok(Disposition::Call(addr, instruction.n1 as usize, flags));
+            },
+
+            // one immediate (extended):
+
+            Opcode::NewTaggedN => {
+                if instruction.n1 < 0 || (instruction.n1 as usize) > MAX_TAG { return Err(ErrorCode::OutOfBounds) }
+                let fill = context.get()?;
+                let slots = context.get()?;
+                let obj = self.new_tagged_object(slots, fill, instruction.n1 as usize, context)?;
+                context.put(obj)?;
+            },
+            Opcode::NewAlignedN => {
+                let fill = context.get()?;
+                let slots = context.get()?;
+                let obj = self.new_aligned_object(slots, fill, instruction.n1, context)?;
+                context.put(obj)?;
+            },
+            Opcode::DecTestLocalN => {
+                let n = instruction.n1 as usize;
+                if n >= context.locals().len() { return Err(ErrorCode::OutOfBounds) }
+                context.check_local(n)?;
+                let v = context.locals()[n].saturating_sub(1);
+                context.locals_mut()[n] = v;
+                context.put(if v == 0 { 1 } else { 0 })?;
+            },
+            Opcode::JumpIfZero => {
+                if context.get()? == 0 { return Ok(Disposition::Jump(jump_target(instruction.n1)?)); }
+            },
+            Opcode::JumpIfNonZero => {
+                if context.get()? != 0 { return Ok(Disposition::Jump(jump_target(instruction.n1)?)); }
+            },
+            Opcode::JumpRel => {
+                return Ok(Disposition::Jump(jump_target(instruction.offset as isize + instruction.n1)?));
+            },
+            Opcode::IncLocalN => {
+                let n = instruction.n1 as usize;
+                if n >= context.locals().len() { return Err(ErrorCode::OutOfBounds) }
+                context.check_local(n)?;
+                context.locals_mut()[n] = self.overflow((context.locals()[n] as isize).overflowing_add(1))? as usize;
+            },
+            Opcode::DecLocalN => {
+                let n = instruction.n1 as usize;
+                if n >= context.locals().len() { return Err(ErrorCode::OutOfBounds) }
+                context.check_local(n)?;
+                context.locals_mut()[n] = self.overflow((context.locals()[n] as isize).overflowing_sub(1))? as usize;
+            },
+            Opcode::CallNativeN => {
+                if instruction.n1 < 0 || instruction.n1 as usize >= self.natives.len() {
+                    return Err(ErrorCode::InvalidNativeIndex);
+                }
+                let count = context.get()?;
+                context.get_n(count)?;
+                if dry {
+                    // a dry run never reaches the host: the native just returns a 0.
+                    context.put(0)?;
+                } else {
+                    let start = context.frame.sp as usize;
+                    // results go in the free stack above the args, then move down over them.
+                    let (args, room) = context.stack_mut()[start ..].split_at_mut(count);
+                    let n = self.natives[instruction.n1 as usize](args, room)?;
+                    if n > room.len() { return Err(ErrorCode::StackOverflow) }
+                    context.stack_mut().copy_within(start + count .. start + count + n, start);
+                    context.frame.sp += n as u8;
+                }
+            },
+            Opcode::PickN => {
+                let sp = context.frame.sp as isize;
+                if instruction.n1 < 0 || instruction.n1 >= sp { return Err(ErrorCode::StackUnderflow) }
+                let v = context.stack()[(sp - 1 - instruction.n1) as usize];
+                context.put(v)?;
+            },
+            Opcode::IncGlobalN => {
+                let n = instruction.n1 as usize;
+                if n >= self.globals.len() { return Err(ErrorCode::OutOfBounds) }
+                self.globals[n] = self.overflow((self.globals[n] as isize).overflowing_add(1))? as usize;
+            },
+            Opcode::ConstantBytes => {
+                let words = self.constant_pool.data.len().div_ceil(4);
+                if instruction.n1 < 0 || instruction.n1 as usize >= words { return Err(ErrorCode::InvalidAddress) }
+                let bytes = self.constant_pool.get_bytes(instruction.n1 as u32).ok_or(ErrorCode::InvalidAddress)?;
+                context.put(bytes.as_ptr() as usize)?;
+                context.put(bytes.len())?;
+            },
+            Opcode::AddGlobalN => {
+                let n = instruction.n1 as usize;
+                if n >= self.globals.len() { return Err(ErrorCode::OutOfBounds) }
+                let v = context.get()?;
+                self.globals[n] = self.overflow((self.globals[n] as isize).overflowing_add(v as isize))? as usize;
+            },
+
+            // zero immediates (extended):
+
+            Opcode::FrameInfo => {
+                context.put(context.code.local_count as usize)?;
+                context.put(context.code.max_stack as usize)?;
+            },
+            Opcode::ExchangeSlot => {
+                let v = context.get()?;
+                let slot = context.get()?;
+                let addr = context.get()?;
+                let old = self.load_slot(addr, slot)?;
+                self.store_slot(addr, slot, v)?;
+                context.put(old)?;
+            },
+            Opcode::TypeOf => {
+                let addr = context.get()?;
+                context.put(self.object_tag(addr)?)?;
+            },
+            Opcode::FreeObject => {
+                let addr = context.get()?;
+                // the host is still holding onto a pinned object.
+                if self.is_pinned(addr) { return Err(ErrorCode::InvalidAddress) }
+                self.free_object(addr)?;
+            },
+            Opcode::SlotDiff => {
+                let b = context.get()?;
+                let a = context.get()?;
+                let word = mem::size_of::<usize>();
+                if a % word != 0 || b % word != 0 { return Err(ErrorCode::Unaligned) }
+                // no pointer arithmetic across objects
+                let start = self.allocation_start(a).ok_or(ErrorCode::InvalidAddress)?;
+                if self.allocation_start(b) != Some(start) { return Err(ErrorCode::InvalidAddress) }
+                context.put(((a as isize - b as isize) / word as isize) as usize)?;
+            },
+            Opcode::JumpDynamic => {
+                let target = context.get()?;
+                if target > u16::MAX as usize { return Err(ErrorCode::OutOfBounds) }
+                let target = target as u16;
+                if self.check_jump_targets && !is_instruction_start(context.code.bytecode, target) {
+                    return Err(ErrorCode::OutOfBounds);
+                }
+                return Ok(Disposition::Jump(target));
+            },
+            Opcode::LoadIndexed => {
+                let index = context.get()? as isize;
+                let addr = context.get()?;
+                let v = self.load_slot(addr, self.checked_index(addr, index)?)?;
+                context.put(v)?;
+            },
+            Opcode::StoreIndexed => {
+                let v = context.get()?;
+                let index = context.get()? as isize;
+                let addr = context.get()?;
+                self.store_slot(addr, self.checked_index(addr, index)?, v)?;
+            },
+            Opcode::Freeze => {
+                let addr = context.get()?;
+                self.freeze(addr)?;
+            },
+            Opcode::CallDepth => {
+                context.put(context.depth)?;
+            },
+            Opcode::NewWeak => {
+                // leave the target on the stack until it's made, so a collection keeps it.
+                let target = *context.stack().last().ok_or(ErrorCode::StackUnderflow)?;
+                let weak = self.allocate_or_collect(context, |r| r.make_weak_ref(target))?;
+                context.get()?;
+                context.put(weak)?;
+            },
+            Opcode::NewFromStack => {
+                let count = context.get()?;
+                // check before allocating, so a bad count doesn't leave garbage behind.
+                if count > 64 { return Err(ErrorCode::InvalidSize) }
+                if count > context.frame.sp as usize { return Err(ErrorCode::StackUnderflow) }
+                let obj = self.new_object(count, count, context)?;
+                context.put(obj)?;
+            },
+            Opcode::Throw => {
+                let code = context.get()?;
+                if code > u16::MAX as usize { return Err(ErrorCode::OutOfBounds) }
+                return Ok(Disposition::Throw(code as u16));
+            },
+            Opcode::CaptureStack => {
+                let count = context.stack().len();
+                let addr = self.allocate_or_collect(context, |r| r.alloc_slots(count))?;
+                let obj = unsafe { core::slice::from_raw_parts_mut(addr as *mut usize, count) };
+                obj.copy_from_slice(context.stack());
+                context.put(addr)?;
+            },
+            Opcode::Select => {
+                let condition = context.get()?;
+                let if_false = context.get()?;
+                let if_true = context.get()?;
+                // all ones if the condition is true, all zeros if not
+                let mask = 0usize.wrapping_sub((condition != 0) as usize);
+                context.put((if_true & mask) | (if_false & !mask))?;
+            },
 
             _ => {
                 return Err(ErrorCode::UnknownOpcode);
@@ -264,18 +1129,341 @@ impl<'rom, 'heap> Runtime<'rom, 'heap> {
         Ok(Disposition::Continue)
     }
 
+    /// Make a block of memory outside the heap readable (but not writable)
+    /// as objects, the same way constants are. It's `OverlappingRegions` if
+    /// it shares memory with the constant pool or the heap.
+    pub fn set_rodata(&mut self, rodata: &'rom [usize]) -> Result<(), ErrorCode> {
+        let start = rodata.as_ptr() as usize;
+        let end = start + mem::size_of_val(rodata);
+        let pool_start = self.constant_pool.data.as_ptr() as usize;
+        let globals = self.globals.as_ptr() as usize;
+        // the heap's extent isn't visible, but it holds the globals.
+        if (start < pool_start + self.constant_pool.data.len() && pool_start < end) ||
+            (start <= globals && globals < end) ||
+            rodata.first().is_some_and(|first| self.heap.is_ptr_inside(first as *const usize)) ||
+            rodata.last().is_some_and(|last| self.heap.is_ptr_inside(last as *const usize))
+        {
+            return Err(ErrorCode::OverlappingRegions);
+        }
+        self.rodata = ConstantPool::new(unsafe { core::slice::from_raw_parts(start as *const u8, end - start) });
+        Ok(())
+    }
+
+    /// Attach source line tables to code blocks, for profilers and
+    /// debuggers. Each table is a list of (pc, line) sorted by pc, where
+    /// each line covers the bytecode up to the next entry.
+    pub fn set_line_tables(&mut self, line_tables: &'rom [(u32, &'rom [(u16, u32)])]) {
+        self.line_tables = line_tables;
+    }
+
+    /// Give `CallNativeN` a table of host functions to call, by index.
+    pub fn set_natives(&mut self, natives: &'rom [NativeFunction]) {
+        self.natives = natives;
+    }
+
+    /// The source line for a pc in a code block, if it has a line table.
+    pub fn source_line(&self, code_offset: u32, pc: u16) -> Option<u32> {
+        let (_, table) = self.line_tables.iter().find(|(offset, _)| *offset == code_offset)?;
+        table.iter().take_while(|(start, _)| *start <= pc).last().map(|(_, line)| *line)
+    }
+
+    /// How many values were left on the stack of the starting frame when
+    /// the last execution finished. Anything other than 0 usually means the
+    /// code generator lost track of the stack.
+    pub fn final_stack_depth(&self) -> usize {
+        self.final_stack_depth
+    }
+
+    /// How many bytes are free in the heap right now.
+    pub fn heap_free_bytes(&self) -> usize {
+        self.heap.get_stats().free_bytes
+    }
+
+    /// Free everything on the heap except the globals and pinned objects,
+    /// and any objects they refer to. Anything else the host is holding
+    /// onto (including a suspended execution, or a context from `start`)
+    /// is gone. Weak references that survive, but whose target doesn't,
+    /// are cleared to 0.
+    pub fn soft_reset(&mut self) {
+        self.suspended = None;
+        self.stepping = None;
+        self.collect(None, &[]);
+    }
+
+    // free everything that can't be reached from the globals, a pinned
+    // object, a suspended execution, a context from `start`, what a
+    // `dry_run` will put back, `roots`, or `context` (and the frames that
+    // called it).
+    fn collect(&mut self, context: Option<&RuntimeContext<'rom, 'heap>>, roots: &[usize]) {
+        // the free list isn't marked, so it would be swept out from under us.
+        self.free_count = 0;
+        self.heap.mark_start();
+        let globals: &'heap [usize] = unsafe { core::slice::from_raw_parts(self.globals.as_ptr(), self.globals.len()) };
+        self.heap.mark(globals.as_ptr());
+        for &v in globals { self.mark_from(v); }
+        for &v in roots { self.mark_from(v); }
+        self.pinned.mark(&mut self.heap);
+        let pinned = mem::replace(&mut self.pinned, AddressList::new());
+        for &start in pinned.as_slice() { self.mark_from(start); }
+        self.pinned = pinned;
+        if let Some(context) = context { self.mark_frames(context); }
+        let suspended = self.suspended.as_ref().and_then(|s| RuntimeContext::resume(&self.heap, s.frame, s.code, s.depth).ok());
+        if let Some(context) = suspended { self.mark_frames(&context); }
+        let stepping = self.stepping.as_ref().and_then(|s| RuntimeContext::resume(&self.heap, s.frame, s.code, s.depth).ok());
+        if let Some(context) = stepping { self.mark_frames(&context); }
+        let saved_globals = self.dry_run_saved.as_mut().map(|saved| {
+            // the saved free list would be swept out from under us too.
+            saved.free_count = 0;
+            unsafe { core::slice::from_raw_parts(saved.globals.as_ptr(), saved.globals.len()) }
+        });
+        if let Some(globals) = saved_globals {
+            self.heap.mark(globals.as_ptr());
+            for &v in globals { self.mark_from(v); }
+        }
+        let saved_suspended = self.dry_run_saved.as_ref().and_then(|saved| saved.suspended.as_ref()).and_then(|s| {
+            RuntimeContext::resume(&self.heap, s.frame, s.code, s.depth).ok()
+        });
+        if let Some(context) = saved_suspended { self.mark_frames(&context); }
+        self.weak_refs.mark(&mut self.heap);
+        self.frozen.mark(&mut self.heap);
+        self.tagged.mark(&mut self.heap);
+        self.clear_weak_refs();
+        self.forget_frozen();
+        self.forget_tagged();
+        self.heap.sweep();
+    }
+
+    // try an allocation, and if the heap is full, collect garbage (keeping
+    // anything `context` can reach) and try once more.
+    fn allocate_or_collect<T>(
+        &mut self,
+        context: &RuntimeContext<'rom, 'heap>,
+        allocate: impl Fn(&mut Self) -> Result<T, ErrorCode>,
+    ) -> Result<T, ErrorCode> {
+        match allocate(self) {
+            Err(ErrorCode::OutOfMemory) => {
+                self.collect(Some(context), &[]);
+                allocate(self)
+            },
+            rv => rv,
+        }
+    }
+
+    // mark a stack frame and each one that called it, and everything their
+    // live values refer to. a frame is marked without tracing all of it,
+    // so values that were popped off the stack don't keep anything alive.
+    fn mark_frames(&mut self, context: &RuntimeContext<'rom, 'heap>) {
+        self.heap.mark(context.frame as *const StackFrame);
+        for &v in context.live_values() { self.mark_from(v); }
+        if let Some(caller) = context.caller(&self.constant_pool, &self.heap) { self.mark_frames(&caller); }
+    }
+
+    // mark the allocation holding `addr`, if it's on the heap, and
+    // everything it refers to (except through weak references).
+    fn mark_from(&mut self, addr: usize) {
+        let start = match self.allocation_start(addr) {
+            Some(start) => start,
+            None => return,
+        };
+        if !self.heap.mark(start as *const usize) { return }
+        // the runtime's own lists are marked, but what's in them isn't a reference.
+        if self.weak_refs.contains(start) || self.is_internal(start) { return }
+        let words = self.heap.size_of_ptr(start as *const usize) / mem::size_of::<usize>();
+        let obj = unsafe { core::slice::from_raw_parts(start as *const usize, words) };
+        for &v in obj { self.mark_from(v); }
+    }
+
+    // after marking: forget weak references that weren't marked, and clear
+    // the ones whose target wasn't. there's no way to ask the heap if
+    // something is marked without marking it, so a cleared target lives
+    // until the next collection, but nothing can reach it. that also means
+    // only the first weak reference to a dead target sees it as unmarked, so
+    // the rest that point into it are cleared along with that one.
+    fn clear_weak_refs(&mut self) {
+        let mut weak_refs = mem::replace(&mut self.weak_refs, AddressList::new());
+        weak_refs.retain(|weak| !self.heap.mark(weak as *const usize));
+        let weak = weak_refs.as_slice();
+        for i in 0 .. weak.len() {
+            let start = match self.allocation_start(unsafe { *(weak[i] as *const usize) }) {
+                Some(start) => start,
+                None => continue,
+            };
+            if !self.heap.mark(start as *const usize) { continue }
+            for &other in &weak[i ..] {
+                let slot = unsafe { &mut *(other as *mut usize) };
+                if self.allocation_start(*slot) == Some(start) { *slot = 0; }
+            }
+        }
+        self.weak_refs = weak_refs;
+    }
+
+    // after marking: forget frozen objects that weren't marked, so their
+    // memory can be reused for something writable. (like weak references,
+    // they'll actually be freed on the next collection.)
+    fn forget_frozen(&mut self) {
+        let mut frozen = mem::replace(&mut self.frozen, AddressList::new());
+        frozen.retain(|start| !self.heap.mark(start as *const usize));
+        self.frozen = frozen;
+    }
+
+    // after marking: forget tagged objects that weren't marked. (like
+    // frozen objects, they're freed next time.)
+    fn forget_tagged(&mut self) {
+        let mut tagged = mem::replace(&mut self.tagged, AddressList::new());
+        tagged.retain(|addr| self.allocation_start(addr).is_some_and(|start| !self.heap.mark(start as *const usize)));
+        self.tagged = tagged;
+    }
+
+    /// Make the heap object at `addr` read-only: storing into any of its
+    /// slots fails with `Immutable` from now on. There's no way to undo it.
+    /// Frozen objects are remembered in a list in the heap, so this can
+    /// fail with `OutOfMemory` if the heap is full.
+    pub fn freeze(&mut self, addr: usize) -> Result<(), ErrorCode> {
+        if self.object_header(addr).is_none() && self.heap.size_of_ptr(addr as *const usize) == 0 {
+            return Err(ErrorCode::InvalidAddress);
+        }
+        let start = self.allocation_start(addr).ok_or(ErrorCode::InvalidAddress)?;
+        if self.is_internal(start) { return Err(ErrorCode::InvalidAddress) }
+        self.frozen.insert(&mut self.heap, start)
+    }
+
+    fn is_frozen(&self, addr: usize) -> bool {
+        !self.frozen.as_slice().is_empty() && self.allocation_start(addr).is_some_and(|start| self.frozen.contains(start))
+    }
+
+    /// Make a weak reference: a 1-slot object holding `target`, which
+    /// doesn't keep `target` alive. When `soft_reset` collects the target,
+    /// the slot is cleared to 0. Weak references are remembered in a list
+    /// in the heap, so there's no limit on how many there can be. Like
+    /// `alloc_object`, the weak reference itself starts out pinned.
+    pub fn new_weak_ref(&mut self, target: usize) -> Result<usize, ErrorCode> {
+        self.pinned.reserve(&mut self.heap)?;
+        let addr = self.make_weak_ref(target)?;
+        self.pinned.insert(&mut self.heap, addr)?;
+        Ok(addr)
+    }
+
+    fn make_weak_ref(&mut self, target: usize) -> Result<usize, ErrorCode> {
+        self.weak_refs.reserve(&mut self.heap)?;
+        let addr = self.alloc_slots(1)?;
+        unsafe { *(addr as *mut usize) = target; }
+        self.weak_refs.insert(&mut self.heap, addr)?;
+        Ok(addr)
+    }
+
+    /// Keep an object alive through garbage collection (and `soft_reset`)
+    /// while the host holds onto it, until it's `unpin`ned. Only the
+    /// globals and running code keep anything else alive, so an object the
+    /// host got from code (or allocated in the heap before `with_heap`)
+    /// has to be pinned to survive the next run that fills the heap. Pins
+    /// are remembered in a list in the heap, so this can be `OutOfMemory`.
+    pub fn pin(&mut self, addr: usize) -> Result<(), ErrorCode> {
+        let start = self.allocation_start(addr).ok_or(ErrorCode::InvalidAddress)?;
+        if self.is_internal(start) { return Err(ErrorCode::InvalidAddress) }
+        self.pinned.insert(&mut self.heap, start)
+    }
+
+    /// Let the collector free a pinned object once nothing refers to it.
+    pub fn unpin(&mut self, addr: usize) {
+        if let Some(start) = self.allocation_start(addr) { self.pinned.retain(|pinned| pinned != start); }
+    }
+
+    fn is_pinned(&self, addr: usize) -> bool {
+        !self.pinned.as_slice().is_empty() && self.allocation_start(addr).is_some_and(|start| self.pinned.contains(start))
+    }
+
+    /// Does this value look like a reference to an object in the constant pool or heap?
+    pub fn is_pointer(&self, addr: usize) -> bool {
+        let ptr = addr as *const usize;
+        addr % mem::size_of::<usize>() == 0 &&
+            (self.constant_pool.safe_ref(ptr).is_some() || self.rodata.safe_ref(ptr).is_some() ||
+                self.heap.is_ptr_inside(ptr))
+    }
+
     pub fn object_size(
         &self,
         addr: usize,
     ) -> Result<usize, ErrorCode> {
-        if self.heap.is_ptr_inside(addr as *const usize) {
+        if let Some(header) = self.object_header(addr) {
+            Ok(header & HEADER_SLOTS_MASK)
+        } else if self.heap.is_ptr_inside(addr as *const usize) {
             Ok(self.heap.size_of_ptr(addr as *const usize) / mem::size_of::<usize>())
+        } else if addr % mem::size_of::<usize>() == 0 && self.rodata.safe_ref(addr as *const usize).is_some() {
+            // rodata has no object boundaries, so it's all the words to the end of it
+            let end = self.rodata.data.as_ptr() as usize + self.rodata.data.len();
+            Ok((end - addr) / mem::size_of::<usize>())
+        } else {
+            // only valid for heap and rodata addresses
+            Err(ErrorCode::InvalidAddress)
+        }
+    }
+
+    // `index` as a slot of the heap object at `addr`, if it's inside the object
+    fn checked_index(&self, addr: usize, index: isize) -> Result<usize, ErrorCode> {
+        if index < 0 || index as usize >= self.object_size(addr)? { return Err(ErrorCode::OutOfBounds) }
+        Ok(index as usize)
+    }
+
+    /// The tag an object was created with (by `NewTaggedN`), or 0 if it
+    /// wasn't created with one.
+    pub fn object_tag(
+        &self,
+        addr: usize,
+    ) -> Result<usize, ErrorCode> {
+        if let Some(header) = self.object_header(addr) {
+            Ok(header >> HEADER_TAG_SHIFT)
+        } else if self.heap.is_ptr_inside(addr as *const usize) {
+            Ok(0)
         } else {
-            // only valid for heap addresses
             Err(ErrorCode::InvalidAddress)
         }
     }
 
+    // is `addr` inside one of the runtime's own lists in the heap? code can't
+    // be allowed to touch those, since the collector trusts what's in them.
+    fn is_internal(&self, addr: usize) -> bool {
+        self.weak_refs.is_inside(addr) || self.frozen.is_inside(addr) || self.tagged.is_inside(addr) ||
+            self.pinned.is_inside(addr)
+    }
+
+    // find the start of the heap allocation (object or stack frame) that
+    // contains this address, by walking back to the nearest one.
+    fn allocation_start(&self, addr: usize) -> Option<usize> {
+        let word = mem::size_of::<usize>();
+        if !self.heap.is_ptr_inside(addr as *const usize) { return None }
+        let mut start = addr - addr % word;
+        for _ in 0 .. MAX_ALLOCATION_WORDS {
+            let size = self.heap.size_of_ptr(start as *const usize);
+            if size > 0 { return if addr < start + size { Some(start) } else { None } }
+            if !self.heap.is_ptr_inside((start - word) as *const usize) { return None }
+            start -= word;
+        }
+        None
+    }
+
+    // tagged objects start one word into their allocation, with the header
+    // word before them. aligned objects are the same, but may have padding
+    // before the header. plain objects start at the beginning of theirs.
+    // only objects recorded when they were made have a header, and it can't
+    // claim more slots than are left in the allocation.
+    fn object_header(&self, addr: usize) -> Option<usize> {
+        let word = mem::size_of::<usize>();
+        if !self.tagged.contains(addr) { return None }
+        let start = self.allocation_start(addr)?;
+        let header = *self.heap.safe_ref((addr - word) as *const usize)?;
+        let end = start + self.heap.size_of_ptr(start as *const usize);
+        if addr + (header & HEADER_SLOTS_MASK) * word > end { return None }
+        Some(header)
+    }
+
+    // validate a (start, count) range of globals from a pair of immediates
+    fn global_range(&self, start: isize, count: isize) -> Result<(usize, usize), ErrorCode> {
+        if start < 0 || count < 0 { return Err(ErrorCode::OutOfBounds) }
+        let (start, count) = (start as usize, count as usize);
+        if start > self.globals.len() || count > self.globals.len() - start { return Err(ErrorCode::OutOfBounds) }
+        Ok((start, count))
+    }
+
     pub fn load_slot(
         &self,
         addr: usize,
@@ -286,7 +1474,9 @@ impl<'rom, 'heap> Runtime<'rom, 'heap> {
         if slot_addr % mem::size_of::<usize>() != 0 { return Err(ErrorCode::Unaligned) }
         let slot_ptr = slot_addr as *const usize;
         let slot = self.constant_pool.safe_ref(slot_ptr).or_else(|| {
-            self.heap.safe_ref(slot_ptr)
+            self.rodata.safe_ref(slot_ptr)
+        }).or_else(|| {
+            if self.is_internal(slot_addr) { None } else { self.heap.safe_ref(slot_ptr) }
         }).ok_or(ErrorCode::InvalidAddress)?;
         Ok(*slot)
     }
@@ -301,26 +1491,242 @@ impl<'rom, 'heap> Runtime<'rom, 'heap> {
         let slot_addr = addr + slot * mem::size_of::<usize>();
         if slot_addr % mem::size_of::<usize>() != 0 { return Err(ErrorCode::Unaligned) }
         let slot_ptr = slot_addr as *mut usize;
+        if self.is_frozen(slot_addr) { return Err(ErrorCode::Immutable) }
+        if self.is_internal(slot_addr) { return Err(ErrorCode::InvalidAddress) }
         let obj = self.heap.safe_ref_mut(slot_ptr).ok_or(ErrorCode::InvalidAddress)?;
         *obj = value;
         Ok(())
     }
 
+    /// Read a slot of the heap object at `addr`, for a host holding an
+    /// object that code returned. Unlike `load_slot`, a slot past the end
+    /// of the object is `OutOfBounds` instead of whatever comes after it.
+    pub fn read_object(&self, addr: usize, slot: usize) -> Result<usize, ErrorCode> {
+        self.load_slot(addr, self.checked_index(addr, slot as isize)?)
+    }
+
+    /// Write a slot of the heap object at `addr`, with the same checks as
+    /// `read_object` (and `Immutable` if it's been frozen).
+    pub fn write_object(&self, addr: usize, slot: usize, value: usize) -> Result<(), ErrorCode> {
+        self.store_slot(addr, self.checked_index(addr, slot as isize)?, value)
+    }
+
     pub fn new_object(
         &mut self,
         slots: usize,
         from_stack: usize,
         context: &mut RuntimeContext<'rom, 'heap>
     ) -> Result<usize, ErrorCode> {
-        if slots > 64 { return Err(ErrorCode::InvalidSize) }
         if from_stack > slots { return Err(ErrorCode::OutOfBounds) }
-        let obj = self.heap.allocate_array::<usize>(slots).ok_or(ErrorCode::OutOfMemory)?;
+        let addr = self.allocate_or_collect(context, |r| r.alloc_slots(slots))?;
         let fields = context.get_n(from_stack)?;
-        for i in 0 .. fields.len() { obj[i] = fields[i]; }
+        let obj = unsafe { core::slice::from_raw_parts_mut(addr as *mut usize, slots) };
+        obj[0 .. fields.len()].copy_from_slice(fields);
+        Ok(addr)
+    }
+
+    /// Allocate an object from the host, the same way `New` would, with
+    /// all its slots empty (or poisoned). It starts out pinned, so it lives
+    /// until it's unpinned (or freed) even if nothing else refers to it.
+    pub fn alloc_object(&mut self, slots: usize) -> Result<usize, ErrorCode> {
+        if slots > 64 { return Err(ErrorCode::InvalidSize) }
+        self.pinned.reserve(&mut self.heap)?;
+        let addr = self.alloc_slots(slots)?;
+        self.pinned.insert(&mut self.heap, addr)?;
+        Ok(addr)
+    }
+
+    fn alloc_slots(&mut self, slots: usize) -> Result<usize, ErrorCode> {
+        if slots > 64 { return Err(ErrorCode::InvalidSize) }
+        let obj = match self.reuse_object(slots) {
+            Some(obj) => obj,
+            None => self.heap.allocate_array::<usize>(slots).ok_or(ErrorCode::OutOfMemory)?,
+        };
+        if self.poison_objects {
+            for slot in obj.iter_mut() { *slot = POISON; }
+        }
         // gross: turn the object into its pointer
         Ok(obj as *mut [usize] as *mut usize as usize)
     }
 
+    /// A read-only view of an object's slots, for the host to inspect.
+    /// Constants and rodata don't record their size, so for those, this is
+    /// every word from `addr` to the end of the constant pool (or rodata).
+    pub fn object_slots(&self, addr: usize) -> Result<&[usize], ErrorCode> {
+        let word = mem::size_of::<usize>();
+        if addr % word != 0 { return Err(ErrorCode::Unaligned) }
+        let count = if self.heap.is_ptr_inside(addr as *const usize) {
+            // only the start of an object, and never past the end of its allocation
+            if self.object_header(addr).is_none() && self.heap.size_of_ptr(addr as *const usize) == 0 {
+                return Err(ErrorCode::InvalidAddress);
+            }
+            if self.is_internal(addr) { return Err(ErrorCode::InvalidAddress) }
+            let start = self.allocation_start(addr).ok_or(ErrorCode::InvalidAddress)?;
+            let end = start + self.heap.size_of_ptr(start as *const usize);
+            self.object_size(addr)?.min((end - addr) / word)
+        } else {
+            let pool = if self.constant_pool.safe_ref(addr as *const usize).is_some() { &self.constant_pool } else { &self.rodata };
+            let end = pool.data.as_ptr() as usize + pool.data.len();
+            if pool.safe_ref(addr as *const usize).is_none() { return Err(ErrorCode::InvalidAddress) }
+            (end - addr) / word
+        };
+        Ok(unsafe { core::slice::from_raw_parts(addr as *const usize, count) })
+    }
+
+    /// Do two byte strings (like the ones `ConstantBytes` loads) hold the
+    /// same bytes? Each can be in the constant pool, rodata, or a heap
+    /// object, but has to be entirely inside it, or it's `InvalidAddress`.
+    pub fn bytes_equal(&self, a_addr: usize, a_len: usize, b_addr: usize, b_len: usize) -> Result<bool, ErrorCode> {
+        Ok(self.byte_range(a_addr, a_len)? == self.byte_range(b_addr, b_len)?)
+    }
+
+    /// Allocate a heap object holding the bytes of one byte string followed
+    /// by another, checked the same way as `bytes_equal`, and return its
+    /// address. Any bytes past the end of them in the last slot are 0.
+    /// It's pinned, like an object from `alloc_object`, and has the same
+    /// 64-slot limit, so joining more than that many words of bytes is
+    /// `InvalidSize`. If the heap is full, garbage is collected (keeping
+    /// the two byte strings) and it tries again.
+    pub fn concat_bytes(&mut self, a_addr: usize, a_len: usize, b_addr: usize, b_len: usize) -> Result<usize, ErrorCode> {
+        // check before allocating, so a bad range doesn't leave garbage behind.
+        self.byte_range(a_addr, a_len)?;
+        self.byte_range(b_addr, b_len)?;
+        let word = mem::size_of::<usize>();
+        let len = a_len + b_len;
+        let size = len.div_ceil(word) * word;
+        let addr = match self.alloc_object(size / word) {
+            Err(ErrorCode::OutOfMemory) => {
+                self.collect(None, &[ a_addr, b_addr ]);
+                self.alloc_object(size / word)
+            },
+            rv => rv,
+        }?;
+        let obj = unsafe { core::slice::from_raw_parts_mut(addr as *mut u8, size) };
+        obj[.. a_len].copy_from_slice(self.byte_range(a_addr, a_len)?);
+        obj[a_len .. len].copy_from_slice(self.byte_range(b_addr, b_len)?);
+        for b in obj[len ..].iter_mut() { *b = 0; }
+        Ok(addr)
+    }
+
+    // the bytes from `addr` to `addr + len`, if they're all inside the
+    // constant pool, rodata, or a single heap allocation.
+    fn byte_range(&self, addr: usize, len: usize) -> Result<&[u8], ErrorCode> {
+        let ptr = addr as *const u8;
+        if let Some(bytes) = self.constant_pool.safe_slice(ptr, len).or_else(|| self.rodata.safe_slice(ptr, len)) {
+            return Ok(bytes);
+        }
+        let start = self.allocation_start(addr).ok_or(ErrorCode::InvalidAddress)?;
+        if self.is_internal(start) { return Err(ErrorCode::InvalidAddress) }
+        let end = start + self.heap.size_of_ptr(start as *const usize);
+        if len > end - addr { return Err(ErrorCode::InvalidAddress) }
+        Ok(unsafe { core::slice::from_raw_parts(ptr, len) })
+    }
+
+    /// Put an object on the free list, so a later `New` of the same size
+    /// can reuse it. The caller promises there are no other references to
+    /// it. If it was pinned, it isn't anymore. If the free list is full,
+    /// the object is left for the GC.
+    pub fn free_object(&mut self, addr: usize) -> Result<(), ErrorCode> {
+        // only plain objects: tagged objects don't start their allocation.
+        if self.object_header(addr).is_some() || self.heap.size_of_ptr(addr as *const usize) == 0 {
+            return Err(ErrorCode::InvalidAddress);
+        }
+        if self.free_list[0 .. self.free_count].contains(&addr) || self.is_internal(addr) {
+            return Err(ErrorCode::InvalidAddress);
+        }
+        // reusing a weak reference's memory would let the GC clear some other object's slot.
+        if self.weak_refs.contains(addr) { return Err(ErrorCode::InvalidAddress) }
+        if self.is_frozen(addr) { return Err(ErrorCode::Immutable) }
+        self.unpin(addr);
+        if self.free_count < FREE_LIST_SIZE {
+            self.free_list[self.free_count] = addr;
+            self.free_count += 1;
+        }
+        Ok(())
+    }
+
+    // take an object off the free list that's big enough for `slots`, but
+    // not so big that a fresh allocation would've been smaller. it's zeroed.
+    fn reuse_object(&mut self, slots: usize) -> Option<&'heap mut [usize]> {
+        let word_size = mem::size_of::<usize>();
+        let index = (0 .. self.free_count).find(|&i| {
+            let size = self.heap.size_of_ptr(self.free_list[i] as *const usize) / word_size;
+            size >= slots && size < slots + FREE_LIST_SLACK
+        })?;
+        let addr = self.free_list[index];
+        self.free_count -= 1;
+        self.free_list[index] = self.free_list[self.free_count];
+
+        let size = self.heap.size_of_ptr(addr as *const usize) / word_size;
+        let obj = unsafe { core::slice::from_raw_parts_mut(addr as *mut usize, size) };
+        for slot in obj.iter_mut() { *slot = 0; }
+        Some(&mut obj[0 .. slots])
+    }
+
+    /// Allocate an object with a hidden header word that records its tag
+    /// and exact size.
+    pub fn new_tagged_object(
+        &mut self,
+        slots: usize,
+        from_stack: usize,
+        tag: usize,
+        context: &mut RuntimeContext<'rom, 'heap>
+    ) -> Result<usize, ErrorCode> {
+        if slots > 64 { return Err(ErrorCode::InvalidSize) }
+        if from_stack > slots { return Err(ErrorCode::OutOfBounds) }
+        let obj = self.allocate_or_collect(context, |r| {
+            r.tagged.reserve(&mut r.heap)?;
+            r.heap.allocate_array::<usize>(slots + 1).ok_or(ErrorCode::OutOfMemory)
+        })?;
+        obj[0] = (tag << HEADER_TAG_SHIFT) | slots;
+        let fields = context.get_n(from_stack)?;
+        obj[1 .. fields.len() + 1].copy_from_slice(fields);
+        if self.poison_objects {
+            for slot in obj[fields.len() + 1 ..].iter_mut() { *slot = POISON; }
+        }
+        let addr = &mut obj[1] as *mut usize as usize;
+        self.tagged.insert(&mut self.heap, addr)?;
+        Ok(addr)
+    }
+
+    /// Allocate an object whose address is a multiple of `align` bytes, by
+    /// padding the front of a tagged object (with tag 0). Word alignment is
+    /// just a plain object.
+    pub fn new_aligned_object(
+        &mut self,
+        slots: usize,
+        from_stack: usize,
+        align: isize,
+        context: &mut RuntimeContext<'rom, 'heap>
+    ) -> Result<usize, ErrorCode> {
+        let word = mem::size_of::<usize>();
+        if align <= 0 || (align as usize) > MAX_ALIGNMENT || !(align as usize).is_power_of_two() {
+            return Err(ErrorCode::Unaligned);
+        }
+        let align = align as usize;
+        if align <= word { return self.new_object(slots, from_stack, context) }
+        if slots > 64 { return Err(ErrorCode::InvalidSize) }
+        if from_stack > slots { return Err(ErrorCode::OutOfBounds) }
+
+        let padding = align / word - 1;
+        let obj = self.allocate_or_collect(context, |r| {
+            r.tagged.reserve(&mut r.heap)?;
+            r.heap.allocate_array::<usize>(slots + 1 + padding).ok_or(ErrorCode::OutOfMemory)
+        })?;
+        let start = obj.as_ptr() as usize;
+        // the first aligned address after the header
+        let addr = (start + word + align - 1) & !(align - 1);
+        let offset = (addr - start) / word;
+        obj[offset - 1] = slots;
+        let fields = context.get_n(from_stack)?;
+        obj[offset .. offset + fields.len()].copy_from_slice(fields);
+        if self.poison_objects {
+            for slot in obj[offset + fields.len() .. offset + slots].iter_mut() { *slot = POISON; }
+        }
+        self.tagged.insert(&mut self.heap, addr)?;
+        Ok(addr)
+    }
+
     pub fn unary(
         &self,
         op: Unary,
@@ -328,12 +1734,19 @@ impl<'rom, 'heap> Runtime<'rom, 'heap> {
     ) -> Result<isize, ErrorCode> {
         match op {
             Unary::Not => Ok(if n1 == 0 { 1 } else { 0 }),
-            Unary::Negative => Ok(-n1),
+            Unary::Negative => self.overflow(n1.overflowing_neg()),
             Unary::BitNot => Ok(!n1),
+            // there's no positive `isize::MIN`, and wrapping back to it would be a surprise.
+            Unary::Abs => n1.checked_abs().ok_or(ErrorCode::ArithmeticOverflow),
             _ => Err(ErrorCode::UnknownOpcode),
         }
     }
 
+    // the wrapped result, unless it overflowed and `trap_overflow` is on
+    fn overflow<T>(&self, (n, overflowed): (T, bool)) -> Result<T, ErrorCode> {
+        if overflowed && self.trap_overflow { Err(ErrorCode::ArithmeticOverflow) } else { Ok(n) }
+    }
+
     pub fn binary(
         &self,
         op: Binary,
@@ -341,20 +1754,38 @@ impl<'rom, 'heap> Runtime<'rom, 'heap> {
         n2: isize,
     ) -> Result<isize, ErrorCode> {
         match op {
-            Binary::Add => Ok(n1.wrapping_add(n2)),
-            Binary::Subtract => Ok(n1.wrapping_sub(n2)),
-            Binary::Multiply => Ok(n1.wrapping_mul(n2)),
-            Binary::Divide => Ok(n1 / n2),
-            Binary::Modulo => Ok(n1 % n2),
+            Binary::Add => self.overflow(n1.overflowing_add(n2)),
+            Binary::Subtract => self.overflow(n1.overflowing_sub(n2)),
+            Binary::Multiply => self.overflow(n1.overflowing_mul(n2)),
+            Binary::Divide => divided(n2, n1.checked_div(n2)),
+            Binary::Modulo => divided(n2, n1.checked_rem(n2)),
             Binary::Equals => Ok(if n1 == n2 { 1 } else { 0 }),
             Binary::LessThan => Ok(if n1 < n2 { 1 } else { 0 }),
             Binary::LessOrEqual => Ok(if n1 <= n2 { 1 } else { 0 }),
             Binary::BitOr => Ok(n1 | n2),
             Binary::BitAnd => Ok(n1 & n2),
             Binary::BitXor => Ok(n1 ^ n2),
-            Binary::ShiftLeft => Ok(n1 << n2),
-            Binary::ShiftRight => Ok(((n1 as usize) >> n2) as isize),
-            Binary::SignShiftRight => Ok(n1 >> n2),
+            // shifting by a negative amount, or by every bit or more, shifts everything out.
+            Binary::ShiftLeft => self.overflow(shift_amount(n2).map_or((0, true), |s| (n1 << s, false))),
+            Binary::ShiftRight => {
+                self.overflow(shift_amount(n2).map_or((0, true), |s| (((n1 as usize) >> s) as isize, false)))
+            },
+            Binary::SignShiftRight => {
+                self.overflow(shift_amount(n2).map_or((if n1 < 0 { -1 } else { 0 }, true), |s| (n1 >> s, false)))
+            },
+            Binary::LogicalAnd => Ok(if n1 != 0 && n2 != 0 { 1 } else { 0 }),
+            Binary::LogicalOr => Ok(if n1 != 0 || n2 != 0 { 1 } else { 0 }),
+            Binary::RefEquals => Ok(if n1 == n2 { 1 } else { 0 }),
+            Binary::GreaterThan => Ok(if n1 > n2 { 1 } else { 0 }),
+            Binary::GreaterOrEqual => Ok(if n1 >= n2 { 1 } else { 0 }),
+            Binary::UnsignedLessThan => Ok(if (n1 as usize) < (n2 as usize) { 1 } else { 0 }),
+            Binary::UnsignedDivide => divided(n2, (n1 as usize).checked_div(n2 as usize).map(|n| n as isize)),
+            Binary::UnsignedModulo => divided(n2, (n1 as usize).checked_rem(n2 as usize).map(|n| n as isize)),
+            Binary::Min => Ok(n1.min(n2)),
+            Binary::Max => Ok(n1.max(n2)),
+            Binary::AddChecked => n1.checked_add(n2).ok_or(ErrorCode::ArithmeticOverflow),
+            Binary::SubtractChecked => n1.checked_sub(n2).ok_or(ErrorCode::ArithmeticOverflow),
+            Binary::MultiplyChecked => n1.checked_mul(n2).ok_or(ErrorCode::ArithmeticOverflow),
             _ => Err(ErrorCode::UnknownOpcode),
         }
     }
@@ -366,3 +1797,47 @@ impl<'rom, 'heap> fmt::Debug for Runtime<'rom, 'heap> {
         write!(f, "Runtime(pool={:?}, heap={:?})", self.constant_pool.data, self.heap)
     }
 }
+
+
+// division only fails on a zero divisor, or `isize::MIN / -1`, which has
+// no answer that fits.
+fn divided(divisor: isize, result: Option<isize>) -> Result<isize, ErrorCode> {
+    result.ok_or(if divisor == 0 { ErrorCode::DivideByZero } else { ErrorCode::ArithmeticOverflow })
+}
+
+// a shift by `n` bits, if that's less than a whole word
+fn shift_amount(n: isize) -> Option<u32> {
+    if n >= 0 && (n as usize) < mem::size_of::<usize>() * 8 { Some(n as u32) } else { None }
+}
+
+// decode the instruction at the frame's pc, and charge its cost against `max_cycles`.
+fn decode_charged(
+    context: &RuntimeContext,
+    limits: &Limits,
+    cycles: &mut usize,
+) -> Result<(Instruction, u16), RuntimeError> {
+    let (instruction, next_pc) = decode_next(context.code.bytecode, context.frame.pc).map_err(|e| {
+        RuntimeError::from(e, context).at_opcode(context)
+    })?;
+    if let Some(m) = limits.max_cycles {
+        *cycles += opcode_cost(instruction.opcode) as usize;
+        if *cycles > m.get() {
+            return Err(RuntimeError::from(ErrorCode::CyclesExceeded, context));
+        }
+    }
+    Ok((instruction, next_pc))
+}
+
+// jump targets have to fit in the 16-bit pc, instead of being truncated into it.
+fn jump_target(n1: isize) -> Result<u16, ErrorCode> {
+    if n1 < 0 || n1 > u16::MAX as isize { return Err(ErrorCode::OutOfBounds) }
+    Ok(n1 as u16)
+}
+
+// extract `width` bits starting at bit `offset`, and sign-extend them.
+fn load_field(v: usize, offset: isize, width: isize) -> Result<usize, ErrorCode> {
+    let bits = (mem::size_of::<usize>() * 8) as isize;
+    if width < 1 || width > bits { return Err(ErrorCode::InvalidSize) }
+    if offset < 0 || offset + width > bits { return Err(ErrorCode::OutOfBounds) }
+    Ok((((v << (bits - offset - width)) as isize) >> (bits - width)) as usize)
+}
@@ -0,0 +1,95 @@
+use crate::constant_pool::Code;
+use crate::disassembler::{decode_next, is_instruction_start, Instruction};
+use crate::error::ErrorCode;
+use crate::opcode::Opcode;
+
+// marks an instruction that no path has reached yet
+const UNREACHED: u8 = 0xff;
+
+/// Find the deepest the stack can get while running a code block, by
+/// following every path through it, so it can be compared to the block's
+/// `max_stack`. This is conservative: opcodes that pop a number of items
+/// chosen at runtime (like the fill count of `New`) are assumed to pop only
+/// their fixed operands, and both sides of every branch are followed.
+///
+/// Returns `None` if the depth can't be known ahead of time: the block
+/// calls something that could return any number of values (`Call` or
+/// `CallN`), jumps to a computed address, or has a loop that keeps growing
+/// the stack. A path that would pop more than it pushed is `StackUnderflow`,
+/// and a jump that doesn't land on an instruction is `OutOfBounds`.
+///
+/// `depths` is used to remember the depth at each instruction, so it needs
+/// to be at least as long as the bytecode.
+pub fn max_stack_depth(code: &Code, depths: &mut [u8]) -> Result<Option<usize>, ErrorCode> {
+    let len = code.bytecode.len();
+    if depths.len() < len { return Err(ErrorCode::OutOfBounds) }
+    for d in depths[0 .. len].iter_mut() { *d = UNREACHED; }
+    if len == 0 { return Ok(Some(0)) }
+    depths[0] = 0;
+
+    let mut max = 0;
+    let mut changed = true;
+    // keep sweeping until no instruction's depth goes up. each sweep raises
+    // at least one depth, and none can pass `UNREACHED`, so this ends.
+    while changed {
+        changed = false;
+        let mut pc = 0;
+        while (pc as usize) < len {
+            let (instruction, next_pc) = decode_next(code.bytecode, pc)?;
+            let depth = depths[pc as usize];
+            pc = next_pc;
+            if depth == UNREACHED { continue }
+
+            if instruction.opcode == Opcode::Unknown { return Err(ErrorCode::UnknownOpcode) }
+            let (pops, pushes) = instruction.stack_effect();
+            if pushes == Instruction::DYNAMIC { return Ok(None) }
+            let pops = if pops == Instruction::DYNAMIC { fixed_operands(instruction.opcode) } else { pops };
+            if pops > depth as usize { return Err(ErrorCode::StackUnderflow) }
+            // every opcode pops what it needs before it pushes, so this is its peak.
+            let after = depth as usize - pops + pushes;
+            if after > max { max = after; }
+
+            let mut targets = [ None, None ];
+            match instruction.opcode {
+                Opcode::Return | Opcode::ReturnN | Opcode::Throw => (),
+                Opcode::Jump => targets[0] = Some(instruction.n1),
+                Opcode::JumpRel => targets[0] = Some(instruction.offset as isize + instruction.n1),
+                Opcode::JumpIfPointer | Opcode::JumpIfZero | Opcode::JumpIfNonZero => {
+                    targets = [ Some(next_pc as isize), Some(instruction.n1) ];
+                },
+                Opcode::If => {
+                    // a false condition skips the next instruction.
+                    let skip_to = if (next_pc as usize) < len { decode_next(code.bytecode, next_pc)?.1 } else { next_pc };
+                    targets = [ Some(next_pc as isize), Some(skip_to as isize) ];
+                },
+                Opcode::JumpDynamic => return Ok(None),
+                _ => targets[0] = Some(next_pc as isize),
+            }
+
+            for &target in targets.iter().flatten() {
+                // running off the end is an implicit return.
+                if target == len as isize { continue }
+                if target < 0 || target as usize > len || !is_instruction_start(code.bytecode, target as u16) {
+                    return Err(ErrorCode::OutOfBounds);
+                }
+                let slot = &mut depths[target as usize];
+                if *slot == UNREACHED || (*slot as usize) < after {
+                    // a loop that keeps pushing has no limit.
+                    if after >= UNREACHED as usize { return Ok(None) }
+                    *slot = after as u8;
+                    changed = true;
+                }
+            }
+        }
+    }
+    Ok(Some(max))
+}
+
+// the operands an opcode with a dynamic pop count reads to find out how
+// many more to pop, which is the least it can pop.
+fn fixed_operands(opcode: Opcode) -> usize {
+    match opcode {
+        Opcode::Call | Opcode::New | Opcode::NewTaggedN | Opcode::NewAlignedN => 2,
+        _ => 1,
+    }
+}
@@ -2,7 +2,7 @@ use core::{fmt, mem, slice};
 use mwgc::Heap;
 
 use crate::constant_pool::{Code, ConstantPool};
-use crate::error::{ErrorCode, RuntimeError};
+use crate::error::ErrorCode;
 
 /// A stack frame as it exists on the runtime's heap, in a linked list back
 /// to the starting frame.
@@ -19,7 +19,7 @@ pub struct StackFrame {
     // 32 bits of other metadata:
     pub pc: u16,
     pub sp: u8,
-    unused1: u8,
+    pub flags: u8,
     // local storage goes here, then the stack slots
 }
 
@@ -27,8 +27,10 @@ pub struct StackFrame {
 /// reconstruct each time we call or return from a function, so we don't need
 /// to waste heap space on it.
 pub struct RuntimeContext<'rom, 'heap> {
-    pub frame: &'heap mut StackFrame,
-    pub code: Code<'rom>,
+    pub(crate) frame: &'heap mut StackFrame,
+    pub(crate) code: Code<'rom>,
+    // how many frames are above this one (0 for the starting frame):
+    pub(crate) depth: usize,
 }
 
 pub enum PreviousContext<'rom, 'heap> {
@@ -38,142 +40,278 @@ pub enum PreviousContext<'rom, 'heap> {
 
 impl<'rom, 'heap> RuntimeContext<'rom, 'heap> {
     fn new(
-        constant_pool: &ConstantPool<'rom>,
         heap: &mut Heap<'heap>,
-        code_addr: usize,
+        code: Code<'rom>,
+        code_offset: u32,
         up_frame: usize,
+        flags: u8,
     ) -> Result<RuntimeContext<'rom, 'heap>, ErrorCode> {
-        let code = constant_pool.get_code(code_addr)?;
-        let total = (code.local_count + code.max_stack) as usize * mem::size_of::<usize>();
+        let mut words = code.local_count as usize + code.max_stack as usize;
+        if flags & FLAG_CHECK_LOCALS != 0 { words += bitmap_words(code.local_count as usize) }
+        let total = words * mem::size_of::<usize>();
         let frame = heap.allocate_dynamic_object::<StackFrame>(total).ok_or(ErrorCode::OutOfMemory)?;
         frame.up_frame = up_frame;
-        frame.code_offset = constant_pool.offset_from_addr(code_addr);
-        Ok(RuntimeContext { frame, code })
+        frame.code_offset = code_offset;
+        frame.flags = flags;
+        let mut context = RuntimeContext { frame, code, depth: 0 };
+        if let Some(bitmap) = context.written_locals() {
+            for word in bitmap.iter_mut() { *word = 0; }
+        }
+        Ok(context)
     }
 
     /// Allocate a new stack frame with no previous frame (this is the starting frame).
-    pub fn start(
-        constant_pool: &ConstantPool<'rom>,
+    /// Use `DETACHED_CODE_OFFSET` if the code didn't come from the constant pool.
+    /// Frames called from this one will inherit its `flags`.
+    pub(crate) fn start(
         heap: &mut Heap<'heap>,
-        code_addr: usize,
+        code: Code<'rom>,
+        code_offset: u32,
+        flags: u8,
     ) -> Result<RuntimeContext<'rom, 'heap>, ErrorCode> {
-        RuntimeContext::new(constant_pool, heap, code_addr, core::ptr::null::<StackFrame>() as usize)
+        RuntimeContext::new(heap, code, code_offset, core::ptr::null::<StackFrame>() as usize, flags)
     }
 
-    /// Allocate a new stack frame that links back to this one.
-    pub fn push(
+    /// Allocate a new stack frame that links back to this one. `call_flags`
+    /// (from `expect_results`) are added to the flags it inherits. If it
+    /// fails, the args are still on this frame's stack.
+    pub(crate) fn push(
         &mut self,
         constant_pool: &ConstantPool<'rom>,
         heap: &mut Heap<'heap>,
         code_addr: usize,
         arg_count: usize,
+        call_flags: u8,
     ) -> Result<RuntimeContext<'rom, 'heap>, ErrorCode> {
+        self.check_sp()?;
+        if (self.frame.sp as usize) < arg_count { return Err(ErrorCode::StackUnderflow) }
+        let code = constant_pool.get_code(code_addr)?;
+        let code_offset = constant_pool.offset_from_addr(code_addr);
+        let up_frame = self.frame as *const StackFrame as usize;
+        let flags = (self.frame.flags & INHERITED_FLAGS) | call_flags;
+        let mut next = RuntimeContext::new(heap, code, code_offset, up_frame, flags)?;
+        next.depth = self.depth + 1;
         let args = self.get_n(arg_count)?;
-        let mut next = RuntimeContext::new(constant_pool, heap, code_addr, self.frame as *const StackFrame as usize)?;
         next.start_locals(args)?;
         Ok(next)
     }
 
+    /// Rebuild the context for a frame that's already on the heap, like one
+    /// that was suspended.
+    pub(crate) fn resume(
+        heap: &Heap<'heap>,
+        frame_addr: usize,
+        code: Code<'rom>,
+        depth: usize,
+    ) -> Result<RuntimeContext<'rom, 'heap>, ErrorCode> {
+        let frame = heap.safe_ref_mut(frame_addr as *mut StackFrame).ok_or(ErrorCode::InvalidAddress)?;
+        Ok(RuntimeContext { frame, code, depth })
+    }
+
     /// Drop this stack frame and return the previous one, if there was one.
-    pub fn pop(
+    pub(crate) fn pop(
         &mut self,
         constant_pool: &ConstantPool<'rom>,
         heap: &Heap<'heap>,
         return_count: usize,
     ) -> Result<PreviousContext<'rom, 'heap>, ErrorCode> {
+        check_results(self.frame.flags, return_count)?;
         let return_values = self.get_n(return_count)?;
 
         let ptr = self.frame.up_frame as *mut StackFrame;
         if ptr.is_null() { return Ok(PreviousContext::Done(return_values)) }
 
         // none of these should error out, since they worked on the way in
-        let frame = heap.safe_ref_mut(ptr).ok_or(ErrorCode::InvalidAddress)?;
-        let code_addr = constant_pool.addr_from_offset(frame.code_offset);
-        let code = constant_pool.get_code(code_addr)?;
+        let frame = heap.safe_ref(ptr).ok_or(ErrorCode::InvalidAddress)?;
+        let code = constant_pool.get_code(constant_pool.addr_from_offset(frame.code_offset))?;
 
-        let mut prev = RuntimeContext { frame, code };
+        let mut prev = RuntimeContext::resume(heap, ptr as usize, code, self.depth - 1)?;
         prev.put_n(return_values)?;
         Ok(PreviousContext::Frame(prev))
     }
 
-    pub fn locals_mut(&mut self) -> &'heap mut [usize] {
+    /// The context of the frame that called this one, if there is one.
+    pub(crate) fn caller(
+        &self,
+        constant_pool: &ConstantPool<'rom>,
+        heap: &Heap<'heap>,
+    ) -> Option<RuntimeContext<'rom, 'heap>> {
+        let ptr = self.frame.up_frame as *mut StackFrame;
+        if ptr.is_null() { return None }
+        let frame = heap.safe_ref(ptr)?;
+        let code = constant_pool.get_code(constant_pool.addr_from_offset(frame.code_offset)).ok()?;
+        RuntimeContext::resume(heap, ptr as usize, code, self.depth.saturating_sub(1)).ok()
+    }
+
+    /// Every value in this frame that could still be used: its locals, and
+    /// the stack up to `sp` (but not anything that's been popped).
+    pub fn live_values(&self) -> &[usize] {
+        let base = self.frame as *const StackFrame as *const usize;
+        let len = (self.code.local_count as usize) + self.stack().len();
+        unsafe { slice::from_raw_parts(base.offset(FRAME_HEADER_WORDS), len) }
+    }
+
+    /// Where the frame is in the heap.
+    pub fn frame_addr(&self) -> usize {
+        self.frame as *const StackFrame as usize
+    }
+
+    pub fn code_offset(&self) -> u32 {
+        self.frame.code_offset
+    }
+
+    pub fn pc(&self) -> u16 {
+        self.frame.pc
+    }
+
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    pub(crate) fn locals_mut(&mut self) -> &mut [usize] {
         let base = self.frame as *mut StackFrame as *mut usize;
         unsafe { slice::from_raw_parts_mut(base.offset(FRAME_HEADER_WORDS), self.code.local_count as usize) }
     }
 
-    pub fn locals(&self) -> &'heap [usize] {
+    pub fn locals(&self) -> &[usize] {
         let base = self.frame as *const StackFrame as *const usize;
         unsafe { slice::from_raw_parts(base.offset(FRAME_HEADER_WORDS), self.code.local_count as usize) }
     }
 
-    pub fn stack_mut(&mut self) -> &'heap mut [usize] {
+    pub(crate) fn stack_mut(&mut self) -> &mut [usize] {
         let base = self.frame as *mut StackFrame as *mut usize;
         let offset = FRAME_HEADER_WORDS + (self.code.local_count as isize);
         unsafe { slice::from_raw_parts_mut(base.offset(offset), self.code.max_stack as usize) }
     }
 
-    pub fn stack(&self) -> &'heap [usize] {
+    pub fn stack(&self) -> &[usize] {
         let base = self.frame as *const StackFrame as *const usize;
         let offset = FRAME_HEADER_WORDS + (self.code.local_count as isize);
-        unsafe { slice::from_raw_parts(base.offset(offset), self.frame.sp as usize) }
+        // never reach past the frame, even if `sp` is wrong.
+        let len = core::cmp::min(self.frame.sp, self.code.max_stack) as usize;
+        unsafe { slice::from_raw_parts(base.offset(offset), len) }
     }
 
-    pub fn get(&mut self) -> Result<usize, ErrorCode> {
-        let stack = self.stack();
+    // `sp` past `max_stack` means the stack bookkeeping is broken, and
+    // anything above `max_stack` belongs to some other object.
+    fn check_sp(&self) -> Result<(), ErrorCode> {
+        if self.frame.sp > self.code.max_stack { return Err(ErrorCode::StackOverflow) }
+        Ok(())
+    }
+
+    pub(crate) fn get(&mut self) -> Result<usize, ErrorCode> {
+        self.check_sp()?;
         if self.frame.sp < 1 { return Err(ErrorCode::StackUnderflow) }
         self.frame.sp -= 1;
-        Ok(stack[self.frame.sp as usize])
+        Ok(self.stack_mut()[self.frame.sp as usize])
     }
 
-    // the last N things added to the stack
-    pub fn get_n(&mut self, n: usize) -> Result<&'heap [usize], ErrorCode> {
-        let stack = self.stack();
-        if self.frame.sp < (n as u8) { return Err(ErrorCode::StackUnderflow) }
+    // the last N things added to the stack. they stay in the frame (above
+    // `sp`) until something else is pushed, or the frame is freed.
+    pub(crate) fn get_n(&mut self, n: usize) -> Result<&'heap [usize], ErrorCode> {
+        self.check_sp()?;
+        // compare before narrowing `n`: a count of 300 must not pass as 44.
+        if (self.frame.sp as usize) < n { return Err(ErrorCode::StackUnderflow) }
         self.frame.sp -= n as u8;
         let start = self.frame.sp as usize;
-        Ok(&stack[start .. start + n])
+        let values = &self.stack_mut()[start .. start + n];
+        Ok(unsafe { slice::from_raw_parts(values.as_ptr(), n) })
     }
 
-    pub fn put(&mut self, n: usize) -> Result<(), ErrorCode> {
+    pub(crate) fn put(&mut self, n: usize) -> Result<(), ErrorCode> {
+        self.check_sp()?;
+        let sp = self.frame.sp as usize;
         let stack = self.stack_mut();
-        if (self.frame.sp as usize) >= stack.len() { return Err(ErrorCode::StackOverflow) }
-        stack[self.frame.sp as usize] = n;
+        if sp >= stack.len() { return Err(ErrorCode::StackOverflow) }
+        stack[sp] = n;
         self.frame.sp += 1;
         Ok(())
     }
 
-    pub fn put_n(&mut self, items: &'heap [usize]) -> Result<(), ErrorCode> {
+    pub(crate) fn put_n(&mut self, items: &'heap [usize]) -> Result<(), ErrorCode> {
         for item in items.iter() { self.put(*item)? }
         Ok(())
     }
 
-    pub fn start_locals(&mut self, values: &[usize]) -> Result<(), ErrorCode> {
-        let locals = self.locals_mut();
-        if values.len() > locals.len() { return Err(ErrorCode::LocalsOverflow) }
-        for i in 0..values.len() { locals[i] = values[i] }
-        Ok(())
+    // with `FLAG_CHECK_LOCALS`, a bitmap after the stack tracks which locals have been written.
+    fn bitmap_offset(&self) -> Option<isize> {
+        if self.frame.flags & FLAG_CHECK_LOCALS == 0 { return None }
+        Some(FRAME_HEADER_WORDS + (self.code.local_count as isize) + (self.code.max_stack as isize))
     }
 
-    pub fn get_local(&mut self, n: usize) -> Result<usize, ErrorCode> {
-        let locals = self.locals();
-        if n >= locals.len() {
-            return Err(ErrorCode::LocalsOverflow);
+    fn written_locals(&mut self) -> Option<&mut [usize]> {
+        let offset = self.bitmap_offset()?;
+        let base = self.frame as *mut StackFrame as *mut usize;
+        let len = bitmap_words(self.code.local_count as usize);
+        Some(unsafe { slice::from_raw_parts_mut(base.offset(offset), len) })
+    }
+
+    pub(crate) fn mark_local_written(&mut self, n: usize) {
+        if let Some(bitmap) = self.written_locals() {
+            bitmap[n / BITMAP_BITS] |= 1 << (n % BITMAP_BITS);
+        }
+    }
+
+    /// Fail with `UninitializedLocal` if locals are being checked, and this one was never written.
+    pub(crate) fn check_local(&self, n: usize) -> Result<(), ErrorCode> {
+        if let Some(offset) = self.bitmap_offset() {
+            let base = self.frame as *const StackFrame as *const usize;
+            let len = bitmap_words(self.code.local_count as usize);
+            let bitmap = unsafe { slice::from_raw_parts(base.offset(offset), len) };
+            if bitmap[n / BITMAP_BITS] & (1 << (n % BITMAP_BITS)) == 0 { return Err(ErrorCode::UninitializedLocal) }
         }
-        Ok(locals[n])
+        Ok(())
+    }
+
+    pub(crate) fn start_locals(&mut self, values: &[usize]) -> Result<(), ErrorCode> {
+        let locals = self.locals_mut();
+        if values.len() > locals.len() { return Err(ErrorCode::LocalsOverflow) }
+        locals[..values.len()].copy_from_slice(values);
+        for i in 0..values.len() { self.mark_local_written(i) }
+        Ok(())
     }
 
-    pub fn put_local(&mut self, n: usize, value: usize) -> Result<(), ErrorCode> {
+    pub(crate) fn put_local(&mut self, n: usize, value: usize) -> Result<(), ErrorCode> {
         let locals = self.locals_mut();
         if n >= locals.len() {
             return Err(ErrorCode::LocalsOverflow);
         }
         locals[n] = value;
+        self.mark_local_written(n);
         Ok(())
     }
+}
+
+// code offset of a frame whose code was handed to us directly, instead of living in the constant pool
+pub const DETACHED_CODE_OFFSET: u32 = 0xffff_ffff;
+
+// frame flags:
+pub const FLAG_CHECK_LOCALS: u8 = 1;
+// the caller expects exactly (flags >> RESULTS_SHIFT) values back:
+pub const FLAG_CHECK_RESULTS: u8 = 2;
+const RESULTS_SHIFT: u8 = 2;
+// flags that a called frame copies from its caller:
+const INHERITED_FLAGS: u8 = FLAG_CHECK_LOCALS;
+
+/// Flags for a call that must return exactly `count` values.
+pub fn expect_results(count: usize) -> Result<u8, ErrorCode> {
+    if count > (0xff >> RESULTS_SHIFT) { return Err(ErrorCode::OutOfBounds) }
+    Ok(FLAG_CHECK_RESULTS | ((count as u8) << RESULTS_SHIFT))
+}
 
-    pub fn to_error(&self, code: ErrorCode) -> RuntimeError {
-        RuntimeError::from(code, self)
+/// Fail with `ReturnCountMismatch` if a frame with these flags can't
+/// return `count` values.
+pub fn check_results(flags: u8, count: usize) -> Result<(), ErrorCode> {
+    if flags & FLAG_CHECK_RESULTS != 0 && (flags >> RESULTS_SHIFT) as usize != count {
+        return Err(ErrorCode::ReturnCountMismatch);
     }
+    Ok(())
+}
+
+const BITMAP_BITS: usize = mem::size_of::<usize>() * 8;
+
+fn bitmap_words(bits: usize) -> usize {
+    bits.div_ceil(BITMAP_BITS)
 }
 
 pub const FRAME_HEADER_WORDS: isize = (mem::size_of::<StackFrame>() / mem::size_of::<usize>()) as isize;
@@ -195,6 +333,7 @@ mod tests {
     use core::mem;
     use mwgc::Heap;
     use crate::constant_pool::ConstantPool;
+    use crate::error::ErrorCode;
     use super::{FRAME_HEADER_WORDS, RuntimeContext, StackFrame};
 
     #[test]
@@ -202,7 +341,10 @@ mod tests {
         let mut data: [u8; 256] = [0; 256];
         let mut heap = Heap::from_bytes(&mut data);
         let pool = ConstantPool::new(&[ 2, 0, 1, 0, 0 ]);
-        let mut context = RuntimeContext::start(&pool, &mut heap, pool.addr_from_offset(0)).unwrap();
+        let code = pool.get_code(pool.addr_from_offset(0)).unwrap();
+        let mut context = RuntimeContext::start(&mut heap, code, 0, 0).unwrap();
+        assert_eq!(context.code.local_count, 2);
+        let frame_addr = context.frame_addr();
         let locals = context.locals_mut();
 
         // make sure we allocated enough memory, and that everything is where we expect.
@@ -210,11 +352,10 @@ mod tests {
         assert!(heap_used >= mem::size_of::<StackFrame>() + 2 * mem::size_of::<usize>());
         assert_eq!(
             locals as *mut _ as *mut usize as usize,
-            context.frame as *mut _ as usize + mem::size_of::<StackFrame>()
+            frame_addr + mem::size_of::<StackFrame>()
         );
         assert!(mem::size_of::<StackFrame>() % mem::size_of::<usize>() == 0);
 
-        assert_eq!(context.code.local_count, 2);
         locals[0] = 123456;
         locals[1] = 4;
         assert_eq!(locals[0], 123456);
@@ -227,7 +368,8 @@ mod tests {
         let mut data: [u8; 256] = [0; 256];
         let mut heap = Heap::from_bytes(&mut data);
         let pool = ConstantPool::new(&[ 2, 0, 1, 0, 0 ]);
-        let mut context = RuntimeContext::start(&pool, &mut heap, pool.addr_from_offset(0)).unwrap();
+        let code = pool.get_code(pool.addr_from_offset(0)).unwrap();
+        let mut context = RuntimeContext::start(&mut heap, code, 0, 0).unwrap();
         context.locals_mut()[2] = 1;
     }
 
@@ -236,22 +378,40 @@ mod tests {
         let mut data: [u8; 256] = [0; 256];
         let mut heap = Heap::from_bytes(&mut data);
         let pool = ConstantPool::new(&[ 2, 2, 1, 0, 0 ]);
-        let mut context = RuntimeContext::start(&pool, &mut heap, pool.addr_from_offset(0)).unwrap();
+        let code = pool.get_code(pool.addr_from_offset(0)).unwrap();
+        let mut context = RuntimeContext::start(&mut heap, code, 0, 0).unwrap();
+        assert_eq!(context.frame.sp, 0);
+        let frame_addr = context.frame_addr();
         let stack = context.stack_mut();
 
         // make sure we allocated enough memory, and that everything is where we expect.
         let heap_used = heap.get_stats().total_bytes - heap.get_stats().free_bytes;
         assert!(heap_used >= mem::size_of::<StackFrame>() + 4 * mem::size_of::<usize>());
         let offset = mem::size_of::<StackFrame>() + 2 * mem::size_of::<usize>();
-        assert_eq!(stack as *mut _ as *mut usize as usize, context.frame as *mut _ as usize + offset);
+        assert_eq!(stack as *mut _ as *mut usize as usize, frame_addr + offset);
 
-        assert_eq!(context.frame.sp, 0);
         stack[0] = 23;
         stack[1] = 19;
         assert_eq!(stack[0], 23);
         assert_eq!(stack[1], 19);
     }
 
+    #[test]
+    fn stack_pointer_past_max() {
+        let mut data: [u8; 256] = [0; 256];
+        let mut heap = Heap::from_bytes(&mut data);
+        let pool = ConstantPool::new(&[ 0, 2, 1, 0, 0 ]);
+        let code = pool.get_code(pool.addr_from_offset(0)).unwrap();
+        let mut context = RuntimeContext::start(&mut heap, code, 0, 0).unwrap();
+
+        context.frame.sp = 3;
+        assert_eq!(context.stack().len(), 2);
+        assert_eq!(context.get(), Err(ErrorCode::StackOverflow));
+        assert_eq!(context.get_n(1), Err(ErrorCode::StackOverflow));
+        assert_eq!(context.put(1), Err(ErrorCode::StackOverflow));
+        assert_eq!(context.frame.sp, 3);
+    }
+
     #[test]
     fn allocation_size() {
         assert_eq!(FRAME_HEADER_WORDS, if mem::size_of::<usize>() == 4 { 3 } else { 2 })
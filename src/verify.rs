@@ -0,0 +1,136 @@
+use crate::constant_pool::{Code, ConstantPool};
+use crate::disassembler::{InstructionStarts, decode_next};
+use crate::error::ErrorCode;
+use crate::opcode::Opcode;
+
+/// Check a code block for problems that can be found without running it:
+/// unknown opcodes, truncated instructions, jumps that don't land on an
+/// instruction, locals past the block's local count, and constants past
+/// the end of the pool. Returns the first problem found.
+pub fn verify_code(pool: &ConstantPool, offset: u32) -> Result<(), ErrorCode> {
+    let mut first = None;
+    check_block(pool, offset, &mut |error| {
+        first = Some(error);
+        false
+    });
+    first.map_or(Ok(()), Err)
+}
+
+/// Check every code block in `code_offsets`, and collect every problem
+/// found as a `(code_offset, error)` pair, instead of stopping at the
+/// first. Returns the total number of problems: if that's more than
+/// `problems` can hold, only the first `problems.len()` are kept.
+pub fn verify_image(pool: &ConstantPool, code_offsets: &[u32], problems: &mut [(u32, ErrorCode)]) -> usize {
+    let mut count = 0;
+    for &offset in code_offsets {
+        check_block(pool, offset, &mut |error| {
+            if count < problems.len() { problems[count] = (offset, error); }
+            count += 1;
+            true
+        });
+    }
+    count
+}
+
+// report each problem until `report` returns false.
+fn check_block(pool: &ConstantPool, offset: u32, report: &mut dyn FnMut(ErrorCode) -> bool) {
+    match pool.get_code(pool.addr_from_offset(offset)) {
+        Ok(code) => check_code(pool, &code, report),
+        Err(error) => { report(error); },
+    }
+}
+
+fn check_code(pool: &ConstantPool, code: &Code, report: &mut dyn FnMut(ErrorCode) -> bool) {
+    let words = pool.data.len().div_ceil(4);
+    let starts = InstructionStarts::new(code.bytecode);
+    let mut pc = 0;
+    while (pc as usize) < code.bytecode.len() {
+        let (instruction, next_pc) = match decode_next(code.bytecode, pc) {
+            Ok(rv) => rv,
+            Err(error) => {
+                // can't find the next instruction, so give up on this block.
+                report(error);
+                return;
+            }
+        };
+
+        let problem = if !Opcode::is_valid(code.bytecode[pc as usize]) {
+            Some(ErrorCode::UnknownOpcode)
+        } else {
+            match instruction.opcode {
+                Opcode::LoadLocalN | Opcode::StoreLocalN => {
+                    if instruction.n1 < 0 || instruction.n1 >= code.local_count as isize {
+                        Some(ErrorCode::LocalsOverflow)
+                    } else {
+                        None
+                    }
+                },
+                Opcode::CopyLocal => {
+                    let local_count = code.local_count as isize;
+                    if instruction.n1 < 0 || instruction.n1 >= local_count || instruction.n2 < 0 || instruction.n2 >= local_count {
+                        Some(ErrorCode::LocalsOverflow)
+                    } else {
+                        None
+                    }
+                },
+                Opcode::Constant => {
+                    if instruction.n1 < 0 || instruction.n1 as usize >= words { Some(ErrorCode::OutOfBounds) } else { None }
+                },
+                Opcode::ConstantBytes => {
+                    if instruction.n1 < 0 || instruction.n1 as usize >= words || pool.get_bytes(instruction.n1 as u32).is_none() {
+                        Some(ErrorCode::OutOfBounds)
+                    } else {
+                        None
+                    }
+                },
+                Opcode::Jump | Opcode::JumpIfPointer | Opcode::JumpIfZero | Opcode::JumpIfNonZero | Opcode::JumpRel => {
+                    if instruction.jump_target().is_some_and(|target| starts.contains(target)) {
+                        None
+                    } else {
+                        Some(ErrorCode::OutOfBounds)
+                    }
+                },
+                _ => None,
+            }
+        };
+        if let Some(error) = problem {
+            if !report(error) { return }
+        }
+        pc = next_pc;
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::constant_pool::ConstantPool;
+    use crate::error::ErrorCode;
+    use crate::opcode::Opcode;
+    use super::{verify_code, verify_image};
+
+    // two code blocks: [local 0 of 1; return], [jump into the middle of an immediate; unknown]
+    const POOL: [u8; 16] = [
+        1, 2, 4, 0, Opcode::LoadLocalN as u8, 0, Opcode::ReturnN as u8, 2,
+        0, 2, 4, 0, Opcode::Jump as u8, 1, 0x0f, Opcode::Return as u8,
+    ];
+
+    #[test]
+    fn verify_clean_block() {
+        let pool = ConstantPool::new(&POOL);
+        assert_eq!(verify_code(&pool, 0), Ok(()));
+        assert_eq!(verify_code(&pool, 2), Err(ErrorCode::OutOfBounds));
+    }
+
+    #[test]
+    fn verify_collects_problems() {
+        let pool = ConstantPool::new(&POOL);
+        let mut problems = [ (0, ErrorCode::Break); 4 ];
+        assert_eq!(verify_image(&pool, &[ 0, 2 ], &mut problems), 2);
+        assert_eq!(&problems[0 .. 2], &[ (2, ErrorCode::OutOfBounds), (2, ErrorCode::UnknownOpcode) ]);
+
+        // counts past the end of the buffer, but only keeps what fits.
+        let mut problems = [ (0, ErrorCode::Break); 1 ];
+        assert_eq!(verify_image(&pool, &[ 2, 2 ], &mut problems), 4);
+        assert_eq!(problems[0], (2, ErrorCode::OutOfBounds));
+    }
+}
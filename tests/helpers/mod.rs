@@ -1,7 +1,7 @@
 // helpers to make a runtime
 
 use core::mem;
-use mwrt::{Runtime, RuntimeError};
+use mwrt::{Runtime, RuntimeError, RuntimeOptions, encode_sint};
 
 const DEFAULT_GLOBALS: usize = 2;
 const DEFAULT_LOCALS: usize = 8;
@@ -26,6 +26,13 @@ impl Bytes {
         b
     }
 
+    // just the bytecode, without a code header
+    pub fn bytecode(codes: &[&[u8]]) -> Bytes {
+        let mut b = Bytes { data: [0; 128], index: 0 };
+        for c in codes { b.add(c) }
+        b
+    }
+
     pub fn basic_code(codes: &[&[u8]]) -> Bytes {
         Bytes::code(DEFAULT_LOCALS, DEFAULT_STACK, codes)
     }
@@ -51,18 +58,20 @@ impl Bytes {
         b
     }
 
-    // pub fn constant_sint(n: isize) -> Bytes {
-    //     let mut b = Bytes { data: [0; 128], index: 0 };
-    //     let mut raw: usize = if n >= 0 { (n as usize) << 1 } else { ((n as usize) << 1) ^ ((0 - 1) as usize) };
-    //     while raw > 128 {
-    //         b.data[b.index] = ((raw & 0x7f) as u8) | 0x80;
-    //         b.index += 1;
-    //         raw >>= 7;
-    //     }
-    //     b.data[b.index] = raw as u8;
-    //     b.index += 1;
-    //     b
-    // }
+    pub fn constant_le_u32(n: u32) -> Bytes {
+        Bytes::data(&n.to_le_bytes())
+    }
+
+    pub fn constant_le_u64(n: u64) -> Bytes {
+        Bytes::data(&n.to_le_bytes())
+    }
+
+    // zigzag encoded, the way immediates are stored
+    pub fn constant_sint(n: isize) -> Bytes {
+        let mut b = Bytes { data: [0; 128], index: 0 };
+        b.index = encode_sint(n, &mut b.data).unwrap();
+        b
+    }
 
     pub fn to_bytes(&self) -> &[u8] {
         &self.data[0 .. self.index]
@@ -78,6 +87,7 @@ pub struct Platform {
     constant_data: [u8; CONSTANT_POOL_SIZE],
     constant_index: usize,
     pub constant_offsets: [u32; 16],
+    constant_lengths: [usize; 16],
     constant_offsets_index: usize,
 }
 
@@ -88,24 +98,46 @@ impl Platform {
             constant_data: [0; CONSTANT_POOL_SIZE],
             constant_index: 0,
             constant_offsets: [0u32; 16],
+            constant_lengths: [0; 16],
             constant_offsets_index: 0,
         }
     }
 
     pub fn with(constants: &[Bytes]) -> Platform {
         let mut p = Platform::new();
-        for c in constants { p.add_constant(c.to_bytes()) }
+        for c in constants { p.add_constant(c.to_bytes()); }
         p
     }
 
-    pub fn add_constant(&mut self, data: &[u8]) {
+    // returns the constant's offset, which is shared with any identical constant added earlier
+    pub fn add_constant(&mut self, data: &[u8]) -> u32 {
+        let n = self.constant_offsets_index;
+        self.constant_offsets_index += 1;
+        for i in 0 .. n {
+            let start = self.constant_offsets[i] as usize;
+            if self.constant_lengths[i] == data.len() && &self.constant_data[start .. start + data.len()] == data {
+                self.constant_offsets[n] = self.constant_offsets[i];
+                self.constant_lengths[n] = data.len();
+                return self.get_constant(n);
+            }
+        }
+
         // align:
         let bits = mem::size_of::<usize>() - 1;
         self.constant_index = (self.constant_index + bits) & !bits;
-        self.constant_offsets[self.constant_offsets_index] = self.constant_index as u32;
-        self.constant_offsets_index += 1;
+        self.constant_offsets[n] = self.constant_index as u32;
+        self.constant_lengths[n] = data.len();
         for i in 0 .. data.len() { self.constant_data[self.constant_index + i] = data[i] }
         self.constant_index += data.len();
+        self.get_constant(n)
+    }
+
+    pub fn pool(&self) -> &[u8] {
+        &self.constant_data[0 .. self.constant_index]
+    }
+
+    pub fn pool_size(&self) -> usize {
+        self.constant_index
     }
 
     pub fn get_constant(&self, index: usize) -> u32 {
@@ -122,6 +154,11 @@ impl Platform {
         Runtime::new(pool, &mut self.heap_data, DEFAULT_GLOBALS, current_time)
     }
 
+    pub fn to_runtime_with(&mut self, options: RuntimeOptions) -> Result<Runtime, RuntimeError> {
+        let pool = &self.constant_data[0 .. self.constant_index];
+        Runtime::with_options(pool, &mut self.heap_data, RuntimeOptions { global_count: DEFAULT_GLOBALS, ..options })
+    }
+
     pub fn execute0(&mut self, code_index: u32, args: &[usize]) -> Result<(), RuntimeError> {
         let mut results: [usize; 16] = [ 0; 16 ];
         self.to_runtime().and_then(|mut r| r.execute(code_index, args, &mut results, None, None)).map(|count| {
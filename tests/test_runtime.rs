@@ -1,7 +1,12 @@
 mod helpers;
 
-use core::{num};
-use mwrt::{Binary, Opcode, Unary};
+use core::{mem, num};
+use mwgc::Heap;
+use mwrt::{
+    Binary, BreakAction, Code, ConstantPool, ErrorCode, ImageHeader, NativeFunction, Opcode, POISON, Reachability, Runtime, RuntimeOptions,
+    StepResult, Unary,
+    max_stack_depth, opcode_cost, reachable_code,
+};
 use helpers::{Bytes, Platform};
 
 const BINARY_ADD: &[u8] = &[ Opcode::Binary as u8, (Binary::Add as u8) << 1 ];
@@ -18,12 +23,29 @@ const BINARY_XOR: &[u8] = &[ Opcode::Binary as u8, (Binary::BitXor as u8) << 1 ]
 const BINARY_LSL: &[u8] = &[ Opcode::Binary as u8, (Binary::ShiftLeft as u8) << 1 ];
 const BINARY_LSR: &[u8] = &[ Opcode::Binary as u8, (Binary::ShiftRight as u8) << 1 ];
 const BINARY_ASR: &[u8] = &[ Opcode::Binary as u8, (Binary::SignShiftRight as u8) << 1 ];
+const BINARY_LAND: &[u8] = &[ Opcode::Binary as u8, (Binary::LogicalAnd as u8) << 1 ];
+const BINARY_LOR: &[u8] = &[ Opcode::Binary as u8, (Binary::LogicalOr as u8) << 1 ];
+const BINARY_GT: &[u8] = &[ Opcode::Binary as u8, (Binary::GreaterThan as u8) << 1 ];
+const BINARY_GE: &[u8] = &[ Opcode::Binary as u8, (Binary::GreaterOrEqual as u8) << 1 ];
+const BINARY_REQ: &[u8] = &[ Opcode::Binary as u8, (Binary::RefEquals as u8) << 1 ];
+const BINARY_ULT: &[u8] = &[ Opcode::Binary as u8, (Binary::UnsignedLessThan as u8) << 1 ];
+const BINARY_UDIV: &[u8] = &[ Opcode::Binary as u8, (Binary::UnsignedDivide as u8) << 1 ];
+const BINARY_UMOD: &[u8] = &[ Opcode::Binary as u8, (Binary::UnsignedModulo as u8) << 1 ];
+const BINARY_MIN: &[u8] = &[ Opcode::Binary as u8, (Binary::Min as u8) << 1 ];
+const BINARY_MAX: &[u8] = &[ Opcode::Binary as u8, (Binary::Max as u8) << 1 ];
+const BINARY_ADDC: &[u8] = &[ Opcode::Binary as u8, (Binary::AddChecked as u8) << 1 ];
+const BINARY_SUBC: &[u8] = &[ Opcode::Binary as u8, (Binary::SubtractChecked as u8) << 1 ];
+const BINARY_MULC: &[u8] = &[ Opcode::Binary as u8, (Binary::MultiplyChecked as u8) << 1 ];
 const BREAK: &[u8] = &[ Opcode::Break as u8 ];
 const CALL: &[u8] = &[ Opcode::Call as u8 ];
+const CAPTURE_STACK: &[u8] = &[ Opcode::CaptureStack as u8 ];
+const CALL_0: &[u8] = &[ Opcode::CallN as u8, 0 ];
 const CALL_1: &[u8] = &[ Opcode::CallN as u8, 2 ];
 const CONST_0: &[u8] = &[ Opcode::Constant as u8, 0 ];
 const DROP: &[u8] = &[ Opcode::Drop as u8 ];
 const DUP: &[u8] = &[ Opcode::Dup as u8 ];
+const EXCHANGE_SLOT: &[u8] = &[ Opcode::ExchangeSlot as u8 ];
+const FRAME_INFO: &[u8] = &[ Opcode::FrameInfo as u8 ];
 const IF: &[u8] = &[ Opcode::If as u8 ];
 const LOAD_GLOBAL_0: &[u8] = &[ Opcode::LoadGlobalN as u8, 0 ];
 const LOAD_GLOBAL_1: &[u8] = &[ Opcode::LoadGlobalN as u8, 2 ];
@@ -41,6 +63,7 @@ const NUM_30: &[u8] = &[ Opcode::Immediate as u8, 60 ];
 const NUM_64: &[u8] = &[ Opcode::Immediate as u8, 0x80, 1 ];
 const NUM_128: &[u8] = &[ Opcode::Immediate as u8, 0x80, 2 ];
 const RETURN: &[u8] = &[ Opcode::Return as u8 ];
+const RETURN_0: &[u8] = &[ Opcode::ReturnN as u8, 0 ];
 const RETURN_1: &[u8] = &[ Opcode::ReturnN as u8, 2 ];
 const SIZE: &[u8] = &[ Opcode::Size as u8 ];
 const SLOT: &[u8] = &[ Opcode::LoadSlot as u8 ];
@@ -57,14 +80,45 @@ const STORE_SLOT: &[u8] = &[ Opcode::StoreSlot as u8 ];
 const STORE_SLOT_0: &[u8] = &[ Opcode::StoreSlotN as u8, 0 ];
 // const STORE_SLOT_1: &[u8] = &[ Opcode::StoreSlotN as u8, 2 ];
 const STORE_SLOT_2: &[u8] = &[ Opcode::StoreSlotN as u8, 4 ];
+const FREE: &[u8] = &[ Opcode::FreeObject as u8 ];
+const IMMEDIATE: &[u8] = &[ Opcode::Immediate as u8 ];
+const SELECT: &[u8] = &[ Opcode::Select as u8 ];
+const SLOT_DIFF: &[u8] = &[ Opcode::SlotDiff as u8 ];
+const TYPE_OF: &[u8] = &[ Opcode::TypeOf as u8 ];
 const UNARY_NOT: &[u8] = &[ Opcode::Unary as u8, (Unary::Not as u8) << 1 ];
 const UNARY_NEG: &[u8] = &[ Opcode::Unary as u8, (Unary::Negative as u8) << 1 ];
 const UNARY_BITNOT: &[u8] = &[ Opcode::Unary as u8, (Unary::BitNot as u8) << 1 ];
+const UNARY_ABS: &[u8] = &[ Opcode::Unary as u8, (Unary::Abs as u8) << 1 ];
+
+const fn room(n: u8) -> [u8; 2] {
+    [ Opcode::StackRoom as u8, n << 1 ]
+}
+
+const fn saddr(n: u8) -> [u8; 2] {
+    [ Opcode::StackAddrN as u8, n << 1 ]
+}
+
+const fn new_tagged(tag: u8) -> [u8; 2] {
+    [ Opcode::NewTaggedN as u8, tag << 1 ]
+}
+
+const fn field(offset: u8, width: u8) -> [u8; 3] {
+    [ Opcode::LoadField as u8, offset << 1, width << 1 ]
+}
 
 const fn jump(offset: u8) -> [u8; 2] {
     [ Opcode::Jump as u8, offset << 1 ]
 }
 
+// zigzag-encoded, so it can go backwards
+const fn jrel(offset: i8) -> [u8; 2] {
+    [ Opcode::JumpRel as u8, ((offset << 1) ^ (offset >> 7)) as u8 ]
+}
+
+const fn jptr(offset: u8) -> [u8; 2] {
+    [ Opcode::JumpIfPointer as u8, offset << 1 ]
+}
+
 
 #[test]
 fn out_of_memory() {
@@ -72,28 +126,177 @@ fn out_of_memory() {
     assert_eq!(format!("{:?}", p.execute0(0, &[])), "Err(OutOfMemory)");
 }
 
+#[test]
+fn no_room_for_globals() {
+    let mut heap_data: [u8; 64] = [ 0; 64 ];
+    let rv = Runtime::new(&[], &mut heap_data, 100, None);
+    assert_eq!(format!("{:?}", rv.err()), "Some(GlobalsAllocationFailed)");
+}
+
 #[test]
 fn unknown() {
     let mut p = Platform::with(&[ Bytes::basic_code(&[ &[ 0xff ] ]) ]);
-    assert_eq!(format!("{:?}", p.execute0(0, &[])), "Err(UnknownOpcode at [frame code=0 pc=0 sp=0])");
+    assert_eq!(format!("{:?}", p.execute0(0, &[])), "Err(UnknownOpcode(0xff) at [frame code=0 pc=0 sp=0])");
+
+    // gaps between opcodes are unknown too, and the error shows the real byte.
+    assert_eq!(Opcode::from_u8(0x0f), Opcode::Unknown);
+    assert_eq!(Opcode::from_u8(Opcode::Jump as u8), Opcode::Jump);
+    assert_eq!(Unary::from_usize(4), Unary::Unknown);
+    assert_eq!(Binary::from_usize(50), Binary::Unknown);
+    assert_eq!(Binary::from_usize(Binary::LogicalOr as usize), Binary::LogicalOr);
+    let mut p = Platform::with(&[ Bytes::basic_code(&[ &[ 0x3f ] ]) ]);
+    assert_eq!(format!("{:?}", p.execute0(0, &[])), "Err(UnknownOpcode(0x3f) at [frame code=0 pc=0 sp=0])");
 }
 
 #[test]
 fn incomplete_immediate() {
     let mut p = Platform::with(&[ Bytes::basic_code(&[ &[ Opcode::Immediate as u8, 0x80 ] ]) ]);
-    assert_eq!(format!("{:?}", p.execute0(0, &[])), "Err(TruncatedCode at [frame code=0 pc=0 sp=0])");
+    assert_eq!(format!("{:?}", p.execute0(0, &[])), "Err(TruncatedCode(0x10) at [frame code=0 pc=0 sp=0])");
+}
+
+#[test]
+fn overlong_immediate() {
+    // more continuation bytes than any word can hold, even with more code after:
+    let mut p = Platform::with(&[ Bytes::basic_code(&[
+        &[ Opcode::Immediate as u8, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x01 ], RETURN_1,
+    ]) ]);
+    assert_eq!(format!("{:?}", p.execute0(0, &[])), "Err(MalformedVarint(0x10) at [frame code=0 pc=0 sp=0])");
 }
 
 #[test]
 fn debugger_break() {
     let mut p = Platform::with(&[ Bytes::basic_code(&[ BREAK ]) ]);
-    assert_eq!(format!("{:?}", p.execute0(0, &[])), "Err(Break at [frame code=0 pc=0 sp=0])");
+    assert_eq!(format!("{:?}", p.execute0(0, &[])), "Err(Break(0x00) at [frame code=0 pc=0 sp=0])");
+}
+
+#[test]
+fn resume_after_break() {
+    let mut p = Platform::with(&[ Bytes::basic_code(&[ NUM_30, BREAK, NUM_1, BINARY_ADD, RETURN_1 ]) ]);
+    let mut r = p.to_runtime().unwrap();
+    let mut results = [ 0 as usize; 1 ];
+    assert_eq!(format!("{:?}", r.execute(0, &[], &mut results, None, None)), "Err(Break(0x00) at [frame code=0 pc=2 sp=1])");
+    assert_eq!(r.intercepted_call(), None);
+    assert_eq!(r.resume(&mut results, None, None).ok(), Some(1));
+    assert_eq!(results[0], 31);
+    assert_eq!(format!("{:?}", r.resume(&mut results, None, None)), "Err(NotSuspended)");
+
+    // there's no call to fake a return from.
+    assert!(r.execute(0, &[], &mut results, None, None).is_err());
+    assert_eq!(format!("{:?}", r.resume_with(&[ 1 ], &mut results, None, None)), "Err(NotSuspended)");
+    assert_eq!(r.resume(&mut results, None, None).ok(), Some(1));
+}
+
+#[test]
+fn single_step() {
+    let mut p = Platform::with(&[ Bytes::basic_code(&[ NUM_1, NUM_2, BINARY_ADD, NUM_0, IF, NUM_30, RETURN_1 ]) ]);
+    let mut r = p.to_runtime().unwrap();
+    let mut context = r.start(0, &[]).unwrap();
+    let steps: &[(Opcode, &[usize])] = &[
+        (Opcode::Immediate, &[ 1 ]),
+        (Opcode::Immediate, &[ 1, 2 ]),
+        (Opcode::Binary, &[ 3 ]),
+        (Opcode::Immediate, &[ 3, 0 ]),
+        // skips the `NUM_30` too.
+        (Opcode::If, &[ 3 ]),
+    ];
+    for &(opcode, stack) in steps {
+        match r.step(&mut context) {
+            Ok(StepResult::Ran(instruction)) => assert_eq!(instruction.opcode, opcode),
+            _ => panic!("didn't step"),
+        }
+        assert_eq!(context.stack(), stack);
+    }
+    match r.step(&mut context) {
+        Ok(StepResult::Returned(values)) => assert_eq!(values, [ 3 ]),
+        _ => panic!("didn't return"),
+    }
+    // it's done now.
+    assert_eq!(r.step(&mut context).err().map(|e| e.code), Some(ErrorCode::NotStepping));
+}
+
+#[test]
+fn step_across_collection() {
+    let mut p = Platform::with(&[
+        // make garbage until it's collected:
+        Bytes::basic_code(&[
+            LOAD_LOCAL_0, &[ Opcode::NewNN as u8, 16, 2 ], STORE_LOCAL_1,
+            &[ Opcode::DecTestLocalN as u8, 0 ], &[ Opcode::JumpIfZero as u8, 0 ], RETURN_0,
+        ]),
+        Bytes::basic_code(&[ NUM_1, NUM_2, BINARY_ADD, RETURN_1 ]),
+    ]);
+    let (garbage, add) = (p.get_constant(0), p.get_constant(1));
+    let mut r = p.to_runtime().unwrap();
+    let mut results = [ 0 as usize; 1 ];
+    let mut context = r.start(add, &[]).unwrap();
+    assert!(r.step(&mut context).is_ok());
+    // the frame being stepped is still in use, so it's not collected.
+    assert_eq!(r.execute(garbage, &[ 50 ], &mut results, None, None).ok(), Some(0));
+    assert!(r.step(&mut context).is_ok());
+    assert_eq!(context.stack(), [ 1, 2 ]);
+    assert!(r.step(&mut context).is_ok());
+    match r.step(&mut context) {
+        Ok(StepResult::Returned(values)) => assert_eq!(values, [ 3 ]),
+        _ => panic!("didn't return"),
+    }
+
+    // a reset lets it go, so it can't be stepped anymore.
+    let mut context = r.start(add, &[]).unwrap();
+    assert!(r.set_local(&mut context, 0, 30).is_ok());
+    assert_eq!(context.locals()[0], 30);
+    assert_eq!(r.set_local(&mut context, 8, 30).err().map(|e| e.code), Some(ErrorCode::LocalsOverflow));
+    r.soft_reset();
+    assert_eq!(r.step(&mut context).err().map(|e| e.code), Some(ErrorCode::NotStepping));
+    assert_eq!(r.set_local(&mut context, 0, 30).err().map(|e| e.code), Some(ErrorCode::NotStepping));
+    // and so does starting another.
+    let mut first = r.start(add, &[]).unwrap();
+    let mut second = r.start(add, &[]).unwrap();
+    assert_eq!(r.step(&mut first).err().map(|e| e.code), Some(ErrorCode::NotStepping));
+    assert!(r.step(&mut second).is_ok());
+}
+
+#[test]
+fn trace() {
+    let mut p = Platform::with(&[ Bytes::basic_code(&[ NUM_1, NUM_2, BINARY_ADD, NUM_1, IF, NUM_30, RETURN_1 ]) ]);
+    let mut r = p.to_runtime().unwrap();
+    let mut results = [ 0 as usize; 1 ];
+    let mut seen = [ (Opcode::Unknown, 0, 0); 8 ];
+    let mut count = 0;
+    let rv = r.execute_traced(0, &[], &mut results, None, None, &mut |instruction, context| {
+        seen[count] = (instruction.opcode, context.pc(), context.stack().len());
+        count += 1;
+    });
+    assert_eq!(rv.ok(), Some(1));
+    assert_eq!(&seen[0 .. count], &[
+        (Opcode::Immediate, 0, 0), (Opcode::Immediate, 2, 1), (Opcode::Binary, 4, 2), (Opcode::Immediate, 6, 1),
+        (Opcode::If, 8, 2), (Opcode::Immediate, 9, 1), (Opcode::ReturnN, 11, 2),
+    ]);
+}
+
+#[test]
+fn break_handler() {
+    fn keep_going(_code_offset: u32, pc: u16) -> BreakAction {
+        assert_eq!(pc, 3);
+        BreakAction::Continue
+    }
+    fn bail(_code_offset: u32, _pc: u16) -> BreakAction {
+        BreakAction::Return
+    }
+
+    let mut p = Platform::with(&[ Bytes::basic_code(&[ NUM_128, BREAK, NUM_1, RETURN ]) ]);
+    let mut r = p.to_runtime_with(RuntimeOptions { break_handler: Some(keep_going), ..RuntimeOptions::default() }).unwrap();
+    let mut results = [ 0 as usize; 1 ];
+    assert_eq!(r.execute(0, &[], &mut results, None, None).ok(), Some(1));
+    assert_eq!(results[0], 128);
+
+    let mut p = Platform::with(&[ Bytes::basic_code(&[ BREAK, NUM_128, NUM_1, RETURN ]) ]);
+    let mut r = p.to_runtime_with(RuntimeOptions { break_handler: Some(bail), ..RuntimeOptions::default() }).unwrap();
+    assert_eq!(r.execute(0, &[], &mut results, None, None).ok(), Some(0));
 }
 
 #[test]
 fn skip_nop() {
     let mut p = Platform::with(&[ Bytes::basic_code(&[ NOP, BREAK ]) ]);
-    assert_eq!(format!("{:?}", p.execute0(0, &[])), "Err(Break at [frame code=0 pc=1 sp=0])");
+    assert_eq!(format!("{:?}", p.execute0(0, &[])), "Err(Break(0x00) at [frame code=0 pc=1 sp=0])");
 }
 
 #[test]
@@ -102,12 +305,187 @@ fn immediate_and_return() {
     assert_eq!(p.execute1(0, &[]).ok(), Some(128));
 }
 
+#[test]
+fn no_room_for_results() {
+    let mut p = Platform::with(&[ Bytes::basic_code(&[ NUM_128, RETURN_1 ]) ]);
+    let mut r = p.to_runtime().unwrap();
+    assert_eq!(format!("{:?}", r.execute(0, &[], &mut [], None, None)), "Err(ResultsOverflow(0x1b) at [frame code=0 pc=3 sp=0])");
+
+    // returning nothing is fine.
+    let mut p = Platform::with(&[ Bytes::basic_code(&[ NUM_0, RETURN ]) ]);
+    let mut r = p.to_runtime().unwrap();
+    assert_eq!(r.execute(0, &[], &mut [], None, None).ok(), Some(0));
+
+    // some room isn't enough: the caller would read past what was written.
+    let mut p = Platform::with(&[ Bytes::basic_code(&[ NUM_1, NUM_2, NUM_30, NUM_128, NUM_2, NUM_2, BINARY_ADD, RETURN ]) ]);
+    let mut r = p.to_runtime().unwrap();
+    let mut results = [ 0 as usize; 2 ];
+    assert_eq!(r.execute(0, &[], &mut results, None, None).err().map(|e| e.code), Some(ErrorCode::ResultsOverflow));
+    assert_eq!(results, [ 0, 0 ]);
+    let mut results = [ 0 as usize; 4 ];
+    assert_eq!(r.execute(0, &[], &mut results, None, None).ok(), Some(4));
+    assert_eq!(results, [ 1, 2, 30, 128 ]);
+}
+
+#[test]
+fn negative_immediate() {
+    let mut p = Platform::with(&[ Bytes::basic_code(&[ NUM_N30, RETURN_1 ]) ]);
+    assert_eq!(p.execute1(0, &[]).ok(), Some((-30 as isize) as usize));
+
+    let mut p = Platform::with(&[ Bytes::basic_code(&[ NUM_N30, UNARY_NEG, RETURN_1 ]) ]);
+    assert_eq!(p.execute1(0, &[]).ok(), Some(30));
+
+    let mut p = Platform::with(&[ Bytes::basic_code(&[ NUM_30, UNARY_NEG, NUM_N30, BINARY_EQ, RETURN_1 ]) ]);
+    assert_eq!(p.execute1(0, &[]).ok(), Some(1));
+
+    // the full word width survives the trip through zigzag
+    let min = Bytes::constant_sint(core::isize::MIN);
+    let max = Bytes::constant_sint(core::isize::MAX);
+    let mut p = Platform::with(&[ Bytes::basic_code(&[
+        IMMEDIATE, min.to_bytes(), IMMEDIATE, max.to_bytes(), BINARY_ADD, RETURN_1
+    ]) ]);
+    assert_eq!(p.execute1(0, &[]).ok(), Some(core::usize::MAX));
+}
+
+#[test]
+fn shared_heap() {
+    let mut p = Platform::with(&[ Bytes::basic_code(&[ NUM_128, NUM_30, NUM_2, NUM_2, NEW, DUP, SLOT_1, NUM_2, RETURN ]) ]);
+    let pool = p.pool().to_vec();
+    let mut heap_data: [u8; 512] = [ 0; 512 ];
+    let mut heap = Heap::from_bytes(&mut heap_data);
+    let foreign = heap.allocate_array::<usize>(4).unwrap();
+    for i in 0 .. 4 { foreign[i] = 1000 + i; }
+
+    let mut r = Runtime::with_heap(&pool, heap, RuntimeOptions { global_count: 2, ..RuntimeOptions::default() }).unwrap();
+    let mut results = [ 0 as usize; 2 ];
+    assert_eq!(r.execute(0, &[], &mut results, None, None).ok(), Some(2));
+    assert_eq!(results[1], 30);
+    assert_eq!(r.object_size(results[0]).ok(), Some(2));
+    assert_ne!(results[0], foreign.as_ptr() as usize);
+    assert_eq!(foreign, &[ 1000, 1001, 1002, 1003 ]);
+}
+
+#[test]
+fn pc_limit() {
+    let mut heap_data: [u8; 256] = [ 0; 256 ];
+    let mut results = [ 0 as usize; 1 ];
+
+    // running off the end of the biggest possible code block is fine.
+    let bytecode = vec![ Opcode::Nop as u8; core::u16::MAX as usize ];
+    let mut r = Runtime::new(&[], &mut heap_data, 0, None).unwrap();
+    assert_eq!(r.execute_code(Code::new(0, 4, &bytecode), &[], &mut results, None, None).ok(), Some(0));
+
+    // but one more byte can't be reached by a 16-bit pc.
+    let bytecode = vec![ Opcode::Nop as u8; core::u16::MAX as usize + 1 ];
+    let mut r = Runtime::new(&[], &mut heap_data, 0, None).unwrap();
+    assert_eq!(
+        format!("{:?}", r.execute_code(Code::new(0, 4, &bytecode), &[], &mut results, None, None)),
+        "Err(OutOfBounds(0x01) at [frame code=ffffffff pc=ffff sp=0])"
+    );
+
+    // a jump past 16 bits doesn't wrap around to the start.
+    let far = Bytes::constant_sint(core::u16::MAX as isize + 2);
+    let mut p = Platform::with(&[ Bytes::basic_code(&[ &[ Opcode::Jump as u8 ], far.to_bytes(), NUM_0, RETURN ]) ]);
+    assert_eq!(format!("{:?}", p.execute0(0, &[])), "Err(OutOfBounds(0x1c) at [frame code=0 pc=0 sp=0])");
+}
+
+#[test]
+fn execute_code_directly() {
+    let bytecode = Bytes::bytecode(&[ NUM_128, NUM_1, RETURN ]);
+    let mut heap_data: [u8; 256] = [ 0; 256 ];
+    let mut r = Runtime::new(&[], &mut heap_data, 0, None).unwrap();
+    let mut results = [ 0 as usize; 4 ];
+    assert_eq!(r.execute_code(Code::new(0, 4, bytecode.to_bytes()), &[], &mut results, None, None).ok(), Some(1));
+    assert_eq!(results[0], 128);
+
+    // can't call out of it:
+    let bytecode = Bytes::bytecode(&[ NUM_0, NUM_0, CALL ]);
+    let rv = r.execute_code(Code::new(0, 4, bytecode.to_bytes()), &[], &mut results, None, None);
+    assert_eq!(format!("{:?}", rv), "Err(InvalidCodeObject(0x04) at [frame code=ffffffff pc=4 sp=0])");
+
+    // too many locals or too much stack, which the constant pool wouldn't allow either:
+    let bytecode = Bytes::bytecode(&[ NUM_0, RETURN_1 ]);
+    let rv = r.execute_code(Code::new(200, 100, bytecode.to_bytes()), &[], &mut results, None, None);
+    assert_eq!(format!("{:?}", rv), "Err(InvalidCodeObject)");
+    let rv = r.execute_code(Code::new(0, 64, bytecode.to_bytes()), &[], &mut results, None, None);
+    assert_eq!(format!("{:?}", rv), "Err(InvalidCodeObject)");
+}
+
+#[test]
+fn final_stack_depth() {
+    let mut p = Platform::with(&[ Bytes::basic_code(&[ NUM_30, NUM_128, RETURN_1 ]) ]);
+    let mut r = p.to_runtime().unwrap();
+    let mut results = [ 0 as usize; 4 ];
+    assert_eq!(r.execute(0, &[], &mut results, None, None).ok(), Some(1));
+    assert_eq!(results[0], 128);
+    assert_eq!(r.final_stack_depth(), 1);
+
+    let mut p = Platform::with(&[ Bytes::basic_code(&[ NUM_30, RETURN_1 ]) ]);
+    let mut r = p.to_runtime().unwrap();
+    assert_eq!(r.execute(0, &[], &mut results, None, None).ok(), Some(1));
+    assert_eq!(r.final_stack_depth(), 0);
+}
+
 #[test]
 fn immediate_dup_and_return() {
     let mut p = Platform::with(&[ Bytes::basic_code(&[ NUM_128, DUP, NUM_2, RETURN ]) ]);
     assert_eq!(p.execute2(0, &[]).ok(), Some((128, 128)));
 }
 
+#[test]
+fn immediate_swap_and_return() {
+    let swap = &[ Opcode::Swap as u8 ][..];
+    let mut p = Platform::with(&[ Bytes::basic_code(&[ NUM_128, NUM_30, swap, NUM_2, RETURN ]) ]);
+    assert_eq!(p.execute2(0, &[]).ok(), Some((30, 128)));
+
+    let mut p = Platform::with(&[ Bytes::basic_code(&[ NUM_128, swap, NUM_2, RETURN ]) ]);
+    assert_eq!(p.execute2(0, &[]).err().map(|e| e.code), Some(ErrorCode::StackUnderflow));
+}
+
+#[test]
+fn immediate_over_and_return() {
+    let over = &[ Opcode::Over as u8 ][..];
+    let mut p = Platform::with(&[ Bytes::basic_code(&[ NUM_128, NUM_30, over, NUM_2, NUM_1, BINARY_ADD, RETURN ]) ]);
+    let mut results = [ 0 as usize; 3 ];
+    let mut r = p.to_runtime().unwrap();
+    assert_eq!(r.execute(0, &[], &mut results, None, None).ok(), Some(3));
+    assert_eq!(results, [ 128, 30, 128 ]);
+
+    let mut p = Platform::with(&[ Bytes::basic_code(&[ NUM_128, over, NUM_2, RETURN ]) ]);
+    assert_eq!(p.execute2(0, &[]).err().map(|e| e.code), Some(ErrorCode::StackUnderflow));
+}
+
+#[test]
+fn immediate_rot_and_return() {
+    let rot = &[ Opcode::Rot as u8 ][..];
+    let mut p = Platform::with(&[ Bytes::basic_code(&[ NUM_1, NUM_30, NUM_128, rot, NUM_2, NUM_1, BINARY_ADD, RETURN ]) ]);
+    let mut results = [ 0 as usize; 3 ];
+    let mut r = p.to_runtime().unwrap();
+    assert_eq!(r.execute(0, &[], &mut results, None, None).ok(), Some(3));
+    assert_eq!(results, [ 30, 128, 1 ]);
+
+    let mut p = Platform::with(&[ Bytes::basic_code(&[ NUM_1, NUM_30, rot, NUM_2, RETURN ]) ]);
+    assert_eq!(p.execute2(0, &[]).err().map(|e| e.code), Some(ErrorCode::StackUnderflow));
+}
+
+#[test]
+fn immediate_pick_and_return() {
+    let pick = |n: u8| [ Opcode::PickN as u8, n << 1 ];
+    let mut p = Platform::with(&[
+        Bytes::basic_code(&[ NUM_128, NUM_30, &pick(0), NUM_2, NUM_1, BINARY_ADD, RETURN ]),
+        Bytes::basic_code(&[ NUM_128, NUM_30, NUM_1, &pick(2), NUM_2, NUM_2, BINARY_ADD, RETURN ]),
+        Bytes::basic_code(&[ NUM_128, NUM_30, &pick(2), NUM_2, RETURN ]),
+    ]);
+    let (deeper, too_deep) = (p.get_constant(1), p.get_constant(2));
+    let mut results = [ 0 as usize; 4 ];
+    let mut r = p.to_runtime().unwrap();
+    assert_eq!(r.execute(0, &[], &mut results, None, None).ok(), Some(3));
+    assert_eq!(results[0 .. 3], [ 128, 30, 30 ]);
+    assert_eq!(r.execute(deeper, &[], &mut results, None, None).ok(), Some(4));
+    assert_eq!(results, [ 128, 30, 1, 128 ]);
+    assert_eq!(r.execute(too_deep, &[], &mut results, None, None).err().map(|e| e.code), Some(ErrorCode::StackUnderflow));
+}
+
 #[test]
 fn immediate_drop_and_return() {
     let mut p = Platform::with(&[ Bytes::basic_code(&[ NUM_128, NUM_30, DROP, NUM_1, RETURN ]) ]);
@@ -120,6 +498,17 @@ fn constant_and_return() {
     assert_eq!(p.execute1(p.get_constant(1), &[]).ok(), Some(300));
 }
 
+#[test]
+fn constant_out_of_range() {
+    let past_end = Bytes::constant_sint(64);
+    let mut p = Platform::with(&[ Bytes::basic_code(&[ NUM_0, &[ Opcode::Constant as u8 ], past_end.to_bytes(), RETURN_1 ]) ]);
+    assert!(p.pool_size() <= 64 * 4);
+    assert_eq!(format!("{:?}", p.execute1(0, &[])), "Err(InvalidAddress(0x11) at [frame code=0 pc=2 sp=1])");
+
+    let mut p = Platform::with(&[ Bytes::basic_code(&[ &[ Opcode::Constant as u8, 1 ], RETURN_1 ]) ]);
+    assert_eq!(format!("{:?}", p.execute1(0, &[])), "Err(InvalidAddress(0x11) at [frame code=0 pc=0 sp=0])");
+}
+
 #[test]
 fn new_object_and_load_slot() {
     let mut p = Platform::with(&[ Bytes::basic_code(&[ NUM_128, NUM_2, NEW_3_2, SLOT_0, NUM_1, RETURN ]) ]);
@@ -141,23 +530,154 @@ fn new_object_and_load_slot() {
     assert_eq!(p.execute1(0, &[]).ok(), Some(0));
 }
 
+#[test]
+fn indexed_slots() {
+    let load_indexed = &[ Opcode::LoadIndexed as u8 ][..];
+    let store_indexed = &[ Opcode::StoreIndexed as u8 ][..];
+    let load = |index: &[u8]| Bytes::basic_code(&[ NUM_1, NUM_2, NEW_3_2, index, load_indexed, RETURN_1 ]);
+    let mut p = Platform::with(&[ load(NUM_0), load(NUM_1), load(NUM_30), load(NUM_N1) ]);
+    assert_eq!(p.execute1(p.get_constant(0), &[]).ok(), Some(1));
+    assert_eq!(p.execute1(p.get_constant(1), &[]).ok(), Some(2));
+    assert_eq!(p.execute1(p.get_constant(2), &[]).err().map(|e| e.code), Some(ErrorCode::OutOfBounds));
+    assert_eq!(p.execute1(p.get_constant(3), &[]).err().map(|e| e.code), Some(ErrorCode::OutOfBounds));
+
+    let store = |index: &[u8]| {
+        Bytes::basic_code(&[ NUM_1, NUM_2, NEW_3_2, DUP, index, NUM_30, store_indexed, index, load_indexed, RETURN_1 ])
+    };
+    let mut p = Platform::with(&[ store(NUM_2), store(NUM_30), store(NUM_N1) ]);
+    assert_eq!(p.execute1(p.get_constant(0), &[]).ok(), Some(30));
+    assert_eq!(p.execute1(p.get_constant(1), &[]).err().map(|e| e.code), Some(ErrorCode::OutOfBounds));
+    assert_eq!(p.execute1(p.get_constant(2), &[]).err().map(|e| e.code), Some(ErrorCode::OutOfBounds));
+}
+
+#[test]
+fn freeze() {
+    let freeze = &[ Opcode::Freeze as u8 ][..];
+    let mut p = Platform::with(&[
+        Bytes::basic_code(&[ NUM_1, NUM_2, NEW_3_2, DUP, freeze, SLOT_1, RETURN_1 ]),
+        Bytes::basic_code(&[ NUM_1, NUM_2, NEW_3_2, DUP, freeze, NUM_30, STORE_SLOT_0, RETURN_0 ]),
+        Bytes::basic_code(&[ NUM_1, NUM_2, NEW_3_2, DUP, freeze, NUM_0, NUM_30, EXCHANGE_SLOT, RETURN_1 ]),
+        // other objects are still fine.
+        Bytes::basic_code(&[ NUM_1, NUM_2, NEW_3_2, freeze, NUM_1, NUM_2, NEW_3_2, DUP, NUM_30, STORE_SLOT_0, SLOT_0, RETURN_1 ]),
+    ]);
+    assert_eq!(p.execute1(p.get_constant(0), &[]).ok(), Some(2));
+    assert_eq!(p.execute0(p.get_constant(1), &[]).err().map(|e| e.code), Some(ErrorCode::Immutable));
+    assert_eq!(p.execute1(p.get_constant(2), &[]).err().map(|e| e.code), Some(ErrorCode::Immutable));
+    assert_eq!(p.execute1(p.get_constant(3), &[]).ok(), Some(30));
+
+    // there's no fixed limit on how many objects can be frozen.
+    let mut r = p.to_runtime().unwrap();
+    let mut objects = [ 0 as usize; 10 ];
+    for obj in objects.iter_mut() {
+        *obj = r.alloc_object(1).unwrap();
+        assert_eq!(r.freeze(*obj), Ok(()));
+    }
+    for &obj in objects.iter() { assert_eq!(r.write_object(obj, 0, 30), Err(ErrorCode::Immutable)); }
+}
+
 #[test]
 fn new_object_errors() {
     // 128 is too big
     let mut p = Platform::with(&[ Bytes::basic_code(&[ NUM_128, NUM_0, NEW, SLOT_0, NUM_1, RETURN ]) ]);
-    assert_eq!(format!("{:?}", p.execute1(0, &[])), "Err(InvalidSize at [frame code=0 pc=5 sp=0])");
+    assert_eq!(format!("{:?}", p.execute1(0, &[])), "Err(InvalidSize(0x06) at [frame code=0 pc=5 sp=0])");
 
     // more slots to fill than are allocated
     let mut p = Platform::with(&[ Bytes::basic_code(&[ NUM_1, NUM_2, NEW, SLOT_0, NUM_1, RETURN ]) ]);
-    assert_eq!(format!("{:?}", p.execute1(0, &[])), "Err(OutOfBounds at [frame code=0 pc=4 sp=0])");
+    assert_eq!(format!("{:?}", p.execute1(0, &[])), "Err(OutOfBounds(0x06) at [frame code=0 pc=4 sp=0])");
 
     // we made a heap that can't actually hold a 64-slot object and also any stack frame at all
     let mut p = Platform::with(&[ Bytes::basic_code(&[ NUM_64, NUM_0, NEW, SLOT_0, NUM_1, RETURN ]) ]);
-    assert_eq!(format!("{:?}", p.execute1(0, &[])), "Err(OutOfMemory at [frame code=0 pc=5 sp=0])");
+    assert_eq!(format!("{:?}", p.execute1(0, &[])), "Err(OutOfMemory(0x06) at [frame code=0 pc=5 sp=0])");
 
     // there aren't enough fields on the stack
     let mut p = Platform::with(&[ Bytes::basic_code(&[ NUM_64, NUM_2, NUM_2, NEW, SLOT_0, NUM_1, RETURN ]) ]);
-    assert_eq!(format!("{:?}", p.execute1(0, &[])), "Err(StackUnderflow at [frame code=0 pc=7 sp=1])");
+    assert_eq!(format!("{:?}", p.execute1(0, &[])), "Err(StackUnderflow(0x06) at [frame code=0 pc=7 sp=1])");
+}
+
+#[test]
+fn new_object_fill() {
+    // every slot filled, in the order they were pushed
+    let num_3: &[u8] = &[ Opcode::Immediate as u8, 6 ];
+    let code: &[&[u8]] = &[
+        NUM_30, NUM_1, NUM_2, num_3, num_3, NEW, DUP, SLOT_0, STORE_LOCAL_0, DUP, SLOT_1, STORE_LOCAL_1, SLOT_2,
+        LOAD_LOCAL_0, LOAD_LOCAL_1, &[ Opcode::ReturnN as u8, 6 ]
+    ];
+    let mut p = Platform::with(&[ Bytes::basic_code(code) ]);
+    let mut r = p.to_runtime_with(RuntimeOptions { poison_objects: true, ..RuntimeOptions::default() }).unwrap();
+    let mut results = [ 0 as usize; 3 ];
+    assert_eq!(r.execute(0, &[], &mut results, None, None).ok(), Some(3));
+    // slot 2, then slot 0 and slot 1 from the locals:
+    assert_eq!(results, [ 2, 30, 1 ]);
+
+    // nothing filled
+    let mut p = Platform::with(&[ Bytes::basic_code(&[ NUM_30, NUM_2, NUM_0, NEW, DUP, SLOT_0, STORE_LOCAL_0, SLOT_1, LOAD_LOCAL_0, &[ Opcode::ReturnN as u8, 6 ] ]) ]);
+    let mut r = p.to_runtime().unwrap();
+    assert_eq!(r.execute(0, &[], &mut results, None, None).ok(), Some(3));
+    assert_eq!(results, [ 30, 0, 0 ]);
+}
+
+#[test]
+fn host_object_slots() {
+    let mut p = Platform::with(&[ Bytes::basic_code(&[
+        LOAD_LOCAL_0, NUM_30, STORE_SLOT_0, LOAD_LOCAL_0, NUM_128, STORE_SLOT_2, NUM_0, RETURN
+    ]) ]);
+    let mut r = p.to_runtime().unwrap();
+    let obj = r.alloc_object(3).unwrap();
+    let mut results = [ 0 as usize; 1 ];
+    assert_eq!(r.execute(0, &[ obj ], &mut results, None, None).ok(), Some(0));
+    assert_eq!(&r.object_slots(obj).unwrap()[0 .. 3], &[ 30, 0, 128 ]);
+    assert_eq!(r.object_slots(obj + 1), Err(ErrorCode::Unaligned));
+    assert_eq!(r.object_slots(0x100), Err(ErrorCode::InvalidAddress));
+    // the middle of an object isn't an object:
+    assert_eq!(r.object_slots(obj + mem::size_of::<usize>()), Err(ErrorCode::InvalidAddress));
+
+    // constants run to the end of the pool.
+    let mut p = Platform::with(&[ Bytes::constant(300), Bytes::constant(301) ]);
+    let start = p.pool().as_ptr() as usize;
+    let r = p.to_runtime().unwrap();
+    assert_eq!(r.object_slots(start), Ok(&[ 300 as usize, 301 ][..]));
+}
+
+#[test]
+fn host_allocated_object() {
+    let mut p = Platform::with(&[ Bytes::basic_code(&[ LOAD_LOCAL_0, SLOT_2, LOAD_LOCAL_0, SLOT_0, NUM_2, RETURN ]) ]);
+    let mut r = p.to_runtime().unwrap();
+    let obj = r.alloc_object(3).unwrap();
+    r.store_slot(obj, 0, 30).unwrap();
+    r.store_slot(obj, 2, 128).unwrap();
+    let mut results = [ 0 as usize; 2 ];
+    assert_eq!(r.execute(0, &[ obj ], &mut results, None, None).ok(), Some(2));
+    assert_eq!(results, [ 128, 30 ]);
+
+    assert_eq!(r.alloc_object(65), Err(ErrorCode::InvalidSize));
+    // eventually, the heap runs out.
+    let rv = (0 .. 1000).map(|_| r.alloc_object(64)).find(|rv| rv.is_err());
+    assert_eq!(rv, Some(Err(ErrorCode::OutOfMemory)));
+}
+
+#[test]
+fn host_read_write_object() {
+    let mut p = Platform::with(&[
+        Bytes::basic_code(&[ NUM_30, NUM_128, NEW_3_2, RETURN_1 ]),
+        Bytes::basic_code(&[ LOAD_LOCAL_0, SLOT_2, RETURN_1 ]),
+    ]);
+    let (make, read) = (p.get_constant(0), p.get_constant(1));
+    let mut r = p.to_runtime().unwrap();
+    let mut results = [ 0 as usize; 1 ];
+    assert_eq!(r.execute(make, &[], &mut results, None, None).ok(), Some(1));
+    let obj = results[0];
+    assert_eq!(r.read_object(obj, 0), Ok(30));
+    assert_eq!(r.read_object(obj, 1), Ok(128));
+    // the object's size is rounded up to a whole heap block, of 4 words here.
+    assert_eq!(r.read_object(obj, 4), Err(ErrorCode::OutOfBounds));
+    assert_eq!(r.write_object(obj, 2, 99), Ok(()));
+    assert_eq!(r.write_object(obj, 4, 99), Err(ErrorCode::OutOfBounds));
+    assert_eq!(r.execute(read, &[ obj ], &mut results, None, None).ok(), Some(1));
+    assert_eq!(results[0], 99);
+
+    // only heap objects:
+    assert_eq!(r.read_object(0x100, 0), Err(ErrorCode::InvalidAddress));
+    assert_eq!(r.write_object(0x100, 0, 99), Err(ErrorCode::InvalidAddress));
 }
 
 #[test]
@@ -193,6 +713,27 @@ fn new_object_and_store_slot() {
     assert_eq!(p.execute1(0, &[]).ok(), Some(2));
 }
 
+#[test]
+fn exchange_slot() {
+    let mut p = Platform::with(&[ Bytes::basic_code(&[
+        NUM_128, NUM_2, NEW_3_2, DUP, NUM_0, NUM_30, EXCHANGE_SLOT, STORE_LOCAL_0, SLOT_0, LOAD_LOCAL_0, NUM_2, RETURN
+    ]) ]);
+    assert_eq!(p.execute2(0, &[]).ok(), Some((30, 128)));
+
+    // off the end of the heap
+    let mut p = Platform::with(&[ Bytes::basic_code(&[
+        NUM_128, NUM_2, NEW_3_2, NUM_128, NUM_30, EXCHANGE_SLOT, RETURN_1
+    ]) ]);
+    assert_eq!(format!("{:?}", p.execute1(0, &[])), "Err(InvalidAddress(0x31) at [frame code=0 pc=d sp=0])");
+
+    // constants are read-only
+    let mut p = Platform::with(&[
+        Bytes::constant(300),
+        Bytes::basic_code(&[ CONST_0, NUM_0, NUM_30, EXCHANGE_SLOT, RETURN_1 ]),
+    ]);
+    assert_eq!(format!("{:?}", p.execute1(p.get_constant(1), &[])), "Err(InvalidAddress(0x31) at [frame code=2 pc=6 sp=0])");
+}
+
 #[test]
 fn constant_object_and_load_slot() {
     let mut p = Platform::with(&[
@@ -208,12 +749,329 @@ fn constant_object_and_load_slot() {
     assert_eq!(p.execute1(p.get_constant(1), &[]).ok(), Some(6));
 }
 
+#[test]
+fn constant_bytes() {
+    let mut p = Platform::with(&[
+        Bytes::data(&[ 5, b'h', b'e', b'l', b'l', b'o' ]),
+        Bytes::basic_code(&[ &[ Opcode::ConstantBytes as u8, 0 ], NUM_2, RETURN ]),
+    ]);
+    let start = p.pool().as_ptr() as usize;
+    let (addr, len) = p.execute2(p.get_constant(1), &[]).unwrap();
+    assert_eq!(len, 5);
+    assert_eq!(&p.pool()[addr - start .. addr - start + len], b"hello");
+
+    // the length runs past the end of the pool:
+    let mut p = Platform::with(&[
+        Bytes::data(&[ 100, 1, 2, 3 ]),
+        Bytes::basic_code(&[ &[ Opcode::ConstantBytes as u8, 0 ], NUM_2, RETURN ]),
+    ]);
+    assert_eq!(
+        format!("{:?}", p.execute2(p.get_constant(1), &[]).err()),
+        "Some(InvalidAddress(0x4c) at [frame code=2 pc=0 sp=0])"
+    );
+}
+
+#[test]
+fn compare_and_concat_bytes() {
+    let mut p = Platform::with(&[
+        Bytes::data(&[ 5, b'h', b'e', b'l', b'l', b'o' ]),
+        Bytes::data(&[ 5, b'w', b'o', b'r', b'l', b'd' ]),
+    ]);
+    let start = p.pool().as_ptr() as usize;
+    let end = start + p.pool_size();
+    let (hello, world) = (start + ((p.get_constant(0) as usize) << 2) + 1, start + ((p.get_constant(1) as usize) << 2) + 1);
+    let mut r = p.to_runtime().unwrap();
+    // a copy in the heap, so the comparison isn't against the same bytes:
+    let hello2 = r.concat_bytes(hello, 5, world, 0).unwrap();
+    assert_ne!(hello2, hello);
+
+    assert_eq!(r.bytes_equal(hello, 5, hello2, 5), Ok(true));
+    assert_eq!(r.bytes_equal(hello, 5, world, 5), Ok(false));
+    assert_eq!(r.bytes_equal(hello, 5, hello2, 4), Ok(false));
+    assert_eq!(r.bytes_equal(hello, 0, world, 0), Ok(true));
+    // past the end of the constant pool:
+    assert_eq!(r.bytes_equal(hello, 5, end - 2, 5), Err(ErrorCode::InvalidAddress));
+    assert_eq!(r.bytes_equal(0x100, 5, hello, 5), Err(ErrorCode::InvalidAddress));
+    // lengths that would wrap around the address space:
+    assert_eq!(r.bytes_equal(start + 1, usize::MAX - 2, hello, 5), Err(ErrorCode::InvalidAddress));
+    assert_eq!(r.bytes_equal(hello, 5, hello2, usize::MAX), Err(ErrorCode::InvalidAddress));
+}
+
+#[test]
+fn concat_bytes() {
+    let mut p = Platform::with(&[
+        Bytes::data(&[ 5, b'h', b'e', b'l', b'l', b'o' ]),
+        Bytes::data(&[ 5, b'w', b'o', b'r', b'l', b'd' ]),
+        Bytes::data(&[ 10, b'h', b'e', b'l', b'l', b'o', b'w', b'o', b'r', b'l', b'd' ]),
+    ]);
+    let start = p.pool().as_ptr() as usize;
+    let mut strings = [ 0 as usize; 3 ];
+    for i in 0 .. 3 { strings[i] = start + ((p.get_constant(i) as usize) << 2) + 1; }
+    let [ hello, world, hello_world ] = strings;
+    let mut r = p.to_runtime().unwrap();
+
+    let joined = r.concat_bytes(hello, 5, world, 5).unwrap();
+    assert_eq!(r.bytes_equal(joined, 10, hello_world, 10), Ok(true));
+    // and it can be joined again, from the heap:
+    let twice = r.concat_bytes(joined, 10, joined, 5).unwrap();
+    assert_eq!(r.bytes_equal(twice, 10, hello_world, 10), Ok(true));
+    assert_eq!(r.bytes_equal(twice + 10, 5, hello, 5), Ok(true));
+    // the rest of the last slot is zeroed:
+    let word = std::mem::size_of::<usize>();
+    assert_eq!(r.read_object(joined, 10 / word), Ok(u16::from_le_bytes([ b'l', b'd' ]) as usize));
+
+    // the heap object only has a block's worth of bytes.
+    assert_eq!(r.bytes_equal(joined, 17, hello_world, 17), Err(ErrorCode::InvalidAddress));
+    assert_eq!(r.concat_bytes(joined, 17, hello, 5), Err(ErrorCode::InvalidAddress));
+}
+
+#[test]
+fn constant_le_words() {
+    let mut p = Platform::with(&[
+        Bytes::constant_le_u32(0x8765_4321),
+        Bytes::basic_code(&[ CONST_0, SLOT_0, NUM_1, RETURN ]),
+    ]);
+    assert_eq!(p.execute1(p.get_constant(1), &[]).ok(), Some(0x8765_4321));
+
+    let mut p = Platform::with(&[
+        Bytes::constant_le_u64(0x0123_4567_89ab_cdef),
+        Bytes::basic_code(&[ CONST_0, SLOT_0, CONST_0, SLOT_1, NUM_2, RETURN ]),
+    ]);
+    let (lo, hi) = p.execute2(p.get_constant(1), &[]).unwrap();
+    if mem::size_of::<usize>() == 8 {
+        assert_eq!(lo as u64, 0x0123_4567_89ab_cdef);
+    } else {
+        assert_eq!((lo as u64, hi as u64), (0x89ab_cdef, 0x0123_4567));
+    }
+}
+
+#[test]
+fn constant_sint() {
+    for &n in [ 0, 1, -1, 63, -64, 64, -65, 8192, -100_000, isize::MAX, isize::MIN ].iter() {
+        let mut p = Platform::with(&[
+            Bytes::basic_code(&[ &[ Opcode::Immediate as u8 ], Bytes::constant_sint(n).to_bytes(), RETURN_1 ]),
+        ]);
+        assert_eq!(p.execute1(0, &[]).ok(), Some(n as usize));
+    }
+}
+
+#[test]
+fn interned_constants() {
+    let mut p = Platform::new();
+    let first = p.add_constant(Bytes::constant_le_u64(300).to_bytes());
+    let size = p.pool_size();
+    let second = p.add_constant(Bytes::constant_le_u64(300).to_bytes());
+    assert_eq!(first, second);
+    assert_eq!(p.pool_size(), size);
+    assert_eq!(p.get_constant(1), first);
+
+    let third = p.add_constant(Bytes::constant_le_u64(301).to_bytes());
+    assert_ne!(third, first);
+    assert!(p.pool_size() > size);
+}
+
+#[test]
+fn line_tables() {
+    static LINES: [(u16, u32); 3] = [ (0, 10), (4, 12), (9, 20) ];
+    static TABLES: [(u32, &[(u16, u32)]); 1] = [ (2, &LINES) ];
+
+    let mut p = Platform::with(&[ Bytes::basic_code(&[ NUM_0, RETURN ]) ]);
+    let mut r = p.to_runtime().unwrap();
+    assert_eq!(r.source_line(2, 4), None);
+    r.set_line_tables(&TABLES);
+    assert_eq!(r.source_line(2, 0), Some(10));
+    assert_eq!(r.source_line(2, 3), Some(10));
+    assert_eq!(r.source_line(2, 4), Some(12));
+    assert_eq!(r.source_line(2, 8), Some(12));
+    assert_eq!(r.source_line(2, 200), Some(20));
+    assert_eq!(r.source_line(0, 4), None);
+}
+
+#[test]
+fn rodata() {
+    let table: [usize; 3] = [ 5, 6, 7 ];
+    let table_addr = table.as_ptr() as usize;
+    let mut results = [ 0 as usize; 4 ];
+
+    let mut p = Platform::with(&[ Bytes::basic_code(&[ LOAD_LOCAL_0, SLOT_1, RETURN_1 ]) ]);
+    let mut r = p.to_runtime().unwrap();
+    assert_eq!(format!("{:?}", r.execute(0, &[ table_addr ], &mut results, None, None)), "Err(InvalidAddress(0x12) at [frame code=0 pc=2 sp=0])");
+    assert_eq!(r.set_rodata(&table), Ok(()));
+    assert_eq!(r.execute(0, &[ table_addr ], &mut results, None, None).ok(), Some(1));
+    assert_eq!(results[0], 6);
+    assert_eq!(r.object_size(table_addr), Ok(3));
+    assert_eq!(r.object_size(table_addr + 2 * mem::size_of::<usize>()), Ok(1));
+
+    let mut p = Platform::with(&[ Bytes::basic_code(&[ LOAD_LOCAL_0, NUM_1, STORE_SLOT_0 ]) ]);
+    let mut r = p.to_runtime().unwrap();
+    r.set_rodata(&table).unwrap();
+    assert_eq!(format!("{:?}", r.execute(0, &[ table_addr ], &mut results, None, None)), "Err(InvalidAddress(0x13) at [frame code=0 pc=4 sp=0])");
+
+    // it can't share memory with the heap or the constant pool.
+    let mut p = Platform::with(&[ Bytes::basic_code(&[ NUM_30, RETURN_1 ]) ]);
+    let pool = p.pool();
+    let pool_table = unsafe { core::slice::from_raw_parts(pool.as_ptr() as *const usize, 1) };
+    let mut r = p.to_runtime().unwrap();
+    assert_eq!(r.set_rodata(pool_table), Err(ErrorCode::OverlappingRegions));
+    let obj = r.alloc_object(2).unwrap();
+    let heap_table = unsafe { core::slice::from_raw_parts(obj as *const usize, 2) };
+    assert_eq!(r.set_rodata(heap_table), Err(ErrorCode::OverlappingRegions));
+}
+
 #[test]
 fn object_size() {
     let mut p = Platform::with(&[ Bytes::basic_code(&[ NUM_128, NUM_2, NEW_3_2, SIZE, NUM_1, RETURN ]) ]);
     assert_eq!(p.execute1(0, &[]).ok(), Some(4));
 }
 
+#[test]
+fn tagged_objects() {
+    let mut p = Platform::with(&[ Bytes::basic_code(&[
+        NUM_30, NUM_1, NUM_1, &new_tagged(7), NUM_1, NUM_0, &new_tagged(9), TYPE_OF, STORE_LOCAL_0,
+        TYPE_OF, LOAD_LOCAL_0, NUM_2, RETURN
+    ]) ]);
+    assert_eq!(p.execute2(0, &[]).ok(), Some((7, 9)));
+
+    // size is exact, and the tag isn't a slot.
+    let mut p = Platform::with(&[ Bytes::basic_code(&[
+        NUM_128, NUM_30, NUM_2, NUM_2, &new_tagged(7), DUP, SIZE, STORE_LOCAL_0, DUP, SLOT_0, STORE_LOCAL_1, SLOT_1,
+        LOAD_LOCAL_0, LOAD_LOCAL_1, &[ Opcode::ReturnN as u8, 6 ]
+    ]) ]);
+    let mut r = p.to_runtime().unwrap();
+    let mut results = [ 0 as usize; 4 ];
+    assert_eq!(r.execute(0, &[], &mut results, None, None).ok(), Some(3));
+    assert_eq!(&results[0..3], &[ 30, 2, 128 ]);
+
+    // untagged objects are 0
+    let mut p = Platform::with(&[ Bytes::basic_code(&[ NUM_128, NUM_2, NEW_3_2, TYPE_OF, RETURN_1 ]) ]);
+    assert_eq!(p.execute1(0, &[]).ok(), Some(0));
+
+    let mut p = Platform::with(&[ Bytes::basic_code(&[ NUM_128, TYPE_OF, RETURN_1 ]) ]);
+    assert_eq!(format!("{:?}", p.execute1(0, &[])), "Err(InvalidAddress(0x32) at [frame code=0 pc=3 sp=0])");
+}
+
+#[test]
+fn header_is_not_a_slot() {
+    // slot 0 of a plain object looks like a header for the tagged object at slot 1.
+    let forged = Bytes::constant_sint((7 << 8) | 3);
+    let word = mem::size_of::<usize>() as u8;
+    let mut p = Platform::with(&[
+        Bytes::basic_code(&[
+            IMMEDIATE, forged.to_bytes(), &[ Opcode::NewNN as u8, 8, 2 ], &[ Opcode::Immediate as u8, word << 1 ],
+            BINARY_ADD, DUP, TYPE_OF, &[ Opcode::ReturnN as u8, 4 ],
+        ]),
+        Bytes::basic_code(&[ NUM_1, NUM_0, &new_tagged(7), STORE_GLOBAL_0, LOAD_GLOBAL_0, RETURN_1 ]),
+    ]);
+    let (forge, tagged) = (p.get_constant(0), p.get_constant(1));
+    let mut r = p.to_runtime().unwrap();
+    let mut results = [ 0 as usize; 2 ];
+    assert_eq!(r.execute(forge, &[], &mut results, None, None).ok(), Some(2));
+    assert_eq!(results[1], 0);
+    assert_eq!(r.object_tag(results[0]), Ok(0));
+    assert_eq!(r.read_object(results[0], 2), Err(ErrorCode::OutOfBounds));
+
+    // a real one is still recognized after a collection.
+    assert_eq!(r.execute(tagged, &[], &mut results, None, None).ok(), Some(1));
+    r.soft_reset();
+    assert_eq!(r.object_tag(results[0]), Ok(7));
+    assert_eq!(r.object_size(results[0]), Ok(1));
+}
+
+#[test]
+fn poison_new_objects() {
+    let code: &[&[u8]] = &[ NUM_128, NUM_2, NEW_3_2, DUP, SLOT_1, STORE_LOCAL_0, SLOT_2, LOAD_LOCAL_0, NUM_2, RETURN ];
+    let mut p = Platform::with(&[ Bytes::basic_code(code) ]);
+    assert_eq!(p.execute2(0, &[]).ok(), Some((0, 2)));
+
+    let mut p = Platform::with(&[ Bytes::basic_code(code) ]);
+    let mut r = p.to_runtime_with(RuntimeOptions { poison_objects: true, ..RuntimeOptions::default() }).unwrap();
+    let mut results = [ 0 as usize; 2 ];
+    assert_eq!(r.execute(0, &[], &mut results, None, None).ok(), Some(2));
+    assert_eq!(results, [ POISON, 2 ]);
+    assert_eq!(POISON & 0xffff_ffff, 0xdead_dead);
+}
+
+#[test]
+fn slot_diff() {
+    let word = mem::size_of::<usize>() as u8;
+    let new_5: &[u8] = &[ Opcode::NewNN as u8, 10, 0 ];
+    let slot_3: &[u8] = &[ Opcode::Immediate as u8, (3 * word) << 1 ];
+    let slot_1: &[u8] = &[ Opcode::Immediate as u8, word << 1 ];
+
+    let mut p = Platform::with(&[ Bytes::basic_code(&[
+        new_5, STORE_LOCAL_0, LOAD_LOCAL_0, slot_3, BINARY_ADD, LOAD_LOCAL_0, slot_1, BINARY_ADD, SLOT_DIFF, RETURN_1
+    ]) ]);
+    assert_eq!(p.execute1(0, &[]).ok(), Some(2));
+
+    let mut p = Platform::with(&[ Bytes::basic_code(&[
+        new_5, STORE_LOCAL_0, LOAD_LOCAL_0, slot_1, BINARY_ADD, LOAD_LOCAL_0, slot_3, BINARY_ADD, SLOT_DIFF, RETURN_1
+    ]) ]);
+    assert_eq!(p.execute1(0, &[]).ok(), Some((-2 as isize) as usize));
+
+    // slots of two different objects
+    let mut p = Platform::with(&[ Bytes::basic_code(&[
+        new_5, slot_1, BINARY_ADD, new_5, slot_3, BINARY_ADD, SLOT_DIFF, RETURN_1
+    ]) ]);
+    assert_eq!(format!("{:?}", p.execute1(0, &[])), "Err(InvalidAddress(0x35) at [frame code=0 pc=e sp=0])");
+
+    // not in the heap at all
+    let mut p = Platform::with(&[ Bytes::basic_code(&[ NUM_128, NUM_128, SLOT_DIFF, RETURN_1 ]) ]);
+    assert_eq!(format!("{:?}", p.execute1(0, &[])), "Err(InvalidAddress(0x35) at [frame code=0 pc=6 sp=0])");
+}
+
+#[test]
+fn free_list() {
+    // the second object reuses the first one, zeroed.
+    let mut p = Platform::with(&[ Bytes::basic_code(&[
+        NUM_30, NUM_30, NUM_2, NUM_2, NEW, DUP, FREE, NUM_2, NUM_0, NEW, DUP, SLOT_1, &[ Opcode::ReturnN as u8, 6 ]
+    ]) ]);
+    let mut r = p.to_runtime().unwrap();
+    let mut results = [ 0 as usize; 3 ];
+    assert_eq!(r.execute(0, &[], &mut results, None, None).ok(), Some(3));
+    assert_eq!(results[0], results[1]);
+    assert_eq!(results[2], 0);
+
+    // the second run only allocates a stack frame.
+    let mut p = Platform::with(&[ Bytes::basic_code(&[ NUM_30, NUM_2, NEW_3_2, FREE, NUM_0, RETURN ]) ]);
+    let mut r = p.to_runtime().unwrap();
+    let free0 = r.heap_free_bytes();
+    assert_eq!(r.execute(0, &[], &mut results, None, None).ok(), Some(0));
+    let free1 = r.heap_free_bytes();
+    assert_eq!(r.execute(0, &[], &mut results, None, None).ok(), Some(0));
+    let free2 = r.heap_free_bytes();
+    assert!(free1 - free2 < free0 - free1);
+
+    // only heap objects can be freed.
+    let mut p = Platform::with(&[ Bytes::basic_code(&[ NUM_128, FREE, NUM_0, RETURN ]) ]);
+    assert_eq!(format!("{:?}", p.execute0(0, &[])), "Err(InvalidAddress(0x33) at [frame code=0 pc=3 sp=0])");
+}
+
+#[test]
+fn load_field() {
+    let packed = Bytes::constant_sint(0x7ff0_fe80);
+    let mut p = Platform::with(&[ Bytes::basic_code(&[
+        IMMEDIATE, packed.to_bytes(), DUP, DUP, &field(0, 8), STORE_LOCAL_0, &field(8, 16), STORE_LOCAL_1, &field(24, 8),
+        LOAD_LOCAL_0, LOAD_LOCAL_1, &[ Opcode::ReturnN as u8, 6 ]
+    ]) ]);
+    let mut r = p.to_runtime().unwrap();
+    let mut results = [ 0 as usize; 3 ];
+    assert_eq!(r.execute(0, &[], &mut results, None, None).ok(), Some(3));
+    assert_eq!(results, [ 127, (-128 as isize) as usize, (-3842 as isize) as usize ]);
+
+    // the whole word is fine, but not more.
+    let bits = (mem::size_of::<usize>() * 8) as isize;
+    let word = Bytes::constant_sint(bits);
+    let mut p = Platform::with(&[ Bytes::basic_code(&[ NUM_N30, &[ Opcode::LoadField as u8, 0 ], word.to_bytes(), RETURN_1 ]) ]);
+    assert_eq!(p.execute1(0, &[]).ok(), Some((-30 as isize) as usize));
+
+    let too_wide = Bytes::constant_sint(bits + 1);
+    let mut p = Platform::with(&[ Bytes::basic_code(&[ NUM_N30, &[ Opcode::LoadField as u8, 0 ], too_wide.to_bytes(), RETURN_1 ]) ]);
+    assert_eq!(format!("{:?}", p.execute1(0, &[])), "Err(InvalidSize(0x21) at [frame code=0 pc=2 sp=0])");
+
+    let mut p = Platform::with(&[ Bytes::basic_code(&[ NUM_N30, &[ Opcode::LoadField as u8, 16 ], word.to_bytes(), RETURN_1 ]) ]);
+    assert_eq!(format!("{:?}", p.execute1(0, &[])), "Err(OutOfBounds(0x21) at [frame code=0 pc=2 sp=0])");
+}
+
 #[test]
 fn load_and_store_local() {
     let mut p = Platform::with(&[ Bytes::basic_code(&[ NUM_128, STORE_LOCAL_0, NUM_2, LOAD_LOCAL_0, RETURN_1 ]) ]);
@@ -226,7 +1084,36 @@ fn load_and_store_local() {
     assert_eq!(p.execute1(0, &[]).ok(), Some(2));
 
     let mut p = Platform::with(&[ Bytes::basic_code(&[ NUM_128, STORE_LOCAL_10 ]) ]);
-    assert_eq!(format!("{:?}", p.execute1(0, &[])), "Err(OutOfBounds at [frame code=0 pc=3 sp=1])");
+    assert_eq!(format!("{:?}", p.execute1(0, &[])), "Err(OutOfBounds(0x15) at [frame code=0 pc=3 sp=1])");
+}
+
+#[test]
+fn uninitialized_locals() {
+    let options = RuntimeOptions { check_uninitialized_locals: true, ..RuntimeOptions::default() };
+    let mut results = [ 0 as usize; 1 ];
+
+    let mut p = Platform::with(&[ Bytes::basic_code(&[ LOAD_LOCAL_1, RETURN_1 ]) ]);
+    assert_eq!(p.execute1(0, &[]).ok(), Some(0));
+    let mut r = p.to_runtime_with(options).unwrap();
+    assert_eq!(format!("{:?}", r.execute(0, &[], &mut results, None, None)), "Err(UninitializedLocal(0x14) at [frame code=0 pc=0 sp=0])");
+
+    // args count as written.
+    let mut p = Platform::with(&[ Bytes::basic_code(&[ NUM_30, STORE_LOCAL_1, LOAD_LOCAL_0, LOAD_LOCAL_1, BINARY_ADD, RETURN_1 ]) ]);
+    let mut r = p.to_runtime_with(options).unwrap();
+    assert_eq!(r.execute(0, &[ 100 ], &mut results, None, None).ok(), Some(1));
+    assert_eq!(results[0], 130);
+
+    // and called functions are checked too.
+    let mut p = Platform::with(&[
+        Bytes::basic_code(&[ LOAD_LOCAL_1, RETURN_1 ]),
+        Bytes::basic_code(&[ NUM_30, CONST_0, CALL_1, RETURN_1 ]),
+    ]);
+    let main = p.get_constant(1);
+    let mut r = p.to_runtime_with(options).unwrap();
+    assert_eq!(
+        format!("{:?}", r.execute(main, &[], &mut results, None, None)),
+        "Err(UninitializedLocal(0x14) at [frame code=0 pc=0 sp=0] -> [frame code=2 pc=6 sp=0])"
+    );
 }
 
 #[test]
@@ -241,7 +1128,66 @@ fn load_and_store_global() {
     assert_eq!(p.execute1(0, &[]).ok(), Some(2));
 
     let mut p = Platform::with(&[ Bytes::basic_code(&[ NUM_128, STORE_GLOBAL_10 ]) ]);
-    assert_eq!(format!("{:?}", p.execute1(0, &[])), "Err(OutOfBounds at [frame code=0 pc=3 sp=1])");
+    assert_eq!(format!("{:?}", p.execute1(0, &[])), "Err(OutOfBounds(0x17) at [frame code=0 pc=3 sp=1])");
+}
+
+#[test]
+fn snapshot_globals() {
+    let snapshot: &[u8] = &[ Opcode::SnapshotGlobals as u8, 0, 4 ];
+    let restore: &[u8] = &[ Opcode::RestoreGlobals as u8, 0, 4 ];
+    let mut p = Platform::with(&[ Bytes::basic_code(&[
+        NUM_128, STORE_GLOBAL_0, NUM_30, STORE_GLOBAL_1, snapshot, STORE_LOCAL_0,
+        NUM_1, STORE_GLOBAL_0, NUM_2, STORE_GLOBAL_1, LOAD_GLOBAL_0, LOAD_GLOBAL_1, BINARY_ADD, STORE_LOCAL_1,
+        LOAD_LOCAL_0, restore, LOAD_LOCAL_1, LOAD_GLOBAL_0, LOAD_GLOBAL_1, LOAD_LOCAL_0, SIZE, &[ Opcode::ReturnN as u8, 8 ]
+    ]) ]);
+    let mut r = p.to_runtime().unwrap();
+    let mut results = [ 0 as usize; 4 ];
+    assert_eq!(r.execute(0, &[], &mut results, None, None).ok(), Some(4));
+    assert_eq!(results[0 .. 3], [ 3, 128, 30 ]);
+    assert!(results[3] >= 2);
+
+    let mut p = Platform::with(&[ Bytes::basic_code(&[ &[ Opcode::SnapshotGlobals as u8, 2, 40 ] ]) ]);
+    assert_eq!(format!("{:?}", p.execute0(0, &[])), "Err(OutOfBounds(0x22) at [frame code=0 pc=0 sp=0])");
+
+    let mut p = Platform::with(&[ Bytes::basic_code(&[ NUM_128, restore ]) ]);
+    assert_eq!(format!("{:?}", p.execute0(0, &[])), "Err(InvalidAddress(0x23) at [frame code=0 pc=3 sp=0])");
+
+    // an object smaller than the range can't be restored from, even if more heap follows it.
+    let mut p = Platform::with(&[
+        Bytes::basic_code(&[
+            NUM_30, STORE_GLOBAL_0, &[ Opcode::NewNN as u8, 4, 0 ], &[ Opcode::NewNN as u8, 16, 0 ], DROP,
+            &[ Opcode::RestoreGlobals as u8, 0, 16 ], RETURN_0,
+        ]),
+        Bytes::basic_code(&[ LOAD_GLOBAL_0, RETURN_1 ]),
+    ]);
+    let load_global = p.get_constant(1);
+    let mut r = p.to_runtime_with(RuntimeOptions { global_count: 8, ..RuntimeOptions::default() }).unwrap();
+    let rv = r.execute(0, &[], &mut results, None, None);
+    assert_eq!(rv.err().map(|e| e.code), Some(ErrorCode::OutOfBounds));
+    assert_eq!(r.execute(load_global, &[], &mut results, None, None).ok(), Some(1));
+    assert_eq!(results[0], 30);
+}
+
+#[test]
+fn increment_global() {
+    let mut p = Platform::with(&[ Bytes::basic_code(&[
+        NUM_128, STORE_GLOBAL_1, &[ Opcode::IncGlobalN as u8, 2 ], &[ Opcode::IncGlobalN as u8, 2 ], LOAD_GLOBAL_1, RETURN_1
+    ]) ]);
+    assert_eq!(p.execute1(0, &[]).ok(), Some(130));
+
+    let mut p = Platform::with(&[ Bytes::basic_code(&[
+        NUM_128, STORE_GLOBAL_1, NUM_N30, &[ Opcode::AddGlobalN as u8, 2 ], LOAD_GLOBAL_1, RETURN_1
+    ]) ]);
+    assert_eq!(p.execute1(0, &[]).ok(), Some(98));
+
+    // wraps around
+    let mut p = Platform::with(&[ Bytes::basic_code(&[
+        NUM_N1, STORE_GLOBAL_0, &[ Opcode::IncGlobalN as u8, 0 ], LOAD_GLOBAL_0, RETURN_1
+    ]) ]);
+    assert_eq!(p.execute1(0, &[]).ok(), Some(0));
+
+    let mut p = Platform::with(&[ Bytes::basic_code(&[ &[ Opcode::IncGlobalN as u8, 20 ] ]) ]);
+    assert_eq!(format!("{:?}", p.execute0(0, &[])), "Err(OutOfBounds(0x41) at [frame code=0 pc=0 sp=0])");
 }
 
 #[test]
@@ -261,9 +1207,19 @@ fn unary() {
     let mut p = Platform::with(&[ Bytes::basic_code(&[ NUM_1, UNARY_BITNOT, RETURN_1 ]) ]);
     assert_eq!(p.execute1(0, &[]).ok(), Some((-2 as isize) as usize));
 
-    let mut p = Platform::with(&[ Bytes::basic_code(&[ NUM_1, &[ Opcode::Unary as u8, 50 ], RETURN_1 ]) ]);
-    assert_eq!(format!("{:?}", p.execute1(0, &[])), "Err(UnknownOpcode at [frame code=0 pc=2 sp=0])");
-}
+    let mut p = Platform::with(&[ Bytes::basic_code(&[ NUM_N30, UNARY_ABS, RETURN_1 ]) ]);
+    assert_eq!(p.execute1(0, &[]).ok(), Some(30));
+
+    let mut p = Platform::with(&[ Bytes::basic_code(&[ NUM_30, UNARY_ABS, RETURN_1 ]) ]);
+    assert_eq!(p.execute1(0, &[]).ok(), Some(30));
+
+    let min = Bytes::constant_sint(core::isize::MIN);
+    let mut p = Platform::with(&[ Bytes::basic_code(&[ IMMEDIATE, min.to_bytes(), UNARY_ABS, RETURN_1 ]) ]);
+    assert_eq!(p.execute1(0, &[]).err().map(|e| e.code), Some(ErrorCode::ArithmeticOverflow));
+
+    let mut p = Platform::with(&[ Bytes::basic_code(&[ NUM_1, &[ Opcode::Unary as u8, 50 ], RETURN_1 ]) ]);
+    assert_eq!(format!("{:?}", p.execute1(0, &[])), "Err(UnknownOpcode(0x18) at [frame code=0 pc=2 sp=0])");
+}
 
 #[test]
 fn binary_math() {
@@ -294,8 +1250,20 @@ fn binary_math() {
     let mut p = Platform::with(&[ Bytes::basic_code(&[ NUM_128, NUM_30, BINARY_MOD, RETURN_1 ]) ]);
     assert_eq!(p.execute1(0, &[]).ok(), Some(8));
 
-    let mut p = Platform::with(&[ Bytes::basic_code(&[ NUM_1, NUM_1, &[ Opcode::Binary as u8, 50 ], RETURN_1 ]) ]);
-    assert_eq!(format!("{:?}", p.execute1(0, &[])), "Err(UnknownOpcode at [frame code=0 pc=4 sp=0])");
+    let mut p = Platform::with(&[ Bytes::basic_code(&[ NUM_1, NUM_0, BINARY_DIV, RETURN_1 ]) ]);
+    assert_eq!(format!("{:?}", p.execute1(0, &[])), "Err(DivideByZero(0x19) at [frame code=0 pc=4 sp=0])");
+
+    let mut p = Platform::with(&[ Bytes::basic_code(&[ NUM_1, NUM_0, BINARY_MOD, RETURN_1 ]) ]);
+    assert_eq!(format!("{:?}", p.execute1(0, &[])), "Err(DivideByZero(0x19) at [frame code=0 pc=4 sp=0])");
+
+    let min = Bytes::constant_sint(core::isize::MIN);
+    for &op in &[ BINARY_DIV, BINARY_MOD ] {
+        let mut p = Platform::with(&[ Bytes::basic_code(&[ IMMEDIATE, min.to_bytes(), NUM_N1, op, RETURN_1 ]) ]);
+        assert_eq!(p.execute1(0, &[]).err().map(|e| e.code), Some(ErrorCode::ArithmeticOverflow));
+    }
+
+    let mut p = Platform::with(&[ Bytes::basic_code(&[ NUM_1, NUM_1, &[ Opcode::Binary as u8, 100 ], RETURN_1 ]) ]);
+    assert_eq!(format!("{:?}", p.execute1(0, &[])), "Err(UnknownOpcode(0x19) at [frame code=0 pc=4 sp=0])");
 }
 
 #[test]
@@ -323,6 +1291,69 @@ fn binary_compare() {
 
     let mut p = Platform::with(&[ Bytes::basic_code(&[ NUM_30, NUM_128, BINARY_LE, RETURN_1 ]) ]);
     assert_eq!(p.execute1(0, &[]).ok(), Some(1));
+
+    let mut p = Platform::with(&[ Bytes::basic_code(&[ NUM_128, NUM_30, BINARY_GT, RETURN_1 ]) ]);
+    assert_eq!(p.execute1(0, &[]).ok(), Some(1));
+
+    let mut p = Platform::with(&[ Bytes::basic_code(&[ NUM_30, NUM_128, BINARY_GT, RETURN_1 ]) ]);
+    assert_eq!(p.execute1(0, &[]).ok(), Some(0));
+
+    let mut p = Platform::with(&[ Bytes::basic_code(&[ NUM_N30, NUM_30, BINARY_GT, RETURN_1 ]) ]);
+    assert_eq!(p.execute1(0, &[]).ok(), Some(0));
+
+    let mut p = Platform::with(&[ Bytes::basic_code(&[ NUM_30, NUM_30, BINARY_GT, RETURN_1 ]) ]);
+    assert_eq!(p.execute1(0, &[]).ok(), Some(0));
+
+    let mut p = Platform::with(&[ Bytes::basic_code(&[ NUM_30, NUM_30, BINARY_GE, RETURN_1 ]) ]);
+    assert_eq!(p.execute1(0, &[]).ok(), Some(1));
+
+    let mut p = Platform::with(&[ Bytes::basic_code(&[ NUM_30, NUM_128, BINARY_GE, RETURN_1 ]) ]);
+    assert_eq!(p.execute1(0, &[]).ok(), Some(0));
+}
+
+#[test]
+fn binary_min_max() {
+    let mut p = Platform::with(&[ Bytes::basic_code(&[ NUM_N30, NUM_30, BINARY_MIN, RETURN_1 ]) ]);
+    assert_eq!(p.execute1(0, &[]).ok(), Some((-30 as isize) as usize));
+
+    let mut p = Platform::with(&[ Bytes::basic_code(&[ NUM_N30, NUM_30, BINARY_MAX, RETURN_1 ]) ]);
+    assert_eq!(p.execute1(0, &[]).ok(), Some(30));
+
+    let mut p = Platform::with(&[ Bytes::basic_code(&[ NUM_128, NUM_30, BINARY_MIN, RETURN_1 ]) ]);
+    assert_eq!(p.execute1(0, &[]).ok(), Some(30));
+
+    let mut p = Platform::with(&[ Bytes::basic_code(&[ NUM_30, NUM_30, BINARY_MAX, RETURN_1 ]) ]);
+    assert_eq!(p.execute1(0, &[]).ok(), Some(30));
+}
+
+#[test]
+fn binary_unsigned() {
+    // 0x8000... is the most negative signed word, but the biggest unsigned one here.
+    let min = Bytes::constant_sint(core::isize::MIN);
+    let mut p = Platform::with(&[ Bytes::basic_code(&[ IMMEDIATE, min.to_bytes(), NUM_30, BINARY_LT, RETURN_1 ]) ]);
+    assert_eq!(p.execute1(0, &[]).ok(), Some(1));
+
+    let mut p = Platform::with(&[ Bytes::basic_code(&[ IMMEDIATE, min.to_bytes(), NUM_30, BINARY_ULT, RETURN_1 ]) ]);
+    assert_eq!(p.execute1(0, &[]).ok(), Some(0));
+
+    let mut p = Platform::with(&[ Bytes::basic_code(&[ NUM_30, IMMEDIATE, min.to_bytes(), BINARY_ULT, RETURN_1 ]) ]);
+    assert_eq!(p.execute1(0, &[]).ok(), Some(1));
+
+    let mut p = Platform::with(&[ Bytes::basic_code(&[ NUM_N1, NUM_2, BINARY_DIV, RETURN_1 ]) ]);
+    assert_eq!(p.execute1(0, &[]).ok(), Some(0));
+
+    let mut p = Platform::with(&[ Bytes::basic_code(&[ NUM_N1, NUM_2, BINARY_UDIV, RETURN_1 ]) ]);
+    assert_eq!(p.execute1(0, &[]).ok(), Some(core::isize::MAX as usize));
+
+    let mut p = Platform::with(&[ Bytes::basic_code(&[ NUM_N1, NUM_2, BINARY_UMOD, RETURN_1 ]) ]);
+    assert_eq!(p.execute1(0, &[]).ok(), Some(1));
+
+    // dividing the most negative word by -1 is fine when they're both unsigned.
+    let mut p = Platform::with(&[ Bytes::basic_code(&[ IMMEDIATE, min.to_bytes(), NUM_N1, BINARY_UDIV, RETURN_1 ]) ]);
+    assert_eq!(p.execute1(0, &[]).ok(), Some(0));
+
+    let mut p = Platform::with(&[ Bytes::basic_code(&[ NUM_1, NUM_0, BINARY_UMOD, RETURN_1 ]) ]);
+    assert_eq!(p.execute1(0, &[]).err().map(|e| e.code), Some(ErrorCode::DivideByZero));
 }
 
 #[test]
@@ -359,6 +1390,395 @@ fn binary_shift() {
 
     let mut p = Platform::with(&[ Bytes::basic_code(&[ NUM_30, NUM_2, BINARY_ASR, RETURN_1 ]) ]);
     assert_eq!(p.execute1(0, &[]).ok(), Some(7));
+
+    // shifting by 64, 1000, or -1 shifts everything out.
+    let num_1000 = &[ Opcode::Immediate as u8, 0xd0, 0x0f ][..];
+    for &amount in &[ NUM_64, num_1000, NUM_N1 ] {
+        for &(n, op, expected) in &[
+            (NUM_N30, BINARY_LSL, 0), (NUM_N30, BINARY_LSR, 0), (NUM_30, BINARY_ASR, 0), (NUM_N30, BINARY_ASR, -1isize as usize),
+        ] {
+            let mut p = Platform::with(&[ Bytes::basic_code(&[ n, amount, op, RETURN_1 ]) ]);
+            assert_eq!(p.execute1(0, &[]).ok(), Some(expected));
+        }
+    }
+}
+
+#[test]
+fn binary_logical() {
+    // 2 & 4 would be 0.
+    let mut p = Platform::with(&[ Bytes::basic_code(&[ NUM_2, NUM_30, BINARY_AND, NUM_2, NUM_30, BINARY_LAND, NUM_2, RETURN ]) ]);
+    assert_eq!(p.execute2(0, &[]).ok(), Some((2, 1)));
+
+    let mut p = Platform::with(&[ Bytes::basic_code(&[ NUM_N30, NUM_0, BINARY_LAND, RETURN_1 ]) ]);
+    assert_eq!(p.execute1(0, &[]).ok(), Some(0));
+
+    let mut p = Platform::with(&[ Bytes::basic_code(&[ NUM_N30, NUM_0, BINARY_LOR, RETURN_1 ]) ]);
+    assert_eq!(p.execute1(0, &[]).ok(), Some(1));
+
+    let mut p = Platform::with(&[ Bytes::basic_code(&[ NUM_2, NUM_128, BINARY_LOR, RETURN_1 ]) ]);
+    assert_eq!(p.execute1(0, &[]).ok(), Some(1));
+
+    let mut p = Platform::with(&[ Bytes::basic_code(&[ NUM_0, NUM_0, BINARY_LOR, RETURN_1 ]) ]);
+    assert_eq!(p.execute1(0, &[]).ok(), Some(0));
+}
+
+#[test]
+fn trap_overflow() {
+    let max = Bytes::constant_sint(core::isize::MAX);
+    let min_bytes = Bytes::constant_sint(core::isize::MIN);
+    let overflows: &[&[&[u8]]] = &[
+        &[ IMMEDIATE, max.to_bytes(), NUM_1, BINARY_ADD, RETURN_1 ],
+        &[ IMMEDIATE, max.to_bytes(), NUM_2, BINARY_MUL, RETURN_1 ],
+        &[ NUM_N1, IMMEDIATE, max.to_bytes(), BINARY_SUB, NUM_1, BINARY_SUB, RETURN_1 ],
+        &[ NUM_1, NUM_128, BINARY_LSL, RETURN_1 ],
+    ];
+    let inc_local = [ Opcode::IncLocalN as u8, 0 ];
+    let dec_local = [ Opcode::DecLocalN as u8, 0 ];
+    let inc_global = [ Opcode::IncGlobalN as u8, 0 ];
+    let add_global = [ Opcode::AddGlobalN as u8, 0 ];
+    let overflows: &[&[&[u8]]] = &[
+        overflows[0], overflows[1], overflows[2], overflows[3],
+        &[ IMMEDIATE, max.to_bytes(), STORE_LOCAL_0, &inc_local, LOAD_LOCAL_0, RETURN_1 ],
+        &[ IMMEDIATE, min_bytes.to_bytes(), STORE_LOCAL_0, &dec_local, LOAD_LOCAL_0, RETURN_1 ],
+        &[ IMMEDIATE, max.to_bytes(), STORE_GLOBAL_0, &inc_global, LOAD_GLOBAL_0, RETURN_1 ],
+        &[ IMMEDIATE, max.to_bytes(), STORE_GLOBAL_0, NUM_2, &add_global, LOAD_GLOBAL_0, RETURN_1 ],
+    ];
+    let wrapped = [
+        core::isize::MIN as usize, (-2isize) as usize, core::isize::MAX as usize, 0,
+        core::isize::MIN as usize, core::isize::MAX as usize, core::isize::MIN as usize, (core::isize::MIN + 1) as usize,
+    ];
+    let trapping = RuntimeOptions { trap_overflow: true, ..RuntimeOptions::default() };
+    for (code, &expected) in overflows.iter().zip(wrapped.iter()) {
+        let mut p = Platform::with(&[ Bytes::basic_code(code) ]);
+        assert_eq!(p.execute1(0, &[]).ok(), Some(expected));
+
+        let mut p = Platform::with(&[ Bytes::basic_code(code) ]);
+        let mut results = [ 0 as usize; 1 ];
+        let rv = p.to_runtime_with(trapping).and_then(|mut r| r.execute(0, &[], &mut results, None, None));
+        assert_eq!(rv.err().map(|e| e.code), Some(ErrorCode::ArithmeticOverflow));
+    }
+
+    // negating the smallest number has no answer either.
+    let min = Bytes::constant_sint(core::isize::MIN);
+    let negate = [ IMMEDIATE, min.to_bytes(), &[ Opcode::Unary as u8, (Unary::Negative as u8) << 1 ], RETURN_1 ];
+    let mut p = Platform::with(&[ Bytes::basic_code(&negate) ]);
+    assert_eq!(p.execute1(0, &[]).ok(), Some(core::isize::MIN as usize));
+    let mut p = Platform::with(&[ Bytes::basic_code(&negate) ]);
+    let mut results = [ 0 as usize; 1 ];
+    let rv = p.to_runtime_with(trapping).and_then(|mut r| r.execute(0, &[], &mut results, None, None));
+    assert_eq!(rv.err().map(|e| e.code), Some(ErrorCode::ArithmeticOverflow));
+}
+
+#[test]
+fn binary_checked() {
+    let max = Bytes::constant_sint(core::isize::MAX);
+    let mut p = Platform::with(&[ Bytes::basic_code(&[ NUM_128, NUM_30, BINARY_ADDC, RETURN_1 ]) ]);
+    assert_eq!(p.execute1(0, &[]).ok(), Some(158));
+
+    let mut p = Platform::with(&[ Bytes::basic_code(&[ NUM_30, NUM_128, BINARY_SUBC, RETURN_1 ]) ]);
+    assert_eq!(p.execute1(0, &[]).ok(), Some((-98 as isize) as usize));
+
+    let mut p = Platform::with(&[ Bytes::basic_code(&[ NUM_N30, NUM_30, BINARY_MULC, RETURN_1 ]) ]);
+    assert_eq!(p.execute1(0, &[]).ok(), Some((-900 as isize) as usize));
+
+    // these trap even without `trap_overflow`.
+    let overflows: &[&[&[u8]]] = &[
+        &[ IMMEDIATE, max.to_bytes(), NUM_1, BINARY_ADDC, RETURN_1 ],
+        &[ NUM_N1, IMMEDIATE, max.to_bytes(), BINARY_SUB, NUM_1, BINARY_SUBC, RETURN_1 ],
+        &[ IMMEDIATE, max.to_bytes(), NUM_2, BINARY_MULC, RETURN_1 ],
+    ];
+    for code in overflows {
+        let mut p = Platform::with(&[ Bytes::basic_code(code) ]);
+        assert_eq!(p.execute1(0, &[]).err().map(|e| e.code), Some(ErrorCode::ArithmeticOverflow));
+    }
+}
+
+#[test]
+fn copy_local() {
+    let copy = |src: u8, dst: u8| [ Opcode::CopyLocal as u8, src << 1, dst << 1 ];
+    let mut p = Platform::with(&[
+        Bytes::basic_code(&[ &copy(0, 1), &copy(0, 7), LOAD_LOCAL_1, &[ Opcode::LoadLocalN as u8, 14 ], NUM_2, RETURN ]),
+        Bytes::basic_code(&[ &copy(8, 0), RETURN_0 ]),
+        Bytes::basic_code(&[ &copy(0, 8), RETURN_0 ]),
+    ]);
+    assert_eq!(p.execute2(p.get_constant(0), &[ 30 ]).ok(), Some((30, 30)));
+    assert_eq!(p.execute0(p.get_constant(1), &[ 30 ]).err().map(|e| e.code), Some(ErrorCode::OutOfBounds));
+    assert_eq!(p.execute0(p.get_constant(2), &[ 30 ]).err().map(|e| e.code), Some(ErrorCode::OutOfBounds));
+}
+
+#[test]
+fn inc_dec_local() {
+    let inc = |n: u8| [ Opcode::IncLocalN as u8, n << 1 ];
+    let dec = |n: u8| [ Opcode::DecLocalN as u8, n << 1 ];
+    let mut p = Platform::with(&[
+        Bytes::basic_code(&[ &inc(0), &inc(0), &dec(1), LOAD_LOCAL_0, LOAD_LOCAL_1, NUM_2, RETURN ]),
+        Bytes::basic_code(&[ &inc(8), RETURN_0 ]),
+        Bytes::basic_code(&[ &dec(8), RETURN_0 ]),
+    ]);
+    assert_eq!(p.execute2(p.get_constant(0), &[ 5, 0 ]).ok(), Some((7, core::usize::MAX)));
+    assert_eq!(p.execute0(p.get_constant(1), &[]).err().map(|e| e.code), Some(ErrorCode::OutOfBounds));
+    assert_eq!(p.execute0(p.get_constant(2), &[]).err().map(|e| e.code), Some(ErrorCode::OutOfBounds));
+}
+
+fn native_sum(args: &[usize], results: &mut [usize]) -> Result<usize, ErrorCode> {
+    if results.is_empty() { return Err(ErrorCode::StackOverflow) }
+    results[0] = args.iter().sum();
+    Ok(1)
+}
+
+fn native_fail(_args: &[usize], _results: &mut [usize]) -> Result<usize, ErrorCode> {
+    Err(ErrorCode::DivideByZero)
+}
+
+static NATIVES: [NativeFunction; 2] = [ native_sum, native_fail ];
+
+#[test]
+fn call_native() {
+    let sys = |n: u8| [ Opcode::CallNativeN as u8, n << 1 ];
+    let mut p = Platform::with(&[
+        // the value under the args is left alone:
+        Bytes::basic_code(&[ NUM_30, NUM_1, NUM_2, NUM_2, &sys(0), NUM_2, RETURN ]),
+        Bytes::basic_code(&[ NUM_0, &sys(1), RETURN_0 ]),
+        Bytes::basic_code(&[ NUM_0, &sys(2), RETURN_0 ]),
+    ]);
+    let (sum, fail, missing) = (p.get_constant(0), p.get_constant(1), p.get_constant(2));
+    let mut r = p.to_runtime().unwrap();
+    r.set_natives(&NATIVES);
+    let mut results = [ 0 as usize; 4 ];
+    assert_eq!(r.execute(sum, &[], &mut results, None, None).ok(), Some(2));
+    assert_eq!(&results[0 .. 2], &[ 30, 3 ]);
+    let rv = r.execute(fail, &[], &mut results, None, None);
+    assert_eq!(rv.err().map(|e| e.code), Some(ErrorCode::DivideByZero));
+    let rv = r.execute(missing, &[], &mut results, None, None);
+    assert_eq!(format!("{:?}", rv), "Err(InvalidNativeIndex(0x4b) at [frame code=a pc=2 sp=1])");
+}
+
+fn native_panic(_args: &[usize], _results: &mut [usize]) -> Result<usize, ErrorCode> {
+    panic!("a dry run called a native");
+}
+
+static PANIC_NATIVES: [NativeFunction; 1] = [ native_panic ];
+
+#[test]
+fn dry_run_skips_natives() {
+    let sys = |n: u8| [ Opcode::CallNativeN as u8, n << 1 ];
+    let mut p = Platform::with(&[
+        // return as many values as the native says, which is 0:
+        Bytes::basic_code(&[ NUM_1, NUM_2, NUM_2, &sys(0), RETURN ]),
+        Bytes::basic_code(&[ NUM_0, &sys(1), RETURN_0 ]),
+    ]);
+    let (call, missing) = (p.get_constant(0), p.get_constant(1));
+    let mut r = p.to_runtime().unwrap();
+    r.set_natives(&PANIC_NATIVES);
+    assert_eq!(r.dry_run(call, &[], None, None, None).ok(), Some(0));
+    // the index is still checked.
+    let rv = r.dry_run(missing, &[], None, None, None);
+    assert_eq!(rv.err().map(|e| e.code), Some(ErrorCode::InvalidNativeIndex));
+}
+
+#[test]
+fn dec_test_local() {
+    // count down from 3 four times: it reaches 0 on the third, then stays there.
+    let dec_test = &[ Opcode::DecTestLocalN as u8, 0 ][..];
+    let mut p = Platform::with(&[ Bytes::basic_code(&[
+        &[ Opcode::Immediate as u8, 6 ], STORE_LOCAL_0, dec_test, dec_test, dec_test, dec_test, LOAD_LOCAL_0,
+        &[ Opcode::ReturnN as u8, 10 ],
+    ]) ]);
+    let mut results = [ 0 as usize; 5 ];
+    let rv = p.to_runtime().and_then(|mut r| r.execute(0, &[], &mut results, None, None));
+    assert_eq!(rv.ok(), Some(5));
+    assert_eq!(results, [ 0, 0, 1, 1, 0 ]);
+
+    let mut p = Platform::with(&[ Bytes::code(1, 2, &[ &[ Opcode::DecTestLocalN as u8, 2 ] ]) ]);
+    assert_eq!(format!("{:?}", p.execute1(0, &[])), "Err(OutOfBounds(0x44) at [frame code=0 pc=0 sp=0])");
+}
+
+#[test]
+fn image_word_size() {
+    let word = mem::size_of::<usize>();
+    let other = if word == 8 { 4 } else { 8 };
+    let p = Platform::with(&[ Bytes::basic_code(&[ NUM_30, RETURN_1 ]) ]);
+    // magic, version, word size, 3 globals, main at 0:
+    let image_for = |word_size: u8| {
+        let mut image = vec![ 0xf0, 0x9f, 0x97, 0xbf, 1, word_size, 3, 0 ];
+        image.extend_from_slice(p.pool());
+        image
+    };
+    let image = image_for(word as u8);
+    let (header, pool) = ConstantPool::from_image(&image).unwrap();
+    assert_eq!(header, ImageHeader { word_size: word as u8, global_count: 3, main: 0 });
+    assert_eq!(pool.target_word_size(), Some(word));
+    assert_eq!(pool.data, p.pool());
+    assert_eq!(ConstantPool::new(p.pool()).target_word_size(), None);
+
+    let mut heap_data = [ 0u8; 512 ];
+    let other_image = image_for(other);
+    let rv = Runtime::from_image(&other_image, &mut heap_data, RuntimeOptions::default());
+    assert_eq!(rv.err().map(|e| e.code), Some(ErrorCode::WordSizeMismatch));
+    assert_eq!(ConstantPool::from_image(&image_for(2)).err(), Some(ErrorCode::InvalidImage));
+    assert_eq!(ConstantPool::from_image(&image[1 ..]).err(), Some(ErrorCode::InvalidImage));
+    assert_eq!(ConstantPool::from_image(&image[.. 7]).err(), Some(ErrorCode::InvalidImage));
+
+    let mut results = [ 0 as usize; 1 ];
+    let mut r = Runtime::from_image(&image, &mut heap_data, RuntimeOptions::default()).unwrap();
+    assert_eq!(r.execute(header.main, &[], &mut results, None, None).ok(), Some(1));
+    assert_eq!(results[0], 30);
+    let mut heap_data = [ 0u8; 512 ];
+    let image = image_for(0);
+    assert!(Runtime::from_image(&image, &mut heap_data, RuntimeOptions::default()).is_ok());
+
+    // main at 200 takes a 2-byte uint, so the header is padded from 9 bytes to 16.
+    let p = Platform::with(&[ Bytes::constant(300), Bytes::constant(301), Bytes::basic_code(&[ CONST_0, SLOT_1, RETURN_1 ]) ]);
+    let mut image = vec![ 0xf0, 0x9f, 0x97, 0xbf, 1, word as u8, 3, 0xc8, 0x01, 0, 0, 0, 0, 0, 0, 0 ];
+    image.extend_from_slice(p.pool());
+    let (header, pool) = ConstantPool::from_image(&image).unwrap();
+    assert_eq!(header.main, 200);
+    assert_eq!(pool.data, p.pool());
+    assert_eq!(ConstantPool::from_image(&image[.. 12]).err(), Some(ErrorCode::InvalidImage));
+    let mut heap_data = [ 0u8; 512 ];
+    let mut r = Runtime::from_image(&image, &mut heap_data, RuntimeOptions::default()).unwrap();
+    assert_eq!(r.execute(p.get_constant(2), &[], &mut results, None, None).ok(), Some(1));
+    assert_eq!(results[0], 301);
+
+    // version 0 has no word size or padding.
+    let mut image = vec![ 0xf0, 0x9f, 0x97, 0xbf, 0, 3, 0 ];
+    image.extend_from_slice(p.pool());
+    let (header, pool) = ConstantPool::from_image(&image).unwrap();
+    assert_eq!(header, ImageHeader { word_size: 0, global_count: 3, main: 0 });
+    assert_eq!(pool.target_word_size(), None);
+    assert_eq!(pool.data, p.pool());
+    image[4] = 2;
+    assert_eq!(ConstantPool::from_image(&image).err(), Some(ErrorCode::InvalidImage));
+}
+
+#[test]
+fn overlapping_regions() {
+    let mut data = [ 0u8; 512 ];
+    let base = data.as_mut_ptr();
+    // the last 64 bytes of the heap are also the constant pool.
+    let (heap, pool) = unsafe {
+        (core::slice::from_raw_parts_mut(base, 512), core::slice::from_raw_parts(base.offset(448), 64))
+    };
+    let rv = Runtime::new(pool, heap, 2, None);
+    assert_eq!(rv.err().map(|e| e.code), Some(ErrorCode::OverlappingRegions));
+
+    let (heap, pool) = unsafe {
+        (core::slice::from_raw_parts_mut(base.offset(64), 64), core::slice::from_raw_parts(base, 512))
+    };
+    let rv = Runtime::new(pool, heap, 2, None);
+    assert_eq!(rv.err().map(|e| e.code), Some(ErrorCode::OverlappingRegions));
+
+    // adjacent is fine.
+    let (heap, pool) = unsafe {
+        (core::slice::from_raw_parts_mut(base, 448), core::slice::from_raw_parts(base.offset(448), 64))
+    };
+    assert!(Runtime::new(pool, heap, 2, None).is_ok());
+}
+
+#[test]
+fn heap_too_small() {
+    let pool = [ 0u8; 4 ];
+    let mut heap = [ 0u8; 8 ];
+    let rv = Runtime::new(&pool, &mut heap, 0, None);
+    assert_eq!(rv.err().map(|e| e.code), Some(ErrorCode::HeapTooSmall));
+
+    let mut heap = [ 0u8; 128 ];
+    assert!(Runtime::new(&pool, &mut heap, 2, None).is_ok());
+}
+
+#[test]
+fn aligned_objects() {
+    let aligned_new = |align: &[u8]| -> Bytes {
+        Bytes::basic_code(&[
+            NUM_30, NUM_2, NUM_1, align, STORE_LOCAL_0,
+            LOAD_LOCAL_0, SIZE, LOAD_LOCAL_0, SLOT_0, LOAD_LOCAL_0, TYPE_OF, LOAD_LOCAL_0, &[ Opcode::ReturnN as u8, 8 ],
+        ])
+    };
+    let word = mem::size_of::<usize>();
+    for &(align, bytes) in &[ (16, &[ Opcode::NewAlignedN as u8, 32 ][..]), (64, &[ Opcode::NewAlignedN as u8, 0x80, 1 ][..]) ] {
+        let mut p = Platform::with(&[ aligned_new(bytes) ]);
+        let mut r = p.to_runtime().unwrap();
+        let mut results = [ 0 as usize; 4 ];
+        assert_eq!(r.execute(0, &[], &mut results, None, None).ok(), Some(4));
+        assert_eq!(&results[0 .. 3], &[ 2, 30, 0 ]);
+        assert_eq!(results[3] % align, 0);
+        assert_eq!(r.object_slots(results[3]).ok(), Some(&[ 30, 0 ][..]));
+    }
+
+    // plain objects are only word-aligned, and so is asking for a word.
+    let mut p = Platform::with(&[ aligned_new(&[ Opcode::NewAlignedN as u8, (word << 1) as u8 ]) ]);
+    let mut results = [ 0 as usize; 4 ];
+    let rv = p.to_runtime().and_then(|mut r| r.execute(0, &[], &mut results, None, None));
+    assert_eq!(rv.ok(), Some(4));
+    assert_eq!(results[3] % word, 0);
+
+    let mut p = Platform::with(&[ aligned_new(&[ Opcode::NewAlignedN as u8, 24 ]) ]);
+    assert_eq!(format!("{:?}", p.execute1(0, &[])), "Err(Unaligned(0x43) at [frame code=0 pc=6 sp=1])");
+}
+
+#[test]
+fn binary_ref_equals() {
+    // two objects holding [ 1, 2, 0 ]
+    let mut p = Platform::with(&[ Bytes::basic_code(&[
+        NUM_1, NUM_2, NEW_3_2, STORE_LOCAL_0, NUM_1, NUM_2, NEW_3_2, STORE_LOCAL_1,
+        LOAD_LOCAL_0, LOAD_LOCAL_0, BINARY_REQ, LOAD_LOCAL_0, LOAD_LOCAL_1, BINARY_REQ, NUM_2, RETURN,
+    ]) ]);
+    assert_eq!(p.execute2(0, &[]).ok(), Some((1, 0)));
+}
+
+#[test]
+fn stack_stays_in_frame() {
+    let mut p = Platform::with(&[ Bytes::code(0, 2, &[ NUM_1, NUM_2, NUM_30, RETURN_1 ]) ]);
+    assert_eq!(format!("{:?}", p.execute1(0, &[])), "Err(StackOverflow(0x10) at [frame code=0 pc=4 sp=2])");
+
+    // asking for 256 values must not wrap around to 0.
+    let mut p = Platform::with(&[ Bytes::code(0, 2, &[ NUM_1, &[ Opcode::ReturnN as u8, 0x80, 4 ] ]) ]);
+    assert_eq!(format!("{:?}", p.execute0(0, &[])), "Err(StackUnderflow(0x1b) at [frame code=0 pc=2 sp=1])");
+
+    // 300 would be 44 as a u8, and there are 46 values to return.
+    let mut code: Vec<&[u8]> = vec![ NUM_1; 46 ];
+    code.push(&[ Opcode::Immediate as u8, 0xd8, 4 ]);
+    code.push(RETURN);
+    let mut p = Platform::with(&[ Bytes::code(0, 47, &code) ]);
+    let mut results = [ 0 as usize; 64 ];
+    let rv = p.to_runtime().and_then(|mut r| r.execute(0, &[], &mut results, None, None));
+    assert_eq!(format!("{:?}", rv), "Err(StackUnderflow(0x05) at [frame code=0 pc=5f sp=2e])");
+}
+
+#[test]
+fn frame_info() {
+    let mut p = Platform::with(&[ Bytes::code(3, 5, &[ FRAME_INFO, NUM_2, RETURN ]) ]);
+    assert_eq!(p.execute2(0, &[]).ok(), Some((3, 5)));
+
+    let mut p = Platform::with(&[ Bytes::code(0, 1, &[ FRAME_INFO ]) ]);
+    assert_eq!(format!("{:?}", p.execute0(0, &[])), "Err(StackOverflow(0x30) at [frame code=0 pc=0 sp=1])");
+}
+
+#[test]
+fn stack_room() {
+    // 2 of 4 stack slots are used, so there's room for 2 more.
+    let mut p = Platform::with(&[ Bytes::code(0, 4, &[ NUM_1, NUM_1, &room(3), RETURN_1 ]) ]);
+    assert_eq!(p.execute1(0, &[]).ok(), Some(0));
+
+    let mut p = Platform::with(&[ Bytes::code(0, 4, &[ NUM_1, NUM_1, &room(2), RETURN_1 ]) ]);
+    assert_eq!(p.execute1(0, &[]).ok(), Some(1));
+
+    let mut p = Platform::with(&[ Bytes::code(0, 4, &[ NUM_1, NUM_1, NUM_1, &room(1), RETURN_1 ]) ]);
+    assert_eq!(p.execute1(0, &[]).ok(), Some(1));
+}
+
+#[test]
+fn stack_address() {
+    let mut p = Platform::with(&[ Bytes::basic_code(&[
+        NUM_30, NUM_128, &saddr(1), NUM_0, NUM_2, STORE_SLOT, NUM_2, RETURN
+    ]) ]);
+    assert_eq!(p.execute2(0, &[]).ok(), Some((2, 128)));
+
+    let mut p = Platform::with(&[ Bytes::basic_code(&[ NUM_30, &saddr(0), SLOT_0, RETURN_1 ]) ]);
+    assert_eq!(p.execute1(0, &[]).ok(), Some(30));
+
+    let mut p = Platform::with(&[ Bytes::basic_code(&[ NUM_30, &saddr(1), RETURN_1 ]) ]);
+    assert_eq!(format!("{:?}", p.execute1(0, &[])), "Err(StackUnderflow(0x1f) at [frame code=0 pc=2 sp=1])");
 }
 
 #[test]
@@ -378,6 +1798,119 @@ fn call_double_and_return() {
     assert_eq!(p.execute1(p.get_constant(1), &[]).ok(), Some(60));
 }
 
+#[test]
+fn call_depth() {
+    let depth = &[ Opcode::CallDepth as u8 ][..];
+    let mut p = Platform::new();
+    let leaf = p.add_constant(Bytes::basic_code(&[ depth, RETURN_1 ]).to_bytes());
+    let call_leaf = &[ Opcode::Constant as u8, (leaf << 1) as u8 ][..];
+    let middle = p.add_constant(Bytes::basic_code(&[ depth, call_leaf, CALL_0, NUM_2, RETURN ]).to_bytes());
+    let call_middle = &[ Opcode::Constant as u8, (middle << 1) as u8 ][..];
+    let main = p.add_constant(Bytes::basic_code(&[ depth, call_middle, CALL_0, NUM_2, NUM_1, BINARY_ADD, RETURN ]).to_bytes());
+    let mut r = p.to_runtime().unwrap();
+    let mut results = [ 0 as usize; 3 ];
+    assert_eq!(r.execute(main, &[], &mut results, None, None).ok(), Some(3));
+    assert_eq!(results, [ 0, 1, 2 ]);
+}
+
+#[test]
+fn intercept_call() {
+    fn only_double(code_offset: u32) -> bool {
+        code_offset == 0
+    }
+
+    let mut p = Platform::with(&[
+        // double:
+        Bytes::basic_code(&[ LOAD_LOCAL_0, NUM_2, BINARY_MUL, RETURN_1 ]),
+        Bytes::basic_code(&[ NUM_30, CONST_0, CALL_1, CONST_0, CALL_1, RETURN_1 ]),
+    ]);
+    let main = p.get_constant(1);
+    let options = RuntimeOptions { call_interceptor: Some(only_double), ..RuntimeOptions::default() };
+    // a dry run isn't intercepted, since there would be nothing to resume.
+    assert_eq!(p.to_runtime_with(options).unwrap().dry_run(main, &[], None, None, None).ok(), Some(1));
+
+    let mut r = p.to_runtime_with(options).unwrap();
+    let mut results = [ 0 as usize; 1 ];
+    assert_eq!(format!("{:?}", r.execute(main, &[], &mut results, None, None)), "Err(CallIntercepted(0x1a) at [frame code=4 pc=6 sp=1])");
+    assert_eq!(r.intercepted_call(), Some((0, &[ 30 as usize ][..])));
+
+    // a dry run that fills the heap leaves the suspended call alone.
+    assert_eq!(r.dry_run(main, &[], None, None, None).ok(), Some(1));
+    assert_eq!(r.intercepted_call(), Some((0, &[ 30 as usize ][..])));
+
+    // let the first call happen, but fake the second one.
+    assert_eq!(format!("{:?}", r.resume(&mut results, None, None)), "Err(CallIntercepted(0x1a) at [frame code=4 pc=a sp=1])");
+    assert_eq!(r.intercepted_call(), Some((0, &[ 60 as usize ][..])));
+    assert_eq!(r.resume_with(&[ 7 ], &mut results, None, None).ok(), Some(1));
+    assert_eq!(results[0], 7);
+    assert_eq!(r.intercepted_call(), None);
+    assert_eq!(format!("{:?}", r.resume(&mut results, None, None)), "Err(NotSuspended)");
+}
+
+#[test]
+fn select() {
+    let mut p = Platform::with(&[ Bytes::basic_code(&[ NUM_30, NUM_128, NUM_1, SELECT, RETURN_1 ]) ]);
+    assert_eq!(p.execute1(0, &[]).ok(), Some(30));
+
+    let mut p = Platform::with(&[ Bytes::basic_code(&[ NUM_30, NUM_128, NUM_0, SELECT, RETURN_1 ]) ]);
+    assert_eq!(p.execute1(0, &[]).ok(), Some(128));
+
+    // any non-zero is true
+    let mut p = Platform::with(&[ Bytes::basic_code(&[ NUM_30, NUM_128, NUM_N1, SELECT, RETURN_1 ]) ]);
+    assert_eq!(p.execute1(0, &[]).ok(), Some(30));
+
+    let mut p = Platform::with(&[ Bytes::basic_code(&[ NUM_128, NUM_1, SELECT, RETURN_1 ]) ]);
+    assert_eq!(format!("{:?}", p.execute1(0, &[])), "Err(StackUnderflow(0x34) at [frame code=0 pc=5 sp=0])");
+}
+
+#[test]
+fn reachable_functions() {
+    let mut p = Platform::with(&[
+        // double:
+        Bytes::basic_code(&[ LOAD_LOCAL_0, NUM_2, BINARY_MUL, RETURN_1 ]),
+        // never called:
+        Bytes::basic_code(&[ NUM_30, RETURN_1 ]),
+        Bytes::basic_code(&[ NUM_30, CONST_0, CALL_1, RETURN_1 ]),
+    ]);
+    let (double, unused, main) = (p.get_constant(0), p.get_constant(1), p.get_constant(2));
+    let pool = ConstantPool::new(p.pool());
+    let mut reachable = [ 0u32; 4 ];
+    let rv = reachable_code(&pool, &[ main ], &mut reachable);
+    assert_eq!(rv, Ok(Reachability { count: 2, has_dynamic_calls: false }));
+    assert_eq!(&reachable[0 .. 2], &[ main, double ]);
+    assert!(!reachable[0 .. 2].contains(&unused));
+
+    // calling through a local can't be followed.
+    let mut p = Platform::with(&[ Bytes::basic_code(&[ NUM_30, LOAD_LOCAL_0, CALL_1, RETURN_1 ]) ]);
+    let pool = ConstantPool::new(p.pool());
+    let rv = reachable_code(&pool, &[ 0 ], &mut reachable);
+    assert_eq!(rv, Ok(Reachability { count: 1, has_dynamic_calls: true }));
+}
+
+#[test]
+fn stack_depth() {
+    let mut depths = [ 0u8; 64 ];
+    let code = Bytes::bytecode(&[ NUM_1, NUM_2, DUP, BINARY_ADD, BINARY_ADD, RETURN_1 ]);
+    assert_eq!(max_stack_depth(&Code::new(0, 8, code.to_bytes()), &mut depths), Ok(Some(3)));
+
+    // both sides of a branch count, even if one never runs:
+    let code = Bytes::bytecode(&[ NUM_1, NUM_1, &jptr(14), NUM_1, NUM_1, NUM_1, RETURN_1, NUM_2, RETURN_1 ]);
+    assert_eq!(max_stack_depth(&Code::new(0, 8, code.to_bytes()), &mut depths), Ok(Some(4)));
+    let code = Bytes::bytecode(&[ NUM_0, IF, NUM_1, NUM_2, RETURN_1 ]);
+    assert_eq!(max_stack_depth(&Code::new(0, 8, code.to_bytes()), &mut depths), Ok(Some(2)));
+
+    // a loop that keeps pushing has no limit, and neither does a call.
+    let code = Bytes::bytecode(&[ NUM_1, &jump(0) ]);
+    assert_eq!(max_stack_depth(&Code::new(0, 8, code.to_bytes()), &mut depths), Ok(None));
+    let code = Bytes::bytecode(&[ CONST_0, CALL_0, RETURN_1 ]);
+    assert_eq!(max_stack_depth(&Code::new(0, 8, code.to_bytes()), &mut depths), Ok(None));
+
+    let code = Bytes::bytecode(&[ NUM_1, BINARY_ADD ]);
+    assert_eq!(max_stack_depth(&Code::new(0, 8, code.to_bytes()), &mut depths), Err(ErrorCode::StackUnderflow));
+    let code = Bytes::bytecode(&[ NUM_1, &jump(1) ]);
+    assert_eq!(max_stack_depth(&Code::new(0, 8, code.to_bytes()), &mut depths), Err(ErrorCode::OutOfBounds));
+}
+
 #[test]
 fn conditional() {
     let mut p = Platform::with(&[ Bytes::basic_code(&[ NUM_30, NUM_1, IF, RETURN_1, NUM_2, RETURN_1 ]) ]);
@@ -402,7 +1935,279 @@ fn jump_around() {
     assert_eq!(p.execute1(0, &[]).ok(), Some(30));
 
     let mut p = Platform::with(&[ Bytes::basic_code(&[ &jump(9) ]) ]);
-    assert_eq!(format!("{:?}", p.execute1(0, &[])), "Err(OutOfBounds at [frame code=0 pc=0 sp=0])");
+    assert_eq!(format!("{:?}", p.execute1(0, &[])), "Err(OutOfBounds(0x1c) at [frame code=0 pc=0 sp=0])");
+}
+
+#[test]
+fn jump_relative() {
+    // forward over a return:
+    let mut p = Platform::with(&[ Bytes::basic_code(&[ NUM_1, &jrel(4), NUM_2, RETURN_1 ]) ]);
+    assert_eq!(p.execute1(0, &[]).ok(), Some(1));
+
+    // add 30 to @1, @0 times, by looping back.
+    let mut p = Platform::with(&[ Bytes::basic_code(&[
+        NUM_0, STORE_LOCAL_1,
+        LOAD_LOCAL_1, NUM_30, BINARY_ADD, STORE_LOCAL_1, &[ Opcode::DecTestLocalN as u8, 0 ], IF, &jrel(4), &jrel(-13),
+        LOAD_LOCAL_1, RETURN_1,
+    ]) ]);
+    assert_eq!(p.execute1(0, &[ 3 ]).ok(), Some(90));
+
+    let mut p = Platform::with(&[ Bytes::basic_code(&[ &jrel(-1) ]) ]);
+    assert_eq!(p.execute1(0, &[]).err().map(|e| e.code), Some(ErrorCode::OutOfBounds));
+    let mut p = Platform::with(&[ Bytes::basic_code(&[ NUM_1, &jrel(4) ]) ]);
+    assert_eq!(p.execute1(0, &[]).err().map(|e| e.code), Some(ErrorCode::OutOfBounds));
+}
+
+#[test]
+fn jump_if_zero() {
+    let jz = |target: u8| [ Opcode::JumpIfZero as u8, target << 1 ];
+    let jnz = |target: u8| [ Opcode::JumpIfNonZero as u8, target << 1 ];
+    let mut p = Platform::with(&[
+        Bytes::basic_code(&[ NUM_0, &jz(8), NUM_30, RETURN_1, NUM_128, RETURN_1 ]),
+        Bytes::basic_code(&[ NUM_1, &jz(8), NUM_30, RETURN_1, NUM_128, RETURN_1 ]),
+        Bytes::basic_code(&[ NUM_1, &jnz(8), NUM_30, RETURN_1, NUM_128, RETURN_1 ]),
+        Bytes::basic_code(&[ NUM_0, &jnz(8), NUM_30, RETURN_1, NUM_128, RETURN_1 ]),
+        Bytes::basic_code(&[ NUM_0, &jz(30) ]),
+    ]);
+    assert_eq!(p.execute1(p.get_constant(0), &[]).ok(), Some(128));
+    assert_eq!(p.execute1(p.get_constant(1), &[]).ok(), Some(30));
+    assert_eq!(p.execute1(p.get_constant(2), &[]).ok(), Some(128));
+    assert_eq!(p.execute1(p.get_constant(3), &[]).ok(), Some(30));
+    assert_eq!(p.execute1(p.get_constant(4), &[]).err().map(|e| e.code), Some(ErrorCode::OutOfBounds));
+}
+
+#[test]
+fn jump_if_pointer() {
+    let mut p = Platform::with(&[ Bytes::basic_code(&[
+        NUM_128, NUM_2, NEW_3_2, &jptr(14), NUM_0, RETURN_1, NUM_1, RETURN_1
+    ]) ]);
+    assert_eq!(p.execute1(0, &[]).ok(), Some(1));
+
+    let mut p = Platform::with(&[
+        Bytes::constant(300),
+        Bytes::basic_code(&[ CONST_0, &jptr(8), NUM_0, RETURN_1, NUM_1, RETURN_1 ]),
+    ]);
+    assert_eq!(p.execute1(p.get_constant(1), &[]).ok(), Some(1));
+
+    let mut p = Platform::with(&[ Bytes::basic_code(&[ NUM_30, &jptr(8), NUM_0, RETURN_1, NUM_1, RETURN_1 ]) ]);
+    assert_eq!(p.execute1(0, &[]).ok(), Some(0));
+
+    let mut p = Platform::with(&[ Bytes::basic_code(&[ NUM_128, NUM_2, NEW_3_2, &jptr(30) ]) ]);
+    assert_eq!(format!("{:?}", p.execute1(0, &[])), "Err(OutOfBounds(0x1e) at [frame code=0 pc=8 sp=0])");
+}
+
+#[test]
+fn jump_dynamic() {
+    let jump_to = |target: u8| -> Bytes {
+        Bytes::basic_code(&[ &[ Opcode::Immediate as u8, target << 1 ], &[ Opcode::JumpDynamic as u8 ], NUM_30, RETURN_1, NUM_128, RETURN_1 ])
+    };
+
+    let mut p = Platform::with(&[ jump_to(7) ]);
+    assert_eq!(p.execute1(0, &[]).ok(), Some(128));
+
+    let mut p = Platform::with(&[ jump_to(3) ]);
+    assert_eq!(p.execute1(0, &[]).ok(), Some(30));
+
+    let mut p = Platform::with(&[ jump_to(12) ]);
+    assert_eq!(format!("{:?}", p.execute1(0, &[])), "Err(OutOfBounds(0x36) at [frame code=0 pc=2 sp=0])");
+
+    // the middle of `NUM_128`
+    let options = RuntimeOptions { check_jump_targets: true, ..RuntimeOptions::default() };
+    let mut p = Platform::with(&[ jump_to(8) ]);
+    let mut r = p.to_runtime_with(options).unwrap();
+    let mut results = [ 0 as usize; 1 ];
+    assert_eq!(format!("{:?}", r.execute(0, &[], &mut results, None, None)), "Err(OutOfBounds(0x36) at [frame code=0 pc=2 sp=0])");
+
+    let mut p = Platform::with(&[ jump_to(7) ]);
+    let mut r = p.to_runtime_with(options).unwrap();
+    assert_eq!(r.execute(0, &[], &mut results, None, None).ok(), Some(1));
+    assert_eq!(results[0], 128);
+}
+
+#[test]
+fn expected_return_count() {
+    let call_expecting = |results: u8| -> Bytes {
+        Bytes::basic_code(&[ NUM_30, CONST_0, &[ Opcode::CallNN as u8, 2, results << 1 ], NUM_1, RETURN_1 ])
+    };
+    let void = Bytes::basic_code(&[ RETURN_0 ]);
+
+    let mut p = Platform::with(&[ void, call_expecting(0) ]);
+    assert_eq!(p.execute1(p.get_constant(1), &[]).ok(), Some(1));
+
+    let void = Bytes::basic_code(&[ RETURN_0 ]);
+    let mut p = Platform::with(&[ void, call_expecting(1) ]);
+    assert_eq!(
+        format!("{:?}", p.execute1(p.get_constant(1), &[])),
+        "Err(ReturnCountMismatch(0x1b) at [frame code=0 pc=0 sp=0] -> [frame code=2 pc=7 sp=0])"
+    );
+
+    // plain calls don't check.
+    let mut p = Platform::with(&[ Bytes::basic_code(&[ RETURN_0 ]), Bytes::basic_code(&[ NUM_30, CONST_0, CALL_1, NUM_1, RETURN_1 ]) ]);
+    assert_eq!(p.execute1(p.get_constant(1), &[]).ok(), Some(1));
+}
+
+#[test]
+fn soft_reset() {
+    // $0 := a new 3-slot object, then build some garbage.
+    let mut p = Platform::with(&[ Bytes::basic_code(&[
+        NUM_2, NUM_30, &[ Opcode::NewNN as u8, 6, 4 ], STORE_GLOBAL_0, NUM_1, STORE_GLOBAL_1,
+        NUM_1, NUM_2, NEW_3_2, DROP, NUM_1, NUM_2, NEW_3_2, DROP, RETURN_0,
+    ]), Bytes::basic_code(&[ LOAD_GLOBAL_1, LOAD_GLOBAL_0, SLOT_1, &[ Opcode::ReturnN as u8, 4 ] ]) ]);
+    let check = p.get_constant(1);
+    let mut r = p.to_runtime().unwrap();
+    let free = r.heap_free_bytes();
+    let mut results = [ 0 as usize; 2 ];
+    assert_eq!(r.execute(0, &[], &mut results, None, None).ok(), Some(0));
+    let used = free - r.heap_free_bytes();
+
+    r.soft_reset();
+    let kept = free - r.heap_free_bytes();
+    assert!(kept > 0 && kept < used);
+    assert_eq!(r.execute(check, &[], &mut results, None, None).ok(), Some(2));
+    assert_eq!(results, [ 1, 30 ]);
+
+    // nothing new to free.
+    r.soft_reset();
+    assert_eq!(free - r.heap_free_bytes(), kept);
+}
+
+#[test]
+fn throw() {
+    let mut p = Platform::with(&[ Bytes::basic_code(&[ NUM_30, &[ Opcode::Throw as u8 ], NUM_1, RETURN_1 ]) ]);
+    let error = p.execute1(0, &[]).unwrap_err();
+    assert_eq!(error.code, ErrorCode::Thrown);
+    assert_eq!(error.thrown, Some(30));
+    assert_eq!(format!("{:?}", error), "Thrown(30) at [frame code=0 pc=2 sp=0]");
+
+    // built-in errors don't carry a thrown code.
+    let error = Platform::with(&[ Bytes::basic_code(&[ BREAK ]) ]).execute1(0, &[]).unwrap_err();
+    assert_eq!(error.thrown, None);
+
+    let mut p = Platform::with(&[ Bytes::basic_code(&[ NUM_N1, &[ Opcode::Throw as u8 ] ]) ]);
+    assert_eq!(format!("{:?}", p.execute1(0, &[])), "Err(OutOfBounds(0x38) at [frame code=0 pc=2 sp=0])");
+}
+
+#[test]
+fn weak_refs() {
+    let new_weak = &[ Opcode::NewWeak as u8 ][..];
+    let mut p = Platform::with(&[
+        // $0 := weak ref to a new object, held by nothing else
+        Bytes::basic_code(&[ NUM_1, NUM_2, NEW_3_2, new_weak, STORE_GLOBAL_0, RETURN_0 ]),
+        // same, but $1 holds the object too
+        Bytes::basic_code(&[ NUM_1, NUM_2, NEW_3_2, DUP, STORE_GLOBAL_1, new_weak, STORE_GLOBAL_0, RETURN_0 ]),
+        Bytes::basic_code(&[ LOAD_GLOBAL_0, SLOT_0, LOAD_GLOBAL_1, BINARY_REQ, LOAD_GLOBAL_0, SLOT_0, NUM_2, RETURN ]),
+    ]);
+    let (weak_only, held, check) = (p.get_constant(0), p.get_constant(1), p.get_constant(2));
+    let mut r = p.to_runtime().unwrap();
+    let mut results = [ 0 as usize; 2 ];
+    assert_eq!(r.execute(weak_only, &[], &mut results, None, None).ok(), Some(0));
+    assert_eq!(r.execute(check, &[], &mut results, None, None).ok(), Some(2));
+    assert!(results[1] != 0);
+    r.soft_reset();
+    assert_eq!(r.execute(check, &[], &mut results, None, None).ok(), Some(2));
+    assert_eq!(results, [ 1, 0 ]);
+
+    assert_eq!(r.execute(held, &[], &mut results, None, None).ok(), Some(0));
+    r.soft_reset();
+    assert_eq!(r.execute(check, &[], &mut results, None, None).ok(), Some(2));
+    assert_eq!(results[0], 1);
+    assert!(results[1] != 0);
+
+    // there's no fixed limit on how many there can be, and every one that
+    // points at the same dead object is cleared.
+    let mut p = Platform::with(&[ Bytes::basic_code(&[ LOAD_LOCAL_0, STORE_GLOBAL_1, RETURN_0 ]) ]);
+    let hold = p.get_constant(0);
+    let mut r = p.to_runtime().unwrap();
+    let weak = r.alloc_object(9).unwrap();
+    assert_eq!(r.execute(hold, &[ weak ], &mut results, None, None).ok(), Some(0));
+    r.soft_reset();
+    let target = r.alloc_object(1).unwrap();
+    r.unpin(target);
+    for i in 0 .. 9 {
+        let w = r.new_weak_ref(target).unwrap();
+        r.write_object(weak, i, w).unwrap();
+    }
+    r.soft_reset();
+    for i in 0 .. 9 { assert_eq!(r.read_object(r.read_object(weak, i).unwrap(), 0), Ok(0)); }
+
+    // making them collects garbage when the heap is full, like `New` does.
+    let mut p = Platform::with(&[ Bytes::basic_code(&[
+        NUM_1, NUM_2, NEW_3_2, new_weak, STORE_LOCAL_1,
+        &[ Opcode::DecTestLocalN as u8, 0 ], &[ Opcode::JumpIfZero as u8, 0 ], RETURN_0,
+    ]) ]);
+    assert_eq!(p.execute0(0, &[ 50 ]).ok(), Some(()));
+
+    // they're tracked in a list in the heap, which code can't read or overwrite.
+    let mut p = Platform::with(&[ Bytes::basic_code(&[ LOAD_LOCAL_0, STORE_GLOBAL_1, RETURN_0 ]) ]);
+    let mut r = p.to_runtime().unwrap();
+    let target = r.alloc_object(1).unwrap();
+    r.unpin(target);
+    let w = r.new_weak_ref(target).unwrap();
+    let word = mem::size_of::<usize>();
+    for addr in (w - 32 * word .. w).step_by(word) {
+        match r.load_slot(addr, 0) {
+            Ok(value) => assert_ne!(value, w),
+            Err(_) => assert!(r.store_slot(addr, 0, 0x10).is_err()),
+        }
+    }
+    assert_eq!(r.execute(hold, &[ w ], &mut results, None, None).ok(), Some(0));
+    r.soft_reset();
+    assert_eq!(r.read_object(w, 0), Ok(0));
+}
+
+#[test]
+fn results_wrapper() {
+    let mut p = Platform::with(&[ Bytes::basic_code(&[ NUM_2, NUM_30, &[ Opcode::ReturnN as u8, 4 ] ]) ]);
+    let mut r = p.to_runtime().unwrap();
+    let mut buffer = [ 0 as usize; 4 ];
+    let results = r.execute_results(0, &[], &mut buffer, None, None).unwrap();
+    assert_eq!(results.len(), 2);
+    assert_eq!(results[0], 2);
+    assert_eq!(results[1], 30);
+    assert_eq!(results.iter().sum::<usize>(), 32);
+    // the rest of the buffer isn't visible.
+    assert_eq!(results.get(2), None);
+    assert!(std::panic::catch_unwind(|| results[2]).is_err());
+}
+
+#[test]
+fn new_from_stack() {
+    let new_from_stack = &[ Opcode::NewFromStack as u8 ][..];
+    let mut p = Platform::with(&[ Bytes::code(1, 8, &[
+        NUM_1, NUM_2, NUM_30, &[ Opcode::Immediate as u8, 6 ], new_from_stack, STORE_LOCAL_0,
+        LOAD_LOCAL_0, SIZE, LOAD_LOCAL_0, SLOT_0, LOAD_LOCAL_0, SLOT_1, LOAD_LOCAL_0, SLOT_2, &[ Opcode::ReturnN as u8, 8 ],
+    ]) ]);
+    let mut results = [ 0 as usize; 4 ];
+    let rv = p.to_runtime().and_then(|mut r| r.execute(0, &[], &mut results, None, None));
+    assert_eq!(rv.ok(), Some(4));
+    assert!(results[0] >= 3);
+    assert_eq!(&results[1 ..], &[ 1, 2, 30 ]);
+
+    let mut p = Platform::with(&[ Bytes::basic_code(&[ NUM_1, NUM_2, new_from_stack, RETURN_1 ]) ]);
+    assert_eq!(format!("{:?}", p.execute1(0, &[])), "Err(StackUnderflow(0x39) at [frame code=0 pc=4 sp=1])");
+
+    let mut p = Platform::with(&[ Bytes::basic_code(&[ &[ Opcode::Immediate as u8, 0x82, 1 ], new_from_stack, RETURN_1 ]) ]);
+    assert_eq!(format!("{:?}", p.execute1(0, &[])), "Err(InvalidSize(0x39) at [frame code=0 pc=3 sp=0])");
+}
+
+#[test]
+fn capture_stack() {
+    let mut p = Platform::with(&[ Bytes::code(1, 8, &[
+        NUM_1, NUM_2, NUM_30, CAPTURE_STACK, STORE_LOCAL_0,
+        LOAD_LOCAL_0, SLOT_0, LOAD_LOCAL_0, SLOT_1, LOAD_LOCAL_0, SLOT_2, &[ Opcode::ReturnN as u8, 12 ],
+    ]) ]);
+    let mut r = p.to_runtime().unwrap();
+    let mut results = [ 0 as usize; 6 ];
+    assert_eq!(r.execute(0, &[], &mut results, None, None).ok(), Some(6));
+    // the captured values are still on the stack, under a copy of them.
+    assert_eq!(results, [ 1, 2, 30, 1, 2, 30 ]);
+
+    // capturing over and over collects the old copies when the heap fills up.
+    let mut p = Platform::with(&[ Bytes::code(2, 8, &[
+        NUM_1, NUM_2, NUM_30, CAPTURE_STACK, STORE_LOCAL_1,
+        &[ Opcode::DecTestLocalN as u8, 0 ], &[ Opcode::JumpIfZero as u8, 12 ], RETURN_0,
+    ]) ]);
+    assert_eq!(p.execute0(0, &[ 50 ]).ok(), Some(()));
 }
 
 #[test]
@@ -421,6 +2226,26 @@ fn current_time() -> usize {
     }
 }
 
+#[test]
+fn cycle_costs() {
+    assert_eq!(opcode_cost(Opcode::Nop), 1);
+    assert_eq!(opcode_cost(Opcode::Immediate), 1);
+    assert_eq!(opcode_cost(Opcode::LoadSlotN), 2);
+    assert_eq!(opcode_cost(Opcode::CallN), 4);
+    assert_eq!(opcode_cost(Opcode::New), 4);
+
+    // NUM_1 NUM_0 NEW = 6 cycles, RETURN_1 is 4 more.
+    let code: &[&[u8]] = &[ NUM_1, NUM_0, NEW, RETURN_1 ];
+    let mut p = Platform::with(&[ Bytes::basic_code(code) ]);
+    let mut results = [ 0 as usize; 4 ];
+    let rv = p.to_runtime().and_then(|mut r| r.execute(0, &[], &mut results, num::NonZeroUsize::new(10), None));
+    assert_eq!(rv.ok(), Some(1));
+
+    let mut p = Platform::with(&[ Bytes::basic_code(code) ]);
+    let rv = p.to_runtime().and_then(|mut r| r.execute(0, &[], &mut results, num::NonZeroUsize::new(9), None));
+    assert_eq!(format!("{:?}", rv), "Err(CyclesExceeded at [frame code=0 pc=5 sp=1])");
+}
+
 #[test]
 fn time_limit() {
     let mut p = Platform::with(&[ Bytes::basic_code(&[ &jump(0) ]) ]);
@@ -432,6 +2257,212 @@ fn time_limit() {
     assert_eq!(format!("{:?}", rv), "Err(TimeExceeded at [frame code=0 pc=0 sp=0])");
 }
 
+// starts 10 ticks before wrapping around to 0.
+static mut WRAPPING_TIMER: usize = core::usize::MAX - 10;
+fn wrapping_time() -> usize {
+    unsafe {
+        WRAPPING_TIMER = WRAPPING_TIMER.wrapping_add(1);
+        WRAPPING_TIMER
+    }
+}
+
+#[test]
+fn time_limit_across_wrap() {
+    // the deadline is 20 ticks away, after the timer wraps.
+    let deadline = num::NonZeroUsize::new(10);
+    let mut p = Platform::with(&[ Bytes::basic_code(&[ NUM_30, RETURN_1 ]) ]);
+    let mut results = [ 0 as usize; 4 ];
+    let rv = p.to_timed_runtime(Some(wrapping_time)).and_then(|mut r| r.execute(0, &[], &mut results, None, deadline));
+    assert_eq!(rv.ok(), Some(1));
+
+    let mut p = Platform::with(&[ Bytes::basic_code(&[ &jump(0) ]) ]);
+    let rv = p.to_timed_runtime(Some(wrapping_time)).and_then(|mut r| r.execute(0, &[], &mut results, None, deadline));
+    assert_eq!(format!("{:?}", rv), "Err(TimeExceeded at [frame code=0 pc=0 sp=0])");
+    assert_eq!(unsafe { WRAPPING_TIMER }, 10);
+}
+
+#[test]
+fn dry_run_heap_limit() {
+    // allocate objects forever, each one holding the one before:
+    let mut p = Platform::with(&[
+        Bytes::code(1, 4, &[ LOAD_LOCAL_0, &[ Opcode::NewNN as u8, 4, 2 ], STORE_LOCAL_0, &jump(0) ]),
+        Bytes::code(0, 4, &[ NUM_1, NUM_0, NEW, DROP, &jump(0) ]),
+    ]);
+    let (chain, garbage) = (p.get_constant(0), p.get_constant(1));
+    let mut r = p.to_runtime().unwrap();
+    let rv = r.dry_run(chain, &[], num::NonZeroUsize::new(1000), Some(96), None);
+    assert_eq!(format!("{:?}", rv), "Err(HeapExceeded at [frame code=0 pc=5 sp=1])");
+
+    // if the heap budget is fine, the cycle limit is next.
+    let rv = r.dry_run(chain, &[], num::NonZeroUsize::new(10), Some(256), None);
+    assert_eq!(format!("{:?}", rv), "Err(CyclesExceeded at [frame code=0 pc=2 sp=1])");
+
+    // garbage doesn't count against the budget.
+    let rv = r.dry_run(garbage, &[], num::NonZeroUsize::new(1000), Some(96), None);
+    assert_eq!(rv.err().map(|e| e.code), Some(ErrorCode::CyclesExceeded));
+}
+
+#[test]
+fn execute_named() {
+    let mut p = Platform::with(&[
+        Bytes::basic_code(&[ NUM_30, RETURN_1 ]),
+        Bytes::basic_code(&[ NUM_128, RETURN_1 ]),
+    ]);
+    let (thirty, big) = (p.get_constant(0), p.get_constant(1));
+    let symbols = p.add_constant(&[ 2, 6, b't', b'h', b'i', b'r', b't', b'y', thirty as u8, 3, b'b', b'i', b'g', big as u8 ]);
+    let mut r = p.to_runtime_with(RuntimeOptions { symbol_table: Some(symbols), ..RuntimeOptions::default() }).unwrap();
+    let mut results = [ 0 as usize; 1 ];
+    assert_eq!(r.execute_named("big", &[], &mut results, None, None).ok(), Some(1));
+    assert_eq!(results[0], 128);
+    assert_eq!(r.execute_named("thirty", &[], &mut results, None, None).ok(), Some(1));
+    assert_eq!(results[0], 30);
+    let rv = r.execute_named("missing", &[], &mut results, None, None);
+    assert_eq!(rv.err().map(|e| e.code), Some(ErrorCode::UnknownSymbol));
+
+    // no symbol table, no names.
+    let mut r = p.to_runtime().unwrap();
+    let rv = r.execute_named("big", &[], &mut results, None, None);
+    assert_eq!(rv.err().map(|e| e.code), Some(ErrorCode::UnknownSymbol));
+}
+
+#[test]
+fn collect_when_full() {
+    // make a 64-byte object and drop it, as many times as local 0 says, then
+    // return the last one made, which still has the count in it.
+    let mut p = Platform::with(&[ Bytes::basic_code(&[
+        LOAD_LOCAL_0, &[ Opcode::NewNN as u8, 16, 2 ], STORE_LOCAL_1,
+        &[ Opcode::DecTestLocalN as u8, 0 ], &[ Opcode::JumpIfZero as u8, 0 ],
+        LOAD_LOCAL_1, SLOT_0, RETURN_1,
+    ]) ]);
+    let mut r = p.to_runtime().unwrap();
+    let mut results = [ 0 as usize; 1 ];
+    assert_eq!(r.execute(0, &[ 50 ], &mut results, None, None).ok(), Some(1));
+    assert_eq!(results[0], 1);
+
+    // a dry run collects too, so it gets just as far.
+    assert_eq!(r.dry_run(0, &[ 50 ], None, None, None).ok(), Some(1));
+
+    // if everything is still reachable, it runs out anyway.
+    let mut p = Platform::with(&[ Bytes::basic_code(&[
+        LOAD_LOCAL_1, &[ Opcode::NewNN as u8, 16, 2 ], STORE_LOCAL_1,
+        &[ Opcode::DecTestLocalN as u8, 0 ], &[ Opcode::JumpIfZero as u8, 0 ], NUM_0, RETURN_1,
+    ]) ]);
+    let mut r = p.to_runtime().unwrap();
+    let rv = r.execute(0, &[ 50, 0 ], &mut results, None, None);
+    assert_eq!(rv.err().map(|e| e.code), Some(ErrorCode::OutOfMemory));
+}
+
+#[test]
+fn collect_keeps_caller_objects() {
+    let mut p = Platform::with(&[
+        // make garbage until it's collected:
+        Bytes::basic_code(&[
+            &[ Opcode::Immediate as u8, 100 ], STORE_LOCAL_0,
+            LOAD_LOCAL_0, &[ Opcode::NewNN as u8, 16, 2 ], STORE_LOCAL_1,
+            &[ Opcode::DecTestLocalN as u8, 0 ], &[ Opcode::JumpIfZero as u8, 8 ], RETURN_0,
+        ]),
+        // an object that only this frame refers to, across the call:
+        Bytes::basic_code(&[
+            NUM_30, &[ Opcode::NewNN as u8, 2, 2 ], STORE_LOCAL_0, CONST_0, CALL_0, LOAD_LOCAL_0, SLOT_0, RETURN_1,
+        ]),
+    ]);
+    assert_eq!(p.execute1(p.get_constant(1), &[]).ok(), Some(30));
+}
+
+#[test]
+fn collect_ignores_popped_values() {
+    // an object that was only ever on the stack, under a weak reference to it:
+    let mut p = Platform::with(&[ Bytes::basic_code(&[
+        NUM_0, NUM_30, &[ Opcode::NewNN as u8, 2, 2 ], DUP, &[ Opcode::NewWeak as u8 ], &[ Opcode::StoreLocalN as u8, 4 ], DROP, DROP,
+        LOAD_LOCAL_0, &[ Opcode::NewNN as u8, 16, 2 ], STORE_LOCAL_1,
+        &[ Opcode::DecTestLocalN as u8, 0 ], &[ Opcode::JumpIfZero as u8, 26 ],
+        &[ Opcode::LoadLocalN as u8, 4 ], SLOT_0, RETURN_1,
+    ]) ]);
+    // once it's popped, a collection clears the weak reference.
+    assert_eq!(p.execute1(0, &[ 50 ]).ok(), Some(0));
+}
+
+#[test]
+fn collect_keeps_pinned_objects() {
+    // make garbage until it's collected, then read slot 0 of the object in local 1.
+    let mut p = Platform::with(&[ Bytes::basic_code(&[
+        &[ Opcode::Immediate as u8, 100 ], STORE_LOCAL_0,
+        LOAD_LOCAL_0, &[ Opcode::NewNN as u8, 16, 2 ], DROP,
+        &[ Opcode::DecTestLocalN as u8, 0 ], &[ Opcode::JumpIfZero as u8, 8 ], LOAD_LOCAL_1, SLOT_0, RETURN_1,
+    ]) ]);
+    let mut r = p.to_runtime().unwrap();
+    let mut results = [ 0 as usize; 1 ];
+    let obj = r.alloc_object(1).unwrap();
+    r.write_object(obj, 0, 30).unwrap();
+    let weak = r.new_weak_ref(obj).unwrap();
+    assert_eq!(r.execute(0, &[ 0, obj ], &mut results, None, None).ok(), Some(1));
+    assert_eq!(results[0], 30);
+    r.soft_reset();
+    assert_eq!(r.read_object(weak, 0), Ok(obj));
+
+    // once it's unpinned, nothing is holding it.
+    r.unpin(obj);
+    r.soft_reset();
+    assert_eq!(r.read_object(weak, 0), Ok(0));
+
+    // code can't free an object the host is holding.
+    let mut p = Platform::with(&[ Bytes::basic_code(&[ LOAD_LOCAL_0, &[ Opcode::FreeObject as u8 ], RETURN_0 ]) ]);
+    let mut r = p.to_runtime().unwrap();
+    let obj = r.alloc_object(1).unwrap();
+    let rv = r.execute(0, &[ obj ], &mut results, None, None);
+    assert_eq!(rv.err().map(|e| e.code), Some(ErrorCode::InvalidAddress));
+    assert_eq!(r.pin(0x100), Err(ErrorCode::InvalidAddress));
+}
+
+#[test]
+fn dry_run_leaves_globals_alone() {
+    let mut p = Platform::with(&[
+        Bytes::basic_code(&[ NUM_128, STORE_GLOBAL_0, NUM_30, RETURN_1 ]),
+        Bytes::basic_code(&[ LOAD_GLOBAL_0, RETURN_1 ]),
+        Bytes::basic_code(&[ &[ Opcode::NewNN as u8, 4, 0 ], RETURN_1 ]),
+    ]);
+    let (load_global, new_2) = (p.get_constant(1), p.get_constant(2));
+    let mut r = p.to_runtime().unwrap();
+    let mut results = [ 0 as usize; 4 ];
+    assert_eq!(r.dry_run(0, &[], None, None, None).ok(), Some(1));
+    assert_eq!(r.execute(load_global, &[], &mut results, None, None).ok(), Some(1));
+    assert_eq!(results[0], 0);
+
+    // the free list is put back too, so the host's freed object is still there to reuse.
+    let freed = r.alloc_object(2).unwrap();
+    r.free_object(freed).unwrap();
+    assert_eq!(r.dry_run(new_2, &[], None, None, None).ok(), Some(1));
+    assert_eq!(r.alloc_object(2).ok(), Some(freed));
+}
+
+#[test]
+fn dry_run_call_depth() {
+    let mut p = Platform::with(&[
+        // recurse forever:
+        Bytes::code(0, 4, &[ CONST_0, NUM_0, CALL ]),
+    ]);
+    let mut r = p.to_runtime().unwrap();
+    let rv = r.dry_run(0, &[], None, None, Some(2));
+    assert_eq!(format!("{:?}", rv), "Err(CallDepthExceeded(0x04) at [frame code=0 pc=4 sp=0] -> [frame code=0 pc=5 sp=0] -> [frame code=0 pc=5 sp=0])");
+}
+
+#[test]
+fn max_call_depth() {
+    let mut p = Platform::with(&[
+        // recurse forever:
+        Bytes::code(0, 4, &[ CONST_0, NUM_0, CALL ]),
+    ]);
+    let mut r = p.to_runtime_with(RuntimeOptions { max_call_depth: Some(2), ..RuntimeOptions::default() }).unwrap();
+    let mut results = [ 0 as usize; 4 ];
+    let rv = r.execute(0, &[], &mut results, None, None);
+    assert_eq!(format!("{:?}", rv), "Err(CallDepthExceeded(0x04) at [frame code=0 pc=4 sp=0] -> [frame code=0 pc=5 sp=0] -> [frame code=0 pc=5 sp=0])");
+
+    // without a limit, it's just out of memory.
+    let mut r = p.to_runtime().unwrap();
+    let rv = r.execute(0, &[], &mut results, None, None);
+    assert_eq!(rv.err().map(|e| e.code), Some(ErrorCode::OutOfMemory));
+}
+
 // FIXME: error cases
 
 // FIXME: maximum cycle count per code block